@@ -0,0 +1 @@
+DELETE FROM config_value_types WHERE name = 'secret';
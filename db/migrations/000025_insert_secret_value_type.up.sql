@@ -0,0 +1 @@
+INSERT INTO config_value_types (name) VALUES ('secret');
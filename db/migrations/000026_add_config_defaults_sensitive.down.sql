@@ -0,0 +1 @@
+ALTER TABLE config_defaults DROP COLUMN sensitive;
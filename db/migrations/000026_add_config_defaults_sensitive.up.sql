@@ -0,0 +1,10 @@
+-- Marks the settings whose values are masked when they're shown to users.
+ALTER TABLE config_defaults ADD COLUMN sensitive BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE config_defaults SET sensitive = TRUE
+WHERE cfg_key LIKE '%Password%'
+OR cfg_key IN ('ClientSecret', 'VICE.ClientSecret', 'ProjectQARobotSecret', 'Legacy.JwtPrivPass', 'APIToken')
+OR (
+    section_id = (SELECT id FROM config_sections WHERE name = 'Agave')
+    AND cfg_key IN ('Key', 'Secret')
+);
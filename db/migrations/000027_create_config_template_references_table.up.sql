@@ -0,0 +1,13 @@
+-- Records the config settings each template refers to. Filled in by
+-- `mgmt templates scan` and used to work out which services are affected by
+-- a change to a config value.
+CREATE TABLE IF NOT EXISTS config_template_references (
+    id SERIAL PRIMARY KEY,
+    config_template_id INT NOT NULL,
+    section_id INT NOT NULL,
+    cfg_key VARCHAR(512) NOT NULL,
+
+    FOREIGN KEY (config_template_id) REFERENCES config_templates(id) ON DELETE CASCADE,
+    FOREIGN KEY (section_id) REFERENCES config_sections(id) ON DELETE CASCADE,
+    UNIQUE (config_template_id, section_id, cfg_key)
+);
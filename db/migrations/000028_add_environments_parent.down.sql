@@ -0,0 +1,3 @@
+ALTER TABLE environments DROP CONSTRAINT environments_parent_id_fkey;
+
+ALTER TABLE environments DROP COLUMN parent_id;
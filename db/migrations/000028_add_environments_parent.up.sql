@@ -0,0 +1,6 @@
+-- Lets an environment inherit config values from a parent environment.
+-- Values are resolved from the defaults, then the parent, then the child.
+ALTER TABLE environments ADD COLUMN parent_id INT;
+
+ALTER TABLE environments ADD CONSTRAINT environments_parent_id_fkey
+    FOREIGN KEY (parent_id) REFERENCES environments(id) ON DELETE SET NULL;
@@ -0,0 +1,47 @@
+CREATE TABLE IF NOT EXISTS environments_features (
+    id SERIAL PRIMARY KEY,
+    environment_id INT NOT NULL,
+    administration BOOLEAN NOT NULL DEFAULT FALSE,
+    analytics BOOLEAN NOT NULL DEFAULT FALSE,
+    agave BOOLEAN NOT NULL DEFAULT FALSE,
+    base_urls BOOLEAN NOT NULL DEFAULT FALSE,
+    cas BOOLEAN NOT NULL DEFAULT FALSE,
+    docker BOOLEAN NOT NULL DEFAULT FALSE,
+    infosquito BOOLEAN NOT NULL DEFAULT FALSE,
+    intercom BOOLEAN NOT NULL DEFAULT FALSE,
+    jaeger BOOLEAN NOT NULL DEFAULT FALSE,
+    jobs BOOLEAN NOT NULL DEFAULT FALSE,
+    jvmopts BOOLEAN NOT NULL DEFAULT FALSE,
+    permanent_id BOOLEAN NOT NULL DEFAULT FALSE,
+    qa BOOLEAN NOT NULL DEFAULT FALSE,
+    qms BOOLEAN NOT NULL DEFAULT FALSE,
+    unleash BOOLEAN NOT NULL DEFAULT FALSE,
+
+    FOREIGN KEY (environment_id) REFERENCES environments(id),
+    UNIQUE (environment_id)
+);
+
+INSERT INTO environments_features (environment_id, administration, analytics, agave, base_urls, cas, docker, infosquito, intercom, jaeger, jobs, jvmopts, permanent_id, qa, qms, unleash)
+SELECT
+    eff.environment_id,
+    MAX(CASE WHEN ff.name = 'administration' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'analytics' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'agave' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'base_urls' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'cas' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'docker' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'infosquito' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'intercom' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'jaeger' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'jobs' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'jvmopts' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'permanent_id' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'qa' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'qms' AND eff.enabled THEN 1 ELSE 0 END) = 1,
+    MAX(CASE WHEN ff.name = 'unleash' AND eff.enabled THEN 1 ELSE 0 END) = 1
+FROM environments_feature_flags eff
+INNER JOIN feature_flags ff ON ff.id = eff.feature_flag_id
+GROUP BY eff.environment_id;
+
+DROP TABLE IF EXISTS environments_feature_flags;
+DROP TABLE IF EXISTS feature_flags;
@@ -0,0 +1,89 @@
+-- Stores feature flags as rows instead of one column per flag. Each flag in
+-- the feature_flags registry turns an optional config section on or off for
+-- an environment. The SQL sticks to what both Postgres and MySQL (Dolt)
+-- accept.
+CREATE TABLE IF NOT EXISTS feature_flags (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    section_id INT NOT NULL,
+    description VARCHAR(512) NOT NULL DEFAULT '',
+
+    FOREIGN KEY (section_id) REFERENCES config_sections(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS environments_feature_flags (
+    id SERIAL PRIMARY KEY,
+    environment_id INT NOT NULL,
+    feature_flag_id INT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+
+    FOREIGN KEY (environment_id) REFERENCES environments(id) ON DELETE CASCADE,
+    FOREIGN KEY (feature_flag_id) REFERENCES feature_flags(id) ON DELETE CASCADE,
+    UNIQUE (environment_id, feature_flag_id)
+);
+
+INSERT INTO config_sections (name)
+SELECT s.name
+FROM (
+    SELECT 'Admin' AS name
+    UNION ALL SELECT 'Analytics'
+    UNION ALL SELECT 'Agave'
+    UNION ALL SELECT 'BaseURLs'
+    UNION ALL SELECT 'CAS'
+    UNION ALL SELECT 'Docker'
+    UNION ALL SELECT 'Infosquito'
+    UNION ALL SELECT 'Intercom'
+    UNION ALL SELECT 'Jaeger'
+    UNION ALL SELECT 'Jobs'
+    UNION ALL SELECT 'JVMOpts'
+    UNION ALL SELECT 'PermanentID'
+    UNION ALL SELECT 'QA'
+    UNION ALL SELECT 'QMS'
+    UNION ALL SELECT 'Unleash'
+) s
+WHERE NOT EXISTS (SELECT 1 FROM config_sections WHERE config_sections.name = s.name);
+
+INSERT INTO feature_flags (name, section_id, description)
+SELECT f.name, config_sections.id, f.description
+FROM (
+    SELECT 'administration' AS name, 'Admin' AS section, 'The groups and attribute that grant admin access.' AS description
+    UNION ALL SELECT 'analytics', 'Analytics', 'The analytics ID and whether analytics are turned on.'
+    UNION ALL SELECT 'agave', 'Agave', 'The Agave storage system, callbacks, and API credentials.'
+    UNION ALL SELECT 'base_urls', 'BaseURLs', 'The base URLs the services use to reach each other.'
+    UNION ALL SELECT 'cas', 'CAS', 'CAS authentication.'
+    UNION ALL SELECT 'docker', 'Docker', 'The image tag and the trusted registries.'
+    UNION ALL SELECT 'infosquito', 'Infosquito', 'The Infosquito indexing schedule.'
+    UNION ALL SELECT 'intercom', 'Intercom', 'The Intercom app and company settings.'
+    UNION ALL SELECT 'jaeger', 'Jaeger', 'The Jaeger tracing endpoints.'
+    UNION ALL SELECT 'jobs', 'Jobs', 'The data transfer image used by jobs.'
+    UNION ALL SELECT 'jvmopts', 'JVMOpts', 'JVM options for the services that run on the JVM.'
+    UNION ALL SELECT 'permanent_id', 'PermanentID', 'The curators group and DataCite settings for permanent ID requests.'
+    UNION ALL SELECT 'qa', 'QA', 'The accounts used by the QA tests.'
+    UNION ALL SELECT 'qms', 'QMS', 'Whether the quota management service is turned on.'
+    UNION ALL SELECT 'unleash', 'Unleash', 'The Unleash feature toggle service.'
+) f
+INNER JOIN config_sections ON config_sections.name = f.section
+WHERE NOT EXISTS (SELECT 1 FROM feature_flags WHERE feature_flags.name = f.name);
+
+INSERT INTO environments_feature_flags (environment_id, feature_flag_id, enabled)
+SELECT ef.environment_id, feature_flags.id, CASE feature_flags.name
+        WHEN 'administration' THEN ef.administration
+        WHEN 'analytics' THEN ef.analytics
+        WHEN 'agave' THEN ef.agave
+        WHEN 'base_urls' THEN ef.base_urls
+        WHEN 'cas' THEN ef.cas
+        WHEN 'docker' THEN ef.docker
+        WHEN 'infosquito' THEN ef.infosquito
+        WHEN 'intercom' THEN ef.intercom
+        WHEN 'jaeger' THEN ef.jaeger
+        WHEN 'jobs' THEN ef.jobs
+        WHEN 'jvmopts' THEN ef.jvmopts
+        WHEN 'permanent_id' THEN ef.permanent_id
+        WHEN 'qa' THEN ef.qa
+        WHEN 'qms' THEN ef.qms
+        WHEN 'unleash' THEN ef.unleash
+    END
+FROM environments_features ef
+CROSS JOIN feature_flags;
+
+DROP TABLE IF EXISTS environments_features;
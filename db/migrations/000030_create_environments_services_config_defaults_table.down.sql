@@ -0,0 +1 @@
+DROP TABLE IF EXISTS environments_services_config_defaults;
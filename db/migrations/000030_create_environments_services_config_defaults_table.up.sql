@@ -0,0 +1,12 @@
+-- Associates a service in an environment with a default config value, for the
+-- settings the environment doesn't override. Filled in by `mgmt templates
+-- scan` alongside environments_services_config_values.
+CREATE TABLE IF NOT EXISTS environments_services_config_defaults (
+    id SERIAL PRIMARY KEY,
+    environment_service_id INT NOT NULL,
+    config_default_id INT NOT NULL,
+
+    FOREIGN KEY (environment_service_id) REFERENCES environments_services(id) ON DELETE CASCADE,
+    FOREIGN KEY (config_default_id) REFERENCES config_defaults(id) ON DELETE CASCADE,
+    UNIQUE (environment_service_id, config_default_id)
+);
@@ -1,5 +1,6 @@
 use crate::configs;
 use crate::git;
+use crate::settings;
 use anyhow::{anyhow, Context, Result};
 use clap::ArgMatches;
 use std::path::Path;
@@ -18,6 +19,7 @@ pub struct App {
     do_check_in: bool,
     clean: bool,
     defaults_path: String,
+    kube_context: Option<String>,
 }
 
 impl App {
@@ -76,6 +78,7 @@ impl App {
             do_check_in: check_in && !no_check_in,
             clean: clean,
             defaults_path: defaults_path.clone(),
+            kube_context: settings::kube_context(matches),
         })
     }
 
@@ -92,6 +95,7 @@ impl App {
             do_check_in: false,
             clean: false,
             defaults_path: String::from("config_values/defaults.yaml"),
+            kube_context: None,
         }
     }
 
@@ -130,6 +134,7 @@ impl App {
     fn dry_run_cmd(&self, cfg_dir: &str) -> Command {
         let mut command: Command = Command::new("kubectl");
         command
+            .args(settings::kubectl_context_args(self.kube_context.as_deref()))
             .args(["-n", &self.namespace])
             .arg("create")
             .arg("secret")
@@ -144,6 +149,7 @@ impl App {
     fn load_cmd(&self) -> Command {
         let mut command: Command = Command::new("kubectl");
         command
+            .args(settings::kubectl_context_args(self.kube_context.as_deref()))
             .args(["-n", &self.namespace])
             .arg("apply")
             .args(["-f", "-"]);
@@ -177,6 +183,7 @@ impl App {
         let abs_path = Path::new(resource_file_path).canonicalize()?;
         let rfp = abs_path.to_str().context("couldn't get path string")?;
         let cmd = Command::new("kubectl")
+            .args(settings::kubectl_context_args(self.kube_context.as_deref()))
             .args(["-n", &self.namespace])
             .arg("apply")
             .args(["-f", rfp])
@@ -228,6 +235,9 @@ impl App {
             .current_dir(submodule_path)
             .arg("build")
             .args(["--namespace", &self.namespace])
+            .args(settings::skaffold_context_args(
+                self.kube_context.as_deref(),
+            ))
             .args(["--file-output", &build_path]);
         command
     }
@@ -253,6 +263,9 @@ impl App {
             .current_dir(submodule_path)
             .arg("deploy")
             .args(["--namespace", &self.namespace])
+            .args(settings::skaffold_context_args(
+                self.kube_context.as_deref(),
+            ))
             .args(["--build-artifacts", &build_path])
             .arg("--force");
         command
@@ -5,6 +5,12 @@ pub fn cli() -> Command {
     Command::new("configs")
         .about("Manages config values files for the DE")
         .subcommand_required(true)
+        .arg(
+            arg!(--"show-secrets" "Show sensitive values instead of masking them")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .value_parser(clap::value_parser!(bool)),
+        )
         .subcommand(
             Command::new("sections")
                 .subcommand(
@@ -42,9 +48,21 @@ pub fn cli() -> Command {
                                 .required(true)
                                 .value_parser(clap::builder::PossibleValuesParser::new([
                                     "string", "int", "bigint", "float", "bool", "json", "csv",
-                                    "tsv", "yaml", "xml",
+                                    "tsv", "yaml", "xml", "secret",
                                 ]))
                                 .help("The type of the value"),
+                            arg!(--"apply" "Re-render the affected configs and restart the services that use the value")
+                                .required(false)
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
+                            arg!(--"templates" <TEMPLATES> "The directory containing the templates, used with --apply")
+                                .required(false)
+                                .default_value("de-releases")
+                                .value_parser(clap::value_parser!(PathBuf)),
+                            arg!(--"configs" <CONFIGS> "The directory the config files are written to, used with --apply")
+                                .required(false)
+                                .default_value("configs")
+                                .value_parser(clap::value_parser!(PathBuf)),
                         ]),
                 )
                 .subcommand(
@@ -61,6 +79,38 @@ pub fn cli() -> Command {
                                 .value_parser(clap::value_parser!(String)),
                         ]),
                 )
+                .subcommand(
+                    Command::new("impact")
+                        .about("Lists the services and templates in an environment that use a setting")
+                        .args([
+                            arg!(-e --"environment" <ENVIRONMENT>)
+                                .required(true)
+                                .visible_alias("env")
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-s --"section" <SECTION>)
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-k --"key" <KEY>)
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        ]),
+                )
+                .subcommand(
+                    Command::new("explain")
+                        .about("Shows where the value used for a setting in an environment comes from")
+                        .args([
+                            arg!(-e --"environment" <ENVIRONMENT>)
+                                .required(true)
+                                .visible_alias("env")
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-s --"section" <SECTION>)
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-k --"key" <KEY>)
+                                .required(true)
+                                .value_parser(clap::value_parser!(String)),
+                        ]),
+                )
                 .subcommand(
                     Command::new("delete")
                         .args([
@@ -101,67 +151,43 @@ pub fn cli() -> Command {
                                 -e --"environment" <ENVIRONMENT>
                                     "The environment to render the config values for"
                             ),
-                            arg!(--"include-all" "Include all settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-admin" "Include the Admin settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-analytics" "Include the Analytics settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-agave" "Include the Agave settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-base-urls" "Include the BaseURLs settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-cas" "Include the CAS settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-docker" "Include the Docker settings in the rendered output")
+                            arg!(--"provenance" "Add a comment block showing where each value came from")
                                 .required(false)
                                 .action(ArgAction::SetTrue)
                                 .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-infosquito" "Include the InfoSquito settings in the rendered output")
+                            arg!(--"include-all" "Include all of the optional sections in the rendered output")
                                 .required(false)
                                 .action(ArgAction::SetTrue)
                                 .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-intercom" "Include the Intercom settings in the rendered output")
+                            arg!(--"include" [SECTION] "Include an optional section in the rendered output, even if the environment's feature flags leave it out. Can be repeated or comma-separated, e.g. --include Agave,QA")
                                 .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-jaeger" "Include the Jaeger settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-jobs" "Include the Jobs settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-jvmpopts" "Include the JVMOpts settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-permanent-id" "Include the PermanentID settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-qa" "Include the QA settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-qms" "Include the QMS settings in the rendered output")
-                                .required(false)
-                                .action(ArgAction::SetTrue)
-                                .value_parser(clap::value_parser!(bool)),
-                            arg!(--"include-unleash" "Include the Unleash settings in the rendered output")
+                                .action(ArgAction::Append)
+                                .value_delimiter(',')
+                                .value_parser(clap::value_parser!(String)),
+                        ]),
+                )
+                .subcommand(
+                    Command::new("check")
+                        .about("Checks the config values for an environment for problems that would break a deployment")
+                        .args([
+                            arg!(-e --"environment" <ENVIRONMENT> "The environment to check")
+                                .required(true)
+                                .visible_alias("env")
+                                .value_parser(clap::value_parser!(String)),
+                        ]),
+                )
+                .subcommand(
+                    Command::new("apply")
+                        .about("Applies a set of changes from a YAML, JSON, or Section.Key=value file to an environment")
+                        .args([
+                            arg!(-e --"environment" <ENVIRONMENT> "The environment to change")
+                                .required(true)
+                                .visible_alias("env")
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-f --"file" <FILE> "The file containing the changes")
+                                .required(true)
+                                .value_parser(clap::value_parser!(PathBuf)),
+                            arg!(--"dry-run" "Show the changes without applying them")
                                 .required(false)
                                 .action(ArgAction::SetTrue)
                                 .value_parser(clap::value_parser!(bool)),
@@ -176,6 +202,10 @@ pub fn cli() -> Command {
                             arg!(--"environment" <ENVIRONMENT>)
                                 .required(true)
                                 .value_parser(clap::value_parser!(String)),
+                            arg!(--"prune" "Delete values in the environment that aren't in the file")
+                                .required(false)
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
                         ]),
                 ),
         )
@@ -197,9 +227,13 @@ pub fn cli() -> Command {
                                 .required(true)
                                 .value_parser(clap::builder::PossibleValuesParser::new([
                                     "string", "int", "bigint", "float", "bool", "json", "csv",
-                                    "tsv", "yaml", "xml",
+                                    "tsv", "yaml", "xml", "secret",
                                 ]))
                                 .help("The type of the value"),
+                            arg!(--"sensitive" "Mask the value of the setting when it's displayed")
+                                .required(false)
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
                         ]),
                 )
                 .subcommand(
@@ -241,4 +275,23 @@ pub fn cli() -> Command {
                             .value_parser(clap::value_parser!(PathBuf))),
                 ),
         )
+        .subcommand(
+            Command::new("secrets")
+                .about("Manages the key used to encrypt secret config values")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("generate-key")
+                        .about("Generates a new key for encrypting secret config values")
+                        .args([arg!(-f --file <FILE> "The file to write the key to. Defaults to the configured key file")
+                            .required(false)
+                            .value_parser(clap::value_parser!(PathBuf))]),
+                )
+                .subcommand(
+                    Command::new("rotate-key")
+                        .about("Re-encrypts all secret config values with a new key")
+                        .args([arg!(--"new-key-file" <FILE> "The file containing the new key. A new key is generated if it doesn't exist")
+                            .required(true)
+                            .value_parser(clap::value_parser!(PathBuf))]),
+                ),
+        )
 }
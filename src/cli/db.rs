@@ -0,0 +1,75 @@
+use clap::{arg, ArgAction, Command};
+use std::path::PathBuf;
+
+pub fn cli() -> Command {
+    Command::new("db")
+        .about("Manage the mgmt database")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("migrate")
+                .about("Applies the schema migrations built into mgmt. The applied version is tracked in the schema_migrations table, the same way golang-migrate tracks it.")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("up")
+                        .about("Applies the pending migrations.")
+                        .args([arg!(-n --steps [STEPS] "The number of migrations to apply. Applies all of them by default.")
+                            .required(false)
+                            .value_parser(clap::value_parser!(usize))]),
+                )
+                .subcommand(
+                    Command::new("down")
+                        .about("Reverts the most recently applied migrations.")
+                        .args([
+                            arg!(-n --steps [STEPS] "The number of migrations to revert.")
+                                .required(false)
+                                .default_value("1")
+                                .conflicts_with("all")
+                                .value_parser(clap::value_parser!(usize)),
+                            arg!(--all "Revert every migration, dropping the schema.")
+                                .required(false)
+                                .action(ArgAction::SetTrue)
+                                .value_parser(clap::value_parser!(bool)),
+                        ]),
+                )
+                .subcommand(
+                    Command::new("status")
+                        .about("Lists the migrations built into mgmt and whether each has been applied."),
+                )
+                .subcommand(
+                    Command::new("version")
+                        .about("Prints the schema version of the database and the version mgmt expects."),
+                )
+                .subcommand(
+                    Command::new("force")
+                        .about("Records a version as applied without running any migrations and clears the dirty flag. Use it after fixing a failed migration by hand, or to start tracking a database set up some other way.")
+                        .args([arg!(<VERSION> "The version to record.")
+                            .required(true)
+                            .value_parser(clap::value_parser!(i64))]),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Writes the repositories, services, images, templates, defaults, and environments in the database to a YAML file.")
+                .args([arg!(-o --out [FILE] "The file to write. Writes to stdout if it isn't set.")
+                    .required(false)
+                    .value_parser(clap::value_parser!(PathBuf))]),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Loads a file written by `mgmt db export`. Refuses to load into a database that already has environment config values, unless --merge or --replace is set.")
+                .args([
+                    arg!(-i --in <FILE> "The file to load.")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--merge "Add to the contents of the database, updating anything with the same name as something in the file.")
+                        .required(false)
+                        .conflicts_with("replace")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--replace "Replace the contents of the database, even if it already has environment config values.")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                ]),
+        )
+}
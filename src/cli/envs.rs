@@ -1,24 +1,7 @@
-use clap::{arg, ArgAction, Command};
+use clap::{arg, ArgAction, ArgGroup, Command};
+use std::path::PathBuf;
 
 pub fn cli() -> Command {
-    let feature_flags = clap::builder::PossibleValuesParser::new([
-        "admin",
-        "analytics",
-        "agave",
-        "base-urls",
-        "cas",
-        "docker",
-        "infosquito",
-        "intercom",
-        "jaeger",
-        "jobs",
-        "jvmopts",
-        "permanent-id",
-        "qa",
-        "qms",
-        "unleash",
-    ]);
-
     Command::new("env")
         .about("Manage environments for the DE")
         .subcommand_required(true)
@@ -33,6 +16,9 @@ pub fn cli() -> Command {
                 arg!(-f --from <FROM> "The name of the environment to use as the basis for the new environment. Inherits services, config templates but not config values from the original environment.")
                     .required(false)
                     .default_value("de")
+                    .value_parser(clap::value_parser!(String)),
+                arg!(-p --parent [PARENT] "The environment to inherit config values from. Values not set in the new environment come from the parent before falling back to the defaults.")
+                    .required(false)
                     .value_parser(clap::value_parser!(String))
             ]),
         )
@@ -45,6 +31,31 @@ pub fn cli() -> Command {
                 ]),
         )
         .subcommand(Command::new("list").about("Lists the environments in the database."))
+        .subcommand(
+            Command::new("sync")
+                .about("Re-renders the configs that have changed and restarts only the services that use them.")
+                .args([
+                    arg!(-e --env <ENV> "The environment to sync")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-t --templates [TEMPLATES] "The directory containing the templates")
+                        .required(false)
+                        .default_value("de-releases")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-c --configs [CONFIGS] "The directory the config files are written to")
+                        .required(false)
+                        .default_value("configs")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"no-restart" "Do not restart the affected services")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--"dry-run" "Only report what would be changed")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                ]),
+        )
         .subcommand(
             Command::new("populate")
                 .args_conflicts_with_subcommands(true)
@@ -91,18 +102,36 @@ pub fn cli() -> Command {
                 .about("Manages feature flags for an environment.")
                 .subcommand(
                     Command::new("set")
-                        .about("Set a feature flag for an environment.")
+                        .about("Set feature flags for an environment.")
                         .args([
-                            arg!(-e --env <ENV> "The environment to set the feature flag for.")
+                            arg!(-e --env <ENV> "The environment to set the feature flags for.")
                                 .required(true)
                                 .value_parser(clap::value_parser!(String)),
-                            arg!(-f --flag <FLAG> "The name of the feature flag to set.")
-                                .required(true)
-                                .value_parser(feature_flags),
-                            arg!(-v --value <VALUE> "The value to set the feature flag to.")
-                                .required(true)
-                                .value_parser(clap::builder::PossibleValuesParser::new(["true", "false"])),
+                            arg!(-f --flag <FLAG> "The name of the feature flag to set. `mgmt env feature-flags list` shows the known flags.")
+                                .required(false)
+                                .requires("value")
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(-v --value <VALUE> "The value to set the feature flag to: true or false (yes/no, on/off and 1/0 also work).")
+                                .required(false)
+                                .requires("flag")
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(--enable <FLAGS> "Feature flags to turn on. Can be repeated or comma-separated, e.g. --enable qa,jaeger")
+                                .required(false)
+                                .action(ArgAction::Append)
+                                .value_delimiter(',')
+                                .value_parser(clap::value_parser!(String)),
+                            arg!(--disable <FLAGS> "Feature flags to turn off. Can be repeated or comma-separated, e.g. --disable agave")
+                                .required(false)
+                                .action(ArgAction::Append)
+                                .value_delimiter(',')
+                                .value_parser(clap::value_parser!(String)),
                         ])
+                        .group(
+                            ArgGroup::new("flags")
+                                .required(true)
+                                .multiple(true)
+                                .args(["flag", "enable", "disable"]),
+                        )
                 )
                 .subcommand(
                     Command::new("list")
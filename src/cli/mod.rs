@@ -1,5 +1,6 @@
 pub mod configs;
 pub mod container_images;
+pub mod db;
 pub mod deploy;
 pub mod envs;
 pub mod release;
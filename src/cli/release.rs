@@ -98,6 +98,10 @@ pub fn cli() -> Command {
                         .required(false)
                         .action(ArgAction::SetTrue)
                         .value_parser(clap::value_parser!(bool)),
+                    arg!(--"no-validate" "Do not validate the config values before deploying")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
                 ])
         )
 }
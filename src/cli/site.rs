@@ -67,6 +67,9 @@ pub fn cli() -> Command {
                     arg!(--"values-filename" [VALUES_FILENAME] "The file containing the configuration values for the environment")
                         .default_value("deployment.yaml")
                         .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"no-validate" "Do not validate the configuration values before deploying")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
                 ])
         )
 }
@@ -1,6 +1,20 @@
-use clap::{arg, ArgAction, Command};
+use clap::{arg, Arg, ArgAction, Command};
 use std::path::PathBuf;
 
+// The arguments for reading templates from a revision of a repo instead of a
+// local directory.
+fn revision_args() -> [Arg; 2] {
+    [
+        arg!(-r --"repo-id" [REPO_ID] "Read the templates from a revision of this repo instead of a local directory. --templates is then a path in the repo")
+            .required(false)
+            .value_parser(clap::value_parser!(i32)),
+        arg!(--revision [REVISION] "The branch, tag, or commit to read the templates from. Defaults to the repo's revision in the database")
+            .required(false)
+            .requires("repo-id")
+            .value_parser(clap::value_parser!(String)),
+    ]
+}
+
 pub fn cli() -> Command {
     Command::new("templates")
         .about("Template-related tools")
@@ -24,7 +38,7 @@ pub fn cli() -> Command {
         )
         .subcommand(
             Command::new("render-dir")
-                .about("Render a directory of templates")
+                .about("Render a directory of templates. Every file is rendered, unless --tera-only is set")
                 .args([
                     arg!(-t --"templates" [TEMPLATES] "Path to the templates directory")
                         .required(true)
@@ -38,6 +52,10 @@ pub fn cli() -> Command {
                     arg!(-o --output [OUTPUT] "Path to the output directory")
                         .required(true)
                         .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"tera-only" "Only render the files ending in .tera, copying the rest as they are")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
                 ]),
         )
         .subcommand(
@@ -57,7 +75,7 @@ pub fn cli() -> Command {
         )
         .subcommand(
             Command::new("render-dir-db")
-                .about("Render a directory of templates with values from the database.")
+                .about("Render a directory of templates with values from the database. Every file is rendered, unless --tera-only is set.")
                 .args([
                     arg!(-t --"templates" [TEMPLATES] "Path to the templates directory")
                         .required(true)
@@ -68,7 +86,12 @@ pub fn cli() -> Command {
                     arg!(-o --output [OUTPUT] "Path to the output directory")
                         .required(true)
                         .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"tera-only" "Only render the files ending in .tera, copying the rest as they are")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
                 ])
+                .args(revision_args())
         )
         .subcommand(
             Command::new("render-db")
@@ -82,9 +105,62 @@ pub fn cli() -> Command {
                         .required(true)
                         .value_parser(clap::value_parser!(String)),
                     arg!(-o --output [OUTPUT] "Path to the output directory")
+                        .required(false)
+                        .required_unless_present("diff-cluster")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(--"diff-cluster" "Show how the rendered configs and secrets differ from what's loaded in the environment's namespace instead of writing them out")
+                        .required(false)
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                    arg!(--"show-secrets" "Show secret values in the diff instead of masking them")
+                        .required(false)
+                        .requires("diff-cluster")
+                        .action(ArgAction::SetTrue)
+                        .value_parser(clap::value_parser!(bool)),
+                ])
+                .args(revision_args())
+        )
+        .subcommand(
+            Command::new("scan")
+                .about("Scans the templates associated with an environment for the config values they use.")
+                .args([
+                    arg!(-t --"templates" [TEMPLATES] "Path to the templates directory")
+                        .required(false)
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-e --environment [ENVIRONMENT] "The name of the environment to scan")
                         .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                ])
+        )
+        .subcommand(
+            Command::new("lint")
+                .about("Checks templates for syntax errors and for variables that aren't config settings.")
+                .args([
+                    arg!(-t --"templates" [TEMPLATES] "Path to the templates directory")
+                        .required(false)
+                        .default_value(".")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-e --environment [ENVIRONMENT] "The environment whose feature flags decide which optional sections are available. All sections are available if it's left out.")
+                        .required(false)
+                        .value_parser(clap::value_parser!(String)),
+                ])
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Checks that the files rendered into a directory haven't been edited and are up to date, using its manifest.json.")
+                .args([
+                    arg!(-d --dir <DIR> "The directory the templates were rendered into")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-t --"templates" [TEMPLATES] "Path to the templates directory that was rendered from")
+                        .required(false)
+                        .default_value(".")
                         .value_parser(clap::value_parser!(PathBuf)),
                 ])
+                .args(revision_args())
+                .mut_arg("revision", |arg| {
+                    arg.help("The branch, tag, or commit to read the templates from. Defaults to the commit recorded for each file in the manifest, and to the repo's revision in the database for files without one")
+                })
         )
         .subcommand(
             Command::new("assoc")
@@ -102,7 +178,10 @@ pub fn cli() -> Command {
                         .value_parser(clap::value_parser!(String)),
                     arg!(-r --"repo-id" [REPO_ID] "The ID of the repo the template is in")
                         .required(true)
-                        .value_parser(clap::value_parser!(u64)),
+                        .value_parser(clap::value_parser!(i32)),
+                    arg!(-p --"render-path" [RENDER_PATH] "Where the rendered config file goes for the service. Defaults to the template's file name. Only allowed with a single template. Deployments load the template into the service-configs secret under its file name, so the service has to mount it here")
+                        .required(false)
+                        .value_parser(clap::value_parser!(String)),
                 ])
         )
         .subcommand(
@@ -114,4 +193,71 @@ pub fn cli() -> Command {
                         .value_parser(clap::value_parser!(String)),
                 ])
         )
+        .subcommand(
+            Command::new("dissoc")
+                .about("Removes the association between a template and a service in an environment.")
+                .args([
+                    arg!(-t --template [TEMPLATE] "A path to a template")
+                        .required(true)
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-s --service [SERVICE] "The name of a service")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-e --environment [ENVIRONMENT] "The name of an environment")
+                        .required(true)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-r --"repo-id" [REPO_ID] "The ID of the repo the template is in")
+                        .required(true)
+                        .value_parser(clap::value_parser!(i32)),
+                ])
+        )
+        .subcommand(
+            Command::new("rm")
+                .about("Removes a template that isn't associated with any services from the database.")
+                .args([
+                    arg!(-t --template [TEMPLATE] "A path to a template")
+                        .required(true)
+                        .action(ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-f --force "Remove the template even if services still use it, along with those associations")
+                        .action(ArgAction::SetTrue),
+                    arg!(-r --"repo-id" [REPO_ID] "The ID of the repo the template is in")
+                        .required(true)
+                        .value_parser(clap::value_parser!(i32)),
+                ])
+        )
+        .subcommand(
+            Command::new("mv")
+                .about("Changes the path of a template, keeping the services it's associated with.")
+                .args([
+                    arg!(<FROM> "The current path of the template")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(<TO> "The new path of the template")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-t --"templates" [TEMPLATES] "Path to the templates directory. If given, the new path has to exist in it")
+                        .required(false)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                    arg!(-r --"repo-id" [REPO_ID] "The ID of the repo the template is in")
+                        .required(true)
+                        .value_parser(clap::value_parser!(i32)),
+                ])
+        )
+        .subcommand(
+            Command::new("copy")
+                .about("Copies the template associations of services in one environment to the same services in another.")
+                .args([
+                    arg!(--"from-env" <FROM_ENV> "The environment to copy the associations from")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(--"to-env" <TO_ENV> "The environment to copy the associations to")
+                        .value_parser(clap::value_parser!(String)),
+                    arg!(-s --service [SERVICE] "Only copy the associations of this service")
+                        .required(false)
+                        .value_parser(clap::value_parser!(String)),
+                ])
+        )
+        .subcommand(
+            Command::new("orphans")
+                .about("Lists the templates that aren't associated with any service in any environment.")
+        )
 }
@@ -1,6 +1,9 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input, Select};
-use sqlx::{Postgres, Transaction};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password, Select};
 
 use serde::{Deserialize, Serialize};
 
@@ -153,7 +156,7 @@ impl LoadFromDatabase for Agave {
 impl Agave {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
         base_url: &url::Url,
@@ -178,14 +181,14 @@ impl Agave {
         add_env_cfg_value(tx, env_id, rdu_id).await?;
         self.redirect_uri = redirect_uri;
 
-        let agave_key = Input::<String>::with_theme(theme)
+        let agave_key = Password::with_theme(theme)
             .with_prompt("Agave Key")
             .interact()?;
         let key_id = set_config_value(tx, "Agave", "Key", &agave_key, "string").await?;
         add_env_cfg_value(tx, env_id, key_id).await?;
         self.key = agave_key;
 
-        let secret = Input::<String>::with_theme(theme)
+        let secret = Password::with_theme(theme)
             .with_prompt("Agave Secret")
             .interact()?;
         let secret_id = set_config_value(tx, "Agave", "Secret", &secret, "string").await?;
@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -120,7 +123,7 @@ impl Amqp {
 
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
         prefix: &str,
@@ -129,7 +132,7 @@ impl Amqp {
             .with_prompt(format!("{} AMQP User", prefix))
             .interact()?;
 
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt(format!("{} AMQP Password", prefix))
             .interact()?;
 
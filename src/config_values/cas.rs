@@ -1,8 +1,10 @@
-use crate::db::{add_env_cfg_value, set_config_value, ConfigurationValue, LoadFromDatabase};
+use crate::db::{
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    ConfigurationValue, LoadFromDatabase,
+};
 use anyhow::{Context, Result};
-use dialoguer::{theme::ColorfulTheme, Input};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -95,7 +97,7 @@ impl From<CAS> for Vec<ConfigurationValue> {
 impl CAS {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> Result<()> {
@@ -117,7 +119,7 @@ impl CAS {
         add_env_cfg_value(tx, env_id, client_id_id).await?;
         self.client_id = client_id;
 
-        let client_secret = Input::<String>::with_theme(theme)
+        let client_secret = Password::with_theme(theme)
             .with_prompt("CAS Client Secret")
             .interact()
             .context("Failed to get Client Secret")?;
@@ -1,77 +1,70 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::config_values::{
     self, agave::Agave, base_urls::BaseURLs, dashboard_aggregator::DashboardAggregator,
     db::DatabaseConfig, db::QMSDatabaseConfig, de::DE, docker::Docker,
     elasticsearch::Elasticsearch, email::Email, grouper::Grouper, icat::Icat,
-    infosquito::Infosquito,
+    infosquito::Infosquito, validation, validation::ValidationIssue,
+};
+use crate::db::{
+    self,
+    backend::{
+        add_env_cfg_value, set_config_value, set_section_flags, upsert_environment, Connection,
+    },
+    LoadFromDatabase,
 };
-use crate::db::{self, add_env_cfg_value, set_config_value, upsert_environment, LoadFromDatabase};
 use anyhow::Context;
 use dialoguer::{console::Style, theme::ColorfulTheme, Input, Select};
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Postgres, Transaction};
-
-#[derive(Debug, Default, Clone, Copy)]
+use sqlx::{Pool, Postgres};
+
+/// The optional sections that `ConfigValues` has fields for. They're left out
+/// unless they're turned on, usually by a feature flag tied to the section.
+pub const OPTIONAL_SECTIONS: [&str; 15] = [
+    "Admin",
+    "Analytics",
+    "Agave",
+    "BaseURLs",
+    "CAS",
+    "Docker",
+    "Infosquito",
+    "Intercom",
+    "Jaeger",
+    "Jobs",
+    "JVMOpts",
+    "PermanentID",
+    "QA",
+    "QMS",
+    "Unleash",
+];
+
+/// Whether each optional config section is included, keyed by section name.
+/// Sections that aren't optional are always included.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SectionOptions {
-    include_admin: bool,
-    include_analytics: bool,
-    include_agave: bool,
-    include_base_urls: bool,
-    include_cas: bool,
-    include_docker: bool,
-    include_infosquito: bool,
-    include_intercom: bool,
-    include_jaeger: bool,
-    include_jobs: bool,
-    include_jvmopts: bool,
-    include_permanent_id: bool,
-    include_qa: bool,
-    include_qms: bool,
-    include_unleash: bool,
+    sections: BTreeMap<String, bool>,
 }
 
-impl From<db::FeatureFlags> for SectionOptions {
-    fn from(ff: db::FeatureFlags) -> Self {
+impl Default for SectionOptions {
+    fn default() -> Self {
         Self {
-            include_admin: ff.administration,
-            include_analytics: ff.analytics,
-            include_agave: ff.agave,
-            include_base_urls: ff.base_urls,
-            include_cas: ff.cas,
-            include_docker: ff.docker,
-            include_infosquito: ff.infosquito,
-            include_intercom: ff.intercom,
-            include_jaeger: ff.jaeger,
-            include_jobs: ff.jobs,
-            include_jvmopts: ff.jvmopts,
-            include_permanent_id: ff.permanent_id,
-            include_qa: ff.qa,
-            include_qms: ff.qms,
-            include_unleash: ff.unleash,
+            sections: OPTIONAL_SECTIONS
+                .iter()
+                .map(|section| (section.to_string(), false))
+                .collect(),
         }
     }
 }
 
-impl From<SectionOptions> for db::FeatureFlags {
-    fn from(so: SectionOptions) -> Self {
-        Self {
-            administration: so.include_admin,
-            analytics: so.include_analytics,
-            agave: so.include_agave,
-            base_urls: so.include_base_urls,
-            cas: so.include_cas,
-            docker: so.include_docker,
-            infosquito: so.include_infosquito,
-            intercom: so.include_intercom,
-            jaeger: so.include_jaeger,
-            jobs: so.include_jobs,
-            jvmopts: so.include_jvmopts,
-            permanent_id: so.include_permanent_id,
-            qa: so.include_qa,
-            qms: so.include_qms,
-            unleash: so.include_unleash,
+/// Every section tied to a feature flag is optional, and is included if any of
+/// the flags tied to it are turned on.
+impl From<Vec<db::EnvFeatureFlag>> for SectionOptions {
+    fn from(flags: Vec<db::EnvFeatureFlag>) -> Self {
+        let mut opts = Self::default();
+        for flag in flags {
+            *opts.sections.entry(flag.section).or_insert(false) |= flag.enabled;
         }
+        opts
     }
 }
 
@@ -84,165 +77,52 @@ impl SectionOptions {
         Ok(ff.into())
     }
 
-    pub fn new(sub_m: &clap::ArgMatches) -> Self {
-        let include_all = if sub_m.contains_id("include-all") {
-            sub_m.get_flag("include-all")
-        } else {
-            false
-        };
-
-        if include_all {
-            Self {
-                include_admin: true,
-                include_analytics: true,
-                include_agave: true,
-                include_base_urls: true,
-                include_cas: true,
-                include_docker: true,
-                include_infosquito: true,
-                include_intercom: true,
-                include_jaeger: true,
-                include_jobs: true,
-                include_jvmopts: true,
-                include_permanent_id: true,
-                include_qa: true,
-                include_qms: true,
-                include_unleash: true,
-            }
-        } else {
-            Self {
-                include_admin: if sub_m.contains_id("include-admin") {
-                    sub_m.get_flag("include-admin")
-                } else {
-                    false
-                },
-
-                include_analytics: if sub_m.contains_id("include-analytics") {
-                    sub_m.get_flag("include-analytics")
-                } else {
-                    false
-                },
-
-                include_agave: if sub_m.contains_id("include-agave") {
-                    sub_m.get_flag("include-agave")
-                } else {
-                    false
-                },
-
-                include_base_urls: if sub_m.contains_id("include-base-urls") {
-                    sub_m.get_flag("include-base-urls")
-                } else {
-                    false
-                },
-
-                include_cas: if sub_m.contains_id("include-cas") {
-                    sub_m.get_flag("include-cas")
-                } else {
-                    false
-                },
-
-                include_docker: if sub_m.contains_id("include-docker") {
-                    sub_m.get_flag("include-docker")
-                } else {
-                    false
-                },
-
-                include_infosquito: if sub_m.contains_id("include-infosquito") {
-                    sub_m.get_flag("include-infosquito")
-                } else {
-                    false
-                },
-
-                include_intercom: if sub_m.contains_id("include-intercom") {
-                    sub_m.get_flag("include-intercom")
-                } else {
-                    false
-                },
-
-                include_jaeger: if sub_m.contains_id("include-jaeger") {
-                    sub_m.get_flag("include-jaeger")
-                } else {
-                    false
-                },
-
-                include_jobs: if sub_m.contains_id("include-jobs") {
-                    sub_m.get_flag("include-jobs")
-                } else {
-                    false
-                },
-
-                include_jvmopts: if sub_m.contains_id("include-jvmopts") {
-                    sub_m.get_flag("include-jvmopts")
-                } else {
-                    false
-                },
-
-                include_permanent_id: if sub_m.contains_id("include-permanent-id") {
-                    sub_m.get_flag("include-permanent-id")
-                } else {
-                    false
-                },
-
-                include_qa: if sub_m.contains_id("include-qa") {
-                    sub_m.get_flag("include-qa")
-                } else {
-                    false
-                },
-
-                include_qms: if sub_m.contains_id("include-qms") {
-                    sub_m.get_flag("include-qms")
-                } else {
-                    false
-                },
-
-                include_unleash: if sub_m.contains_id("include-unleash") {
-                    sub_m.get_flag("include-unleash")
-                } else {
-                    false
-                },
+    /// Turns on the sections passed with `--include <SECTION>`, or all of them
+    /// if `--include-all` is set.
+    pub fn include_from_args(&mut self, sub_m: &clap::ArgMatches) -> anyhow::Result<()> {
+        if sub_m.contains_id("include-all") && sub_m.get_flag("include-all") {
+            return self.set_all(true);
+        }
+
+        if sub_m.contains_id("include") {
+            for section in sub_m.get_many::<String>("include").unwrap_or_default() {
+                self.set_section(section, true);
             }
         }
+
+        Ok(())
     }
 
     pub fn set_all(&mut self, all: bool) -> anyhow::Result<()> {
-        self.include_admin = all;
-        self.include_analytics = all;
-        self.include_agave = all;
-        self.include_base_urls = all;
-        self.include_cas = all;
-        self.include_docker = all;
-        self.include_infosquito = all;
-        self.include_intercom = all;
-        self.include_jaeger = all;
-        self.include_jobs = all;
-        self.include_jvmopts = all;
-        self.include_permanent_id = all;
-        self.include_qa = all;
-        self.include_qms = all;
-        self.include_unleash = all;
+        self.sections
+            .values_mut()
+            .for_each(|include| *include = all);
 
         Ok(())
     }
 
+    /// Turns an optional section on or off. A section that wasn't optional
+    /// becomes optional.
+    pub fn set_section(&mut self, section: &str, include: bool) {
+        self.sections.insert(section.to_string(), include);
+    }
+
     pub fn include_section(&self, section: &str) -> bool {
-        match section {
-            "Admin" => self.include_admin,
-            "Analytics" => self.include_analytics,
-            "Agave" => self.include_agave,
-            "BaseURLs" => self.include_base_urls,
-            "CAS" => self.include_cas,
-            "Docker" => self.include_docker,
-            "InfoSquito" => self.include_infosquito,
-            "Intercom" => self.include_intercom,
-            "Jaeger" => self.include_jaeger,
-            "Jobs" => self.include_jobs,
-            "JVMOpts" => self.include_jvmopts,
-            "PermanentID" => self.include_permanent_id,
-            "QA" => self.include_qa,
-            "QMS" => self.include_qms,
-            "Unleash" => self.include_unleash,
-            _ => true,
-        }
+        self.sections.get(section).copied().unwrap_or(true)
+    }
+
+    /// Returns the optional sections, mapped to whether they're included.
+    pub fn sections(&self) -> &BTreeMap<String, bool> {
+        &self.sections
+    }
+
+    /// Returns the names of the optional sections that are turned on.
+    pub fn enabled_sections(&self) -> Vec<String> {
+        self.sections
+            .iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(section, _)| section.clone())
+            .collect()
     }
 }
 
@@ -884,6 +764,19 @@ impl ConfigValues {
         self.section_options = section_options;
     }
 
+    pub fn section_options(&self) -> SectionOptions {
+        self.section_options.clone()
+    }
+
+    /// Checks the values for problems that would break a deployment, such as
+    /// missing required settings, malformed URLs, bad ports, and optional
+    /// sections that are enabled but empty. Returns an empty list if the
+    /// values are usable.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let cfgs: Vec<db::ConfigurationValue> = self.clone().into();
+        validation::validate_values(&cfgs, &self.section_options)
+    }
+
     pub fn reset_sections(&mut self) -> anyhow::Result<()> {
         if !self.section_options.include_section("Agave") {
             self.agave = None;
@@ -954,63 +847,63 @@ impl ConfigValues {
     pub fn generate_section_options(&self) -> SectionOptions {
         let mut opts = SectionOptions::default();
         if let Some(_agave) = &self.agave {
-            opts.include_agave = true;
+            opts.set_section("Agave", true);
         }
 
         if let Some(_base_urls) = &self.base_urls {
-            opts.include_base_urls = true;
+            opts.set_section("BaseURLs", true);
         }
 
         if let Some(_docker) = &self.docker {
-            opts.include_docker = true;
+            opts.set_section("Docker", true);
         }
 
         if let Some(_infosquito) = &self.infosquito {
-            opts.include_infosquito = true;
+            opts.set_section("Infosquito", true);
         }
 
         if let Some(_intercom) = &self.intercom {
-            opts.include_intercom = true;
+            opts.set_section("Intercom", true);
         }
 
         if let Some(_jobs) = &self.jobs {
-            opts.include_jobs = true;
+            opts.set_section("Jobs", true);
         }
 
         if let Some(_jvmopts) = &self.jvmopts {
-            opts.include_jvmopts = true;
+            opts.set_section("JVMOpts", true);
         }
 
         if let Some(_permanent_id) = &self.permanent_id {
-            opts.include_permanent_id = true;
+            opts.set_section("PermanentID", true);
         }
 
         if let Some(_unleash) = &self.unleash {
-            opts.include_unleash = true;
+            opts.set_section("Unleash", true);
         }
 
         if let Some(_admin) = &self.admin {
-            opts.include_admin = true;
+            opts.set_section("Admin", true);
         }
 
         if let Some(_analytics) = &self.analytics {
-            opts.include_analytics = true;
+            opts.set_section("Analytics", true);
         }
 
         if let Some(_qms) = &self.qms {
-            opts.include_qms = true;
+            opts.set_section("QMS", true);
         }
 
         if let Some(_jaeger) = &self.jaeger {
-            opts.include_jaeger = true;
+            opts.set_section("Jaeger", true);
         }
 
         if let Some(_qa) = &self.qa {
-            opts.include_qa = true;
+            opts.set_section("QA", true);
         }
 
         if let Some(_cas) = &self.cas {
-            opts.include_cas = true;
+            opts.set_section("CAS", true);
         }
 
         opts
@@ -1053,7 +946,7 @@ impl ConfigValues {
         Ok(new_cv)
     }
 
-    pub async fn ask_for_info(&mut self, tx: &mut Transaction<'_, Postgres>) -> anyhow::Result<()> {
+    pub async fn ask_for_info(&mut self, tx: &mut Connection<'_>) -> anyhow::Result<()> {
         let mut theme = ColorfulTheme::default();
         theme.hint_style = Style::new().yellow();
         let mut section_options = SectionOptions::default();
@@ -1097,10 +990,10 @@ impl ConfigValues {
         add_env_cfg_value(tx, env_id, timezone_id).await?;
 
         // We don't prompt for this yet.
-        section_options.include_base_urls = true;
+        section_options.set_section("BaseURLs", true);
 
         // We also don't prompt for this yet.
-        section_options.include_cas = false;
+        section_options.set_section("CAS", false);
 
         // Fill in the DE and iRODS settings first, since they have some
         // values that can be used as defaults later.
@@ -1129,7 +1022,7 @@ impl ConfigValues {
                 .ask_for_info(tx, &theme, env_id, &base_uri, &irods_external)
                 .await?;
             self.agave = Some(new_agave);
-            section_options.include_agave = true;
+            section_options.set_section("Agave", true);
         }
 
         let mut new_da = DashboardAggregator::default();
@@ -1146,7 +1039,7 @@ impl ConfigValues {
             let mut new_docker = Docker::default();
             new_docker.ask_for_info(tx, &theme, env_id).await?;
             self.docker = Some(new_docker);
-            section_options.include_docker = true;
+            section_options.set_section("Docker", true);
         }
 
         self.elasticsearch.ask_for_info(tx, &theme, env_id).await?;
@@ -1166,7 +1059,7 @@ impl ConfigValues {
             let mut new_infosquito = Infosquito::default();
             new_infosquito.ask_for_info(tx, &theme, env_id).await?;
             self.infosquito = Some(new_infosquito);
-            section_options.include_infosquito = true;
+            section_options.set_section("Infosquito", true);
         }
 
         let intercom_enabled = Select::with_theme(&theme)
@@ -1179,7 +1072,7 @@ impl ConfigValues {
             let mut new_intercom = config_values::intercom::Intercom::default();
             new_intercom.ask_for_info(tx, &theme, env_id).await?;
             self.intercom = Some(new_intercom);
-            section_options.include_intercom = true;
+            section_options.set_section("Intercom", true);
         }
 
         let jobs_enabled = Select::with_theme(&theme)
@@ -1192,7 +1085,7 @@ impl ConfigValues {
             let mut new_jobs = config_values::misc::Jobs::default();
             new_jobs.ask_for_info(tx, &theme, env_id).await?;
             self.jobs = Some(new_jobs);
-            section_options.include_jobs = true;
+            section_options.set_section("Jobs", true);
         }
 
         let jvmopts_enabled = Select::with_theme(&theme)
@@ -1205,7 +1098,7 @@ impl ConfigValues {
             let mut new_jvmopts = config_values::jvmopts::JVMOpts::default();
             new_jvmopts.ask_for_info(tx, &theme, env_id).await?;
             self.jvmopts = Some(new_jvmopts);
-            section_options.include_jvmopts = true;
+            section_options.set_section("JVMOpts", true);
         }
 
         self.keycloak.ask_for_info(tx, &theme, env_id).await?;
@@ -1221,7 +1114,7 @@ impl ConfigValues {
             let mut new_permanent_id = config_values::misc::PermanentId::default();
             new_permanent_id.ask_for_info(tx, &theme, env_id).await?;
             self.permanent_id = Some(new_permanent_id);
-            section_options.include_permanent_id = true;
+            section_options.set_section("PermanentID", true);
         }
 
         self.de_db
@@ -1305,7 +1198,7 @@ impl ConfigValues {
                 )
                 .await?;
             self.unleash_db = Some(new_unleash_db);
-            section_options.include_unleash = true;
+            section_options.set_section("Unleash", true);
         }
 
         let qms_enabled = Select::with_theme(&theme)
@@ -1329,7 +1222,7 @@ impl ConfigValues {
             let mut new_qms = config_values::misc::Qms::default();
             new_qms.ask_for_info(tx, &theme, env_id).await?;
             self.qms = Some(new_qms);
-            section_options.include_qms = true;
+            section_options.set_section("QMS", true);
         }
 
         self.user_portal.ask_for_info(tx, &theme, env_id).await?;
@@ -1344,7 +1237,7 @@ impl ConfigValues {
             let mut new_admin = config_values::misc::Admin::default();
             new_admin.ask_for_info(tx, &theme, env_id).await?;
             self.admin = Some(new_admin);
-            section_options.include_admin = true;
+            section_options.set_section("Admin", true);
         }
 
         let analytics_enabled = Select::with_theme(&theme)
@@ -1357,7 +1250,7 @@ impl ConfigValues {
             let mut new_analytics = config_values::misc::Analytics::default();
             new_analytics.ask_for_info(tx, &theme, env_id).await?;
             self.analytics = Some(new_analytics);
-            section_options.include_analytics = true;
+            section_options.set_section("Analytics", true);
         }
 
         let mut new_harbor = config_values::misc::Harbor::default();
@@ -1374,7 +1267,7 @@ impl ConfigValues {
             let mut new_jaeger = config_values::misc::Jaeger::default();
             new_jaeger.ask_for_info(tx, &theme, env_id).await?;
             self.jaeger = Some(new_jaeger);
-            section_options.include_jaeger = true;
+            section_options.set_section("Jaeger", true);
         }
 
         let qa_enabled = Select::with_theme(&theme)
@@ -1387,7 +1280,7 @@ impl ConfigValues {
             let mut new_qa = config_values::qa::QA::default();
             new_qa.ask_for_info(tx, &theme, env_id).await?;
             self.qa = Some(new_qa);
-            section_options.include_qa = true;
+            section_options.set_section("QA", true);
         }
 
         let cas_enabled = Select::with_theme(&theme)
@@ -1400,11 +1293,11 @@ impl ConfigValues {
             let mut new_cas = config_values::cas::CAS::default();
             new_cas.ask_for_info(tx, &theme, env_id).await?;
             self.cas = Some(new_cas);
-            //section_options.include_cas = true;
+            //section_options.set_section("CAS", true);
         }
 
+        set_section_flags(tx, &self.environment, section_options.sections()).await?;
         self.section_options = section_options;
-        db::upsert_feature_flags(tx, &self.environment, &section_options.into()).await?;
 
         Ok(())
     }
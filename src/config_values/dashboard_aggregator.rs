@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
 use dialoguer::{theme::ColorfulTheme, Input};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -64,7 +67,7 @@ impl From<Website> for Vec<db::ConfigurationValue> {
 impl Website {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -133,7 +136,7 @@ impl From<DashboardAggregator> for Vec<db::ConfigurationValue> {
 impl DashboardAggregator {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input, Select};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password, Select};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -120,7 +123,7 @@ impl DatabaseConfig {
 
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
         prefix: &str,
@@ -135,10 +138,15 @@ impl DatabaseConfig {
             .default(user.to_string())
             .interact()?;
 
-        let password = Input::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt(format!("{} Database Password", prefix))
-            .default(pass.to_string())
+            .allow_empty_password(true)
             .interact()?;
+        let password = if password.is_empty() {
+            pass.to_string()
+        } else {
+            password
+        };
 
         let host = Input::<String>::with_theme(theme)
             .with_prompt(format!("{} Database Host", prefix))
@@ -307,7 +315,7 @@ impl From<QMSDatabaseConfig> for Vec<db::ConfigurationValue> {
 impl QMSDatabaseConfig {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
         name: &str,
@@ -320,10 +328,15 @@ impl QMSDatabaseConfig {
             .default(user.to_string())
             .interact()?;
 
-        let password = Input::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("QMS Database Password")
-            .default(pass.to_string())
+            .allow_empty_password(true)
             .interact()?;
+        let password = if password.is_empty() {
+            pass.to_string()
+        } else {
+            password
+        };
 
         let host = Input::<String>::with_theme(theme)
             .with_prompt("QMS Database Host")
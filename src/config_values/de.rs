@@ -1,8 +1,11 @@
 use crate::config_values::amqp::Amqp;
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
 use dialoguer::{theme::ColorfulTheme, Input};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
@@ -72,7 +75,7 @@ impl From<DESubscriptions> for Vec<db::ConfigurationValue> {
 impl DESubscriptions {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -127,7 +130,7 @@ pub struct DECoge {
 impl DECoge {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -272,7 +275,7 @@ impl From<DETools> for Vec<db::ConfigurationValue> {
 impl DETools {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -421,7 +424,7 @@ impl From<Info> for Vec<db::ConfigurationValue> {
 impl Info {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -605,7 +608,7 @@ impl From<DE> for Vec<db::ConfigurationValue> {
 impl DE {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
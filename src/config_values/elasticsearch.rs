@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -115,7 +118,7 @@ impl From<Elasticsearch> for Vec<db::ConfigurationValue> {
 impl Elasticsearch {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -129,9 +132,9 @@ impl Elasticsearch {
             .allow_empty(true)
             .interact()?;
 
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("ElasticSearch Password")
-            .allow_empty(true)
+            .allow_empty_password(true)
             .interact()?;
 
         let index = Input::<String>::with_theme(theme)
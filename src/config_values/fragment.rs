@@ -0,0 +1,278 @@
+//! # Fragments
+//!
+//! Parses partial config values documents, used to change a handful of
+//! settings at once. A fragment is either a YAML or JSON document in the same
+//! nested shape that `mgmt configs values render` produces, or a list of flat
+//! `Section.Key=value` lines like a dotenv file.
+use anyhow::{anyhow, Result};
+use serde_yaml::Value;
+use std::collections::HashSet;
+
+/// A single setting from a fragment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FragmentValue {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+}
+
+impl FragmentValue {
+    fn new(section: &str, key: &str, value: &str) -> Self {
+        FragmentValue {
+            section: section.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+// Converts a YAML value into the string that gets stored in the database.
+// Lists are stored as comma separated values.
+fn scalar_string(value: &Value, path: &str) -> Result<String> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(s.clone()),
+        Value::Sequence(items) => Ok(items
+            .iter()
+            .map(|item| scalar_string(item, path))
+            .collect::<Result<Vec<String>>>()?
+            .join(",")),
+        _ => Err(anyhow!("unsupported value for {}", path)),
+    }
+}
+
+// Flattens a nested mapping into dot-separated keys.
+fn flatten(section: &str, prefix: &str, value: &Value, out: &mut Vec<FragmentValue>) -> Result<()> {
+    match value {
+        Value::Mapping(map) => {
+            for (k, v) in map {
+                let k = k
+                    .as_str()
+                    .ok_or_else(|| anyhow!("non-string key in section {}", section))?;
+                let key = if prefix.is_empty() {
+                    k.to_string()
+                } else {
+                    format!("{}.{}", prefix, k)
+                };
+                flatten(section, &key, v, out)?;
+            }
+        }
+        _ => {
+            let path = format!("{}.{}", section, prefix);
+            out.push(FragmentValue::new(
+                section,
+                prefix,
+                &scalar_string(value, &path)?,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Parses a nested document. Top-level settings that aren't in a section go
+// into the TopLevel section.
+fn parse_nested(doc: &serde_yaml::Mapping) -> Result<Vec<FragmentValue>> {
+    let mut values = Vec::new();
+
+    for (k, v) in doc {
+        let name = k
+            .as_str()
+            .ok_or_else(|| anyhow!("non-string key at the top level"))?;
+        match v {
+            Value::Mapping(_) => flatten(name, "", v, &mut values)?,
+            _ => values.push(FragmentValue::new(
+                "TopLevel",
+                name,
+                &scalar_string(v, name)?,
+            )),
+        }
+    }
+
+    Ok(values)
+}
+
+// Returns whether a fragment is in the flat form: every line that isn't blank
+// or a comment starts with a setting name made of letters, digits,
+// underscores, and dots, followed by an equals sign. This is checked before
+// trying YAML, since a flat value containing ": " also parses as a mapping.
+fn is_flat(contents: &str) -> bool {
+    let mut lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .peekable();
+
+    lines.peek().is_some()
+        && lines.all(|line| {
+            line.split_once('=').is_some_and(|(path, _)| {
+                let path = path.trim_end();
+                !path.is_empty()
+                    && path
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            })
+        })
+}
+
+// Parses flat Section.Key=value lines. Blank lines and comments are skipped,
+// and matching quotes around the value are removed.
+fn parse_flat(contents: &str) -> Result<Vec<FragmentValue>> {
+    let mut values = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (path, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected Section.Key=value", index + 1))?;
+        let path = path.trim();
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+            .unwrap_or(value);
+
+        let (section, key) = path.split_once('.').unwrap_or(("TopLevel", path));
+        if section.is_empty() || key.is_empty() {
+            return Err(anyhow!("line {}: expected Section.Key=value", index + 1));
+        }
+
+        values.push(FragmentValue::new(section, key, value));
+    }
+
+    Ok(values)
+}
+
+/// Parses a fragment into the list of settings it contains. Documents made up
+/// of `Section.Key=value` lines are read as flat lines, documents that parse
+/// as a YAML or JSON mapping are treated as nested, and anything else is read
+/// as flat lines so that the errors point at the bad line. Settings may only
+/// appear once.
+///
+/// # Examples
+/// ```ignore
+/// let values = parse_fragment("DEDB:\n  Host: db.example.org\n")?;
+/// let values = parse_fragment("DEDB.Host=db.example.org\n")?;
+/// ```
+pub fn parse_fragment(contents: &str) -> Result<Vec<FragmentValue>> {
+    let values = if is_flat(contents) {
+        parse_flat(contents)?
+    } else {
+        match serde_yaml::from_str::<Value>(contents) {
+            Ok(Value::Mapping(doc)) => parse_nested(&doc)?,
+            _ => parse_flat(contents)?,
+        }
+    };
+
+    let mut seen = HashSet::new();
+    for value in &values {
+        if !seen.insert((value.section.as_str(), value.key.as_str())) {
+            return Err(anyhow!(
+                "{}.{} is set more than once",
+                value.section,
+                value.key
+            ));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Checks that a value can be parsed as the given value type.
+pub fn check_type(value: &str, value_type: &str) -> Result<()> {
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    let ok = match value_type {
+        "int" => value.parse::<i32>().is_ok(),
+        "bigint" => value.parse::<i64>().is_ok(),
+        "float" => value.parse::<f64>().is_ok(),
+        "bool" => value.parse::<bool>().is_ok(),
+        "json" => serde_json::from_str::<serde_json::Value>(value).is_ok(),
+        "yaml" => serde_yaml::from_str::<Value>(value).is_ok(),
+        _ => true,
+    };
+
+    if ok {
+        Ok(())
+    } else {
+        Err(anyhow!("'{}' is not a valid {}", value, value_type))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_nested() {
+        let values = parse_fragment(
+            "Environment: prod\nDEDB:\n  Host: db.example.org\n  Port: 5432\nDE:\n  AMQP:\n    URI: amqp://x\nDocker:\n  TrustedRegistries: [a, b]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            values,
+            vec![
+                FragmentValue::new("TopLevel", "Environment", "prod"),
+                FragmentValue::new("DEDB", "Host", "db.example.org"),
+                FragmentValue::new("DEDB", "Port", "5432"),
+                FragmentValue::new("DE", "AMQP.URI", "amqp://x"),
+                FragmentValue::new("Docker", "TrustedRegistries", "a,b"),
+            ]
+        );
+
+        let values = parse_fragment(r#"{"DEDB": {"Name": "de"}}"#).unwrap();
+        assert_eq!(values, vec![FragmentValue::new("DEDB", "Name", "de")]);
+    }
+
+    #[test]
+    fn test_parse_flat() {
+        let values = parse_fragment(
+            "# changes\nDEDB.Host=db.example.org\n\nDE.AMQP.URI = \"amqp://x?a=b\"\nNamespace=prod\n",
+        )
+        .unwrap();
+        assert_eq!(
+            values,
+            vec![
+                FragmentValue::new("DEDB", "Host", "db.example.org"),
+                FragmentValue::new("DE", "AMQP.URI", "amqp://x?a=b"),
+                FragmentValue::new("TopLevel", "Namespace", "prod"),
+            ]
+        );
+
+        // Values that would also parse as YAML mappings.
+        let values = parse_fragment("Email.Subject=Note: hi\nEmail.Src=a: b\n").unwrap();
+        assert_eq!(
+            values,
+            vec![
+                FragmentValue::new("Email", "Subject", "Note: hi"),
+                FragmentValue::new("Email", "Src", "a: b"),
+            ]
+        );
+        let values = parse_fragment("Email.Subject=Note: hi").unwrap();
+        assert_eq!(
+            values,
+            vec![FragmentValue::new("Email", "Subject", "Note: hi")]
+        );
+
+        assert!(parse_fragment("DEDB.Host=a\nDEDB.Host=b\n").is_err());
+        assert!(parse_fragment("DEDB.Host\n").is_err());
+    }
+
+    #[test]
+    fn test_check_type() {
+        assert!(check_type("5432", "int").is_ok());
+        assert!(check_type("", "int").is_ok());
+        assert!(check_type("five", "int").is_err());
+        assert!(check_type("true", "bool").is_ok());
+        assert!(check_type("yes", "bool").is_err());
+        assert!(check_type("anything", "string").is_ok());
+    }
+}
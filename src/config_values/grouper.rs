@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -92,7 +95,7 @@ impl From<GrouperLoader> for Vec<db::ConfigurationValue> {
 impl GrouperLoader {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -104,7 +107,7 @@ impl GrouperLoader {
             .with_prompt("Grouper Loader User")
             .interact()?;
 
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("Grouper Loader Password")
             .interact()?;
 
@@ -216,7 +219,7 @@ impl From<Grouper> for Vec<db::ConfigurationValue> {
 impl Grouper {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
         env: &str,
@@ -225,7 +228,7 @@ impl Grouper {
             .with_prompt("Grouper Morph String")
             .interact()?;
 
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("Grouper Password")
             .interact()?;
 
@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -102,7 +105,7 @@ impl From<Icat> for Vec<db::ConfigurationValue> {
 impl Icat {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -119,7 +122,7 @@ impl Icat {
             .with_prompt("ICAT User")
             .interact()?;
 
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("ICAT Password")
             .interact()?;
 
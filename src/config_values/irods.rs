@@ -1,8 +1,11 @@
 use crate::config_values::amqp::Amqp;
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -69,7 +72,7 @@ impl From<IrodsWebDav> for Vec<db::ConfigurationValue> {
 impl IrodsWebDav {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
         external: &str,
@@ -274,7 +277,7 @@ impl Default for Irods {
 impl Irods {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -298,7 +301,7 @@ impl Irods {
             .with_prompt("iRODS Zone")
             .interact()?;
 
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("iRODS Password")
             .interact()?;
 
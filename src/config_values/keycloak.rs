@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -79,7 +82,7 @@ impl From<KeycloakVice> for Vec<db::ConfigurationValue> {
 impl KeycloakVice {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -88,7 +91,7 @@ impl KeycloakVice {
             .default("de-vice".into())
             .interact()?;
 
-        let client_secret = Input::<String>::with_theme(theme)
+        let client_secret = Password::with_theme(theme)
             .with_prompt("Keycloak VICE Client Secret")
             .interact()?;
 
@@ -223,7 +226,7 @@ impl From<Keycloak> for Vec<db::ConfigurationValue> {
 impl Keycloak {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -241,7 +244,7 @@ impl Keycloak {
             .default("de".into())
             .interact()?;
 
-        let client_secret = Input::<String>::with_theme(theme)
+        let client_secret = Password::with_theme(theme)
             .with_prompt("Keycloak Client Secret")
             .interact()?;
 
@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input, Select};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password, Select};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -80,7 +83,7 @@ impl From<Jobs> for Vec<db::ConfigurationValue> {
 impl Jobs {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -135,11 +138,11 @@ impl Default for Pgp {
 impl Pgp {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
-        let key_password = Input::<String>::with_theme(theme)
+        let key_password = Password::with_theme(theme)
             .with_prompt("PGP Key Password")
             .interact()?;
         let key_password_id =
@@ -220,7 +223,7 @@ impl Default for PermanentIdDataCite {
 impl PermanentIdDataCite {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -233,7 +236,7 @@ impl PermanentIdDataCite {
             .with_prompt("Permanent ID DataCite User")
             .interact()?;
 
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("Permanent ID DataCite Password")
             .interact()?;
 
@@ -363,7 +366,7 @@ impl Default for PermanentId {
 impl PermanentId {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -542,7 +545,7 @@ impl From<Unleash> for Vec<db::ConfigurationValue> {
 impl Unleash {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -561,7 +564,7 @@ impl Unleash {
             .default("DE-Maintenance".into())
             .interact()?;
 
-        let api_token = Input::<String>::with_theme(theme)
+        let api_token = Password::with_theme(theme)
             .with_prompt("Unleash API Token")
             .interact()?;
 
@@ -615,7 +618,7 @@ impl Default for UserPortal {
 impl UserPortal {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -751,7 +754,7 @@ impl From<Admin> for Vec<db::ConfigurationValue> {
 impl Admin {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -854,7 +857,7 @@ impl From<Analytics> for Vec<db::ConfigurationValue> {
 impl Analytics {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -998,7 +1001,7 @@ impl From<Harbor> for Vec<db::ConfigurationValue> {
 impl Harbor {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -1011,7 +1014,7 @@ impl Harbor {
             .with_prompt("Harbor Project QA Robot Name")
             .interact()?;
 
-        let project_qa_robot_secret = Input::<String>::with_theme(theme)
+        let project_qa_robot_secret = Password::with_theme(theme)
             .with_prompt("Harbor Project QA Robot Secret")
             .interact()?;
 
@@ -1109,7 +1112,7 @@ impl From<Qms> for Vec<db::ConfigurationValue> {
 impl Qms {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -1204,7 +1207,7 @@ impl From<Jaeger> for Vec<db::ConfigurationValue> {
 impl Jaeger {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -9,6 +9,7 @@ pub mod de;
 pub mod docker;
 pub mod elasticsearch;
 pub mod email;
+pub mod fragment;
 pub mod grouper;
 pub mod icat;
 pub mod infosquito;
@@ -17,7 +18,9 @@ pub mod irods;
 pub mod jvmopts;
 pub mod keycloak;
 pub mod misc;
+pub mod provenance;
 pub mod qa;
+pub mod validation;
 pub mod vice;
 
 // These are features that are truly optional. In other words, they do not need
@@ -0,0 +1,288 @@
+//! # Provenance
+//!
+//! Works out where each configuration value for an environment comes from.
+//! The rules are the same ones `ConfigValues::merge_with` uses when templates
+//! are rendered: an environment value overrides the default, unless the
+//! environment value is empty, in which case the default is used instead.
+//! Environments with a parent are resolved in layers, from the defaults to the
+//! furthest ancestor and down to the environment itself.
+use crate::db::ConfigurationValue;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Where a merged configuration value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    /// Only a default value exists.
+    Default,
+    /// An environment's value replaces the default.
+    Override,
+    /// The environments only have empty values, so the default is used.
+    EmptyOverrideIgnored,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Source::Default => "default",
+            Source::Override => "override",
+            Source::EmptyOverrideIgnored => "empty-override-ignored",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A value set in one of the environments an environment inherits from,
+/// including the environment itself.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Layer {
+    pub environment: String,
+    pub value: ConfigurationValue,
+}
+
+/// The provenance of a single merged configuration value.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Provenance {
+    pub source: Source,
+    /// The value that ends up being used.
+    pub value: ConfigurationValue,
+    /// The row in config_defaults, if there is one.
+    pub default: Option<ConfigurationValue>,
+    /// The rows in config_values for each environment that sets the value,
+    /// from the furthest ancestor to the environment itself.
+    pub layers: Vec<Layer>,
+}
+
+impl Provenance {
+    /// Returns the Section.Key name of the value.
+    pub fn name(&self) -> String {
+        format!("{}.{}", self.value.section, self.value.key)
+    }
+
+    /// Returns the layer whose value is used, if the default isn't.
+    pub fn winner(&self) -> Option<&Layer> {
+        if self.source != Source::Override {
+            return None;
+        }
+
+        self.layers
+            .iter()
+            .rev()
+            .find(|layer| layer.value.id == self.value.id)
+    }
+
+    /// Describes the rows involved, starting with the environment itself,
+    /// e.g. `config_values id 12 from prod (used), config_defaults id 3
+    /// (overridden)`.
+    pub fn rows(&self) -> String {
+        let winner = self.winner().map(|layer| layer.value.id);
+
+        let mut parts: Vec<String> = self
+            .layers
+            .iter()
+            .rev()
+            .map(|layer| {
+                let status = if Some(layer.value.id) == winner {
+                    "used"
+                } else if layer.value.value.is_empty() {
+                    "empty, ignored"
+                } else {
+                    "overridden"
+                };
+                format!(
+                    "config_values id {} from {} ({})",
+                    layer.value.id, layer.environment, status
+                )
+            })
+            .collect();
+
+        match &self.default {
+            Some(default) => parts.push(format!(
+                "config_defaults id {} ({})",
+                default.id,
+                if winner.is_some() {
+                    "overridden"
+                } else {
+                    "used"
+                }
+            )),
+            None => parts.push("no default".to_string()),
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// Traces every value in the merge of the defaults and the values from each
+/// environment layer, given from the furthest ancestor to the environment
+/// itself as (environment name, values) pairs. The results are sorted by
+/// section and key.
+///
+/// # Example
+/// ```ignore
+/// let layers = vec![("prod".to_string(), env_values)];
+/// for p in trace(&defaults, &layers) {
+///     println!("{}: {} ({})", p.name(), p.source, p.rows());
+/// }
+/// ```
+pub fn trace(
+    defaults: &[ConfigurationValue],
+    layers: &[(String, Vec<ConfigurationValue>)],
+) -> Vec<Provenance> {
+    let mut merged: BTreeMap<(String, String), Provenance> = BTreeMap::new();
+
+    for default in defaults {
+        merged.insert(
+            (default.section.clone(), default.key.clone()),
+            Provenance {
+                source: Source::Default,
+                value: default.clone(),
+                default: Some(default.clone()),
+                layers: Vec::new(),
+            },
+        );
+    }
+
+    for (environment, env_values) in layers {
+        for env_value in env_values {
+            let layer = Layer {
+                environment: environment.clone(),
+                value: env_value.clone(),
+            };
+
+            let id = (env_value.section.clone(), env_value.key.clone());
+            match merged.get_mut(&id) {
+                Some(p) => {
+                    p.layers.push(layer);
+                    if !env_value.value.is_empty() {
+                        p.source = Source::Override;
+                        p.value = env_value.clone();
+                    } else if p.source == Source::Default {
+                        p.source = Source::EmptyOverrideIgnored;
+                    }
+                }
+                None => {
+                    merged.insert(
+                        id,
+                        Provenance {
+                            source: Source::Override,
+                            value: env_value.clone(),
+                            default: None,
+                            layers: vec![layer],
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    merged.into_values().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cfg(id: i32, key: &str, value: &str) -> ConfigurationValue {
+        ConfigurationValue {
+            id,
+            section: "Keycloak".to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: "string".to_string(),
+        }
+    }
+
+    fn summarize(traced: &[Provenance]) -> Vec<(String, Source, String, String)> {
+        traced
+            .iter()
+            .map(|p| (p.name(), p.source, p.value.value.clone(), p.rows()))
+            .collect()
+    }
+
+    #[test]
+    fn test_trace() {
+        let defaults = vec![
+            cfg(1, "ServerURI", "https://default"),
+            cfg(2, "Realm", "CyVerse"),
+            cfg(3, "ClientID", "de"),
+        ];
+        let env_values = vec![
+            cfg(10, "ServerURI", "https://prod"),
+            cfg(11, "Realm", ""),
+            cfg(12, "Extra", "x"),
+        ];
+
+        let traced = trace(&defaults, &[("prod".to_string(), env_values)]);
+
+        assert_eq!(
+            summarize(&traced),
+            vec![
+                (
+                    "Keycloak.ClientID".to_string(),
+                    Source::Default,
+                    "de".to_string(),
+                    "config_defaults id 3 (used)".to_string()
+                ),
+                (
+                    "Keycloak.Extra".to_string(),
+                    Source::Override,
+                    "x".to_string(),
+                    "config_values id 12 from prod (used), no default".to_string()
+                ),
+                (
+                    "Keycloak.Realm".to_string(),
+                    Source::EmptyOverrideIgnored,
+                    "CyVerse".to_string(),
+                    "config_values id 11 from prod (empty, ignored), config_defaults id 2 (used)"
+                        .to_string()
+                ),
+                (
+                    "Keycloak.ServerURI".to_string(),
+                    Source::Override,
+                    "https://prod".to_string(),
+                    "config_values id 10 from prod (used), config_defaults id 1 (overridden)"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_layers() {
+        let defaults = vec![
+            cfg(1, "ServerURI", "https://default"),
+            cfg(2, "Realm", "CyVerse"),
+        ];
+        let parent = vec![
+            cfg(10, "ServerURI", "https://staging"),
+            cfg(11, "Realm", "Staging"),
+        ];
+        let child = vec![cfg(20, "ServerURI", "https://prod"), cfg(21, "Realm", "")];
+
+        let traced = trace(
+            &defaults,
+            &[("staging".to_string(), parent), ("prod".to_string(), child)],
+        );
+
+        assert_eq!(
+            summarize(&traced),
+            vec![
+                (
+                    "Keycloak.Realm".to_string(),
+                    Source::Override,
+                    "Staging".to_string(),
+                    "config_values id 21 from prod (empty, ignored), config_values id 11 from staging (used), config_defaults id 2 (overridden)".to_string()
+                ),
+                (
+                    "Keycloak.ServerURI".to_string(),
+                    Source::Override,
+                    "https://prod".to_string(),
+                    "config_values id 20 from prod (used), config_values id 10 from staging (overridden), config_defaults id 1 (overridden)".to_string()
+                ),
+            ]
+        );
+        assert_eq!(traced[0].winner().unwrap().environment, "staging");
+    }
+}
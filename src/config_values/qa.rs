@@ -1,7 +1,10 @@
-use crate::db::{self, add_env_cfg_value, set_config_value, LoadFromDatabase};
-use dialoguer::{theme::ColorfulTheme, Input};
+use crate::db::{
+    self,
+    backend::{add_env_cfg_value, set_config_value, Connection},
+    LoadFromDatabase,
+};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -101,14 +104,14 @@ impl From<QACeph> for Vec<db::ConfigurationValue> {
 impl QACeph {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("Ceph Password")
-            .allow_empty(true)
-            .interact_text()?;
+            .allow_empty_password(true)
+            .interact()?;
 
         let username = Input::<String>::with_theme(theme)
             .with_prompt("Ceph Username")
@@ -284,14 +287,14 @@ impl From<QADE> for Vec<db::ConfigurationValue> {
 impl QADE {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("DE Password")
-            .allow_empty(true)
-            .interact_text()?;
+            .allow_empty_password(true)
+            .interact()?;
 
         let username = Input::<String>::with_theme(theme)
             .with_prompt("DE Username")
@@ -318,10 +321,10 @@ impl QADE {
             .allow_empty(true)
             .interact_text()?;
 
-        let admin_password = Input::<String>::with_theme(theme)
+        let admin_password = Password::with_theme(theme)
             .with_prompt("DE Admin Password")
-            .allow_empty(true)
-            .interact_text()?;
+            .allow_empty_password(true)
+            .interact()?;
 
         let password_id = set_config_value(tx, "QA", "DE.Password", &password, "string").await?;
         add_env_cfg_value(tx, env_id, password_id).await?;
@@ -522,14 +525,14 @@ impl From<QALegacy> for Vec<db::ConfigurationValue> {
 impl QALegacy {
     async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
-        let password = Input::<String>::with_theme(theme)
+        let password = Password::with_theme(theme)
             .with_prompt("Legacy Password")
-            .allow_empty(true)
-            .interact_text()?;
+            .allow_empty_password(true)
+            .interact()?;
 
         let username = Input::<String>::with_theme(theme)
             .with_prompt("Legacy Username")
@@ -556,20 +559,20 @@ impl QALegacy {
             .allow_empty(true)
             .interact_text()?;
 
-        let admin_password = Input::<String>::with_theme(theme)
+        let admin_password = Password::with_theme(theme)
             .with_prompt("Legacy Admin Password")
-            .allow_empty(true)
-            .interact_text()?;
+            .allow_empty_password(true)
+            .interact()?;
 
-        let admin_password2 = Input::<String>::with_theme(theme)
+        let admin_password2 = Password::with_theme(theme)
             .with_prompt("Legacy Admin Password 2")
-            .allow_empty(true)
-            .interact_text()?;
+            .allow_empty_password(true)
+            .interact()?;
 
-        let jwt_priv_pass = Input::<String>::with_theme(theme)
+        let jwt_priv_pass = Password::with_theme(theme)
             .with_prompt("Legacy JWT Priv Pass")
-            .allow_empty(true)
-            .interact_text()?;
+            .allow_empty_password(true)
+            .interact()?;
 
         let password_id =
             set_config_value(tx, "QA", "Legacy.Password", &password, "string").await?;
@@ -690,7 +693,7 @@ impl From<QA> for Vec<db::ConfigurationValue> {
 impl QA {
     pub async fn ask_for_info(
         &mut self,
-        tx: &mut Transaction<'_, Postgres>,
+        tx: &mut Connection<'_>,
         theme: &ColorfulTheme,
         env_id: i32,
     ) -> anyhow::Result<()> {
@@ -0,0 +1,361 @@
+//! # Validation
+//!
+//! Checks a set of configuration values for problems that would break a
+//! deployment: required settings that were never filled in, URLs that don't
+//! parse, ports that are out of range, and optional sections that are turned
+//! on by a feature flag but have nothing in them.
+//!
+//! Secret values have to be decrypted before they're validated. A secret that's
+//! still encrypted is reported, since its format can't be checked, and the
+//! values of secrets are masked in the report.
+use crate::config_values::config::SectionOptions;
+use crate::db::ConfigurationValue;
+use crate::secrets;
+use std::collections::HashMap;
+use std::fmt;
+use url::Url;
+
+/// The settings that must have a non-empty value before an environment can be
+/// deployed, listed as (section, key) pairs.
+const REQUIRED: &[(&str, &str)] = &[
+    ("TopLevel", "Environment"),
+    ("TopLevel", "Namespace"),
+    ("TopLevel", "UIDDomain"),
+    ("DE", "BaseURI"),
+    ("Elasticsearch", "BaseURI"),
+    ("Email", "Src"),
+    ("Email", "Dest"),
+    ("Grouper", "MorphString"),
+    ("Grouper", "Password"),
+    ("Grouper", "FolderNamePrefix"),
+    ("Grouper", "Loader.URI"),
+    ("ICAT", "Host"),
+    ("ICAT", "User"),
+    ("ICAT", "Password"),
+    ("IRODS", "Host"),
+    ("IRODS", "User"),
+    ("IRODS", "Zone"),
+    ("IRODS", "Password"),
+    ("IRODS", "ExternalHost"),
+    ("Keycloak", "ServerURI"),
+    ("Keycloak", "Realm"),
+    ("Keycloak", "ClientID"),
+    ("Keycloak", "ClientSecret"),
+    ("PGP", "KeyPassword"),
+    ("UserPortal", "BaseURI"),
+    ("VICE", "BaseURI"),
+];
+
+/// The database sections that must be configured for a deployment.
+const REQUIRED_DATABASES: &[&str] = &[
+    "DEDB",
+    "GrouperDB",
+    "NotificationsDB",
+    "PermissionsDB",
+    "MetadataDB",
+    "QMSDB",
+];
+
+/// The settings every database section needs.
+const REQUIRED_DATABASE_KEYS: &[&str] = &["User", "Password", "Host", "Name"];
+
+/// Settings whose names look like URLs but hold a bare host name.
+const NOT_URLS: &[(&str, &str)] = &[("Harbor", "URL")];
+
+/// The kinds of problems that validation can find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    Missing,
+    MalformedUrl,
+    BadPort,
+    EmptySection,
+    Encrypted,
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IssueKind::Missing => "missing",
+            IssueKind::MalformedUrl => "malformed URL",
+            IssueKind::BadPort => "bad port",
+            IssueKind::EmptySection => "empty section",
+            IssueKind::Encrypted => "encrypted",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A single problem found in a set of configuration values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub section: String,
+    pub key: Option<String>,
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(
+                f,
+                "{}.{}: {} ({})",
+                self.section, key, self.message, self.kind
+            ),
+            None => write!(f, "{}: {} ({})", self.section, self.message, self.kind),
+        }
+    }
+}
+
+impl ValidationIssue {
+    fn new(section: &str, key: Option<&str>, kind: IssueKind, message: String) -> Self {
+        ValidationIssue {
+            section: section.to_string(),
+            key: key.map(|k| k.to_string()),
+            kind,
+            message,
+        }
+    }
+}
+
+// Returns whether the key holds a URL, based on the naming conventions used
+// in the config sections.
+fn is_url_key(section: &str, key: &str) -> bool {
+    if NOT_URLS.contains(&(section, key)) {
+        return false;
+    }
+
+    let last = key.rsplit('.').next().unwrap_or(key).to_lowercase();
+    section == "BaseURLs" || last.ends_with("uri") || last.ends_with("url")
+}
+
+// Returns whether the key holds a port number.
+fn is_port_key(key: &str) -> bool {
+    key.rsplit('.').next().unwrap_or(key) == "Port"
+}
+
+/// Validates a flattened list of configuration values. The section options
+/// determine which optional sections are expected to be filled in.
+///
+/// # Examples
+/// ```ignore
+/// let issues = validate_values(&cfgs, &opts);
+/// for issue in issues {
+///     println!("{}", issue);
+/// }
+/// ```
+pub fn validate_values(
+    cfgs: &[ConfigurationValue],
+    opts: &SectionOptions,
+) -> Vec<ValidationIssue> {
+    let mut issues: Vec<ValidationIssue> = Vec::new();
+
+    let lookup: HashMap<(&str, &str), &str> = cfgs
+        .iter()
+        .map(|cfg| {
+            (
+                (cfg.section.as_str(), cfg.key.as_str()),
+                cfg.value.as_str(),
+            )
+        })
+        .collect();
+
+    let mut required: Vec<(&str, &str)> = REQUIRED.to_vec();
+    let mut databases: Vec<&str> = REQUIRED_DATABASES.to_vec();
+    if opts.include_section("Unleash") {
+        databases.push("UnleashDB");
+    }
+    for database in databases {
+        for key in REQUIRED_DATABASE_KEYS {
+            required.push((database, key));
+        }
+    }
+
+    for (section, key) in required {
+        let is_empty = lookup
+            .get(&(section, key))
+            .is_none_or(|value| value.trim().is_empty());
+        if is_empty {
+            issues.push(ValidationIssue::new(
+                section,
+                Some(key),
+                IssueKind::Missing,
+                "a value is required for deployment".to_string(),
+            ));
+        }
+    }
+
+    for cfg in cfgs {
+        if cfg.value.is_empty() {
+            continue;
+        }
+
+        let is_url = is_url_key(&cfg.section, &cfg.key);
+        let is_port = is_port_key(&cfg.key);
+
+        // An encrypted value parses as a URL, so it would always pass.
+        if (is_url || is_port) && secrets::is_encrypted(&cfg.value) {
+            issues.push(ValidationIssue::new(
+                &cfg.section,
+                Some(&cfg.key),
+                IssueKind::Encrypted,
+                "the value is encrypted, so its format can't be checked".to_string(),
+            ));
+            continue;
+        }
+
+        let shown = match secrets::is_secret(cfg) {
+            true => secrets::MASK,
+            false => cfg.value.as_str(),
+        };
+
+        if is_url {
+            if let Err(e) = Url::parse(&cfg.value) {
+                issues.push(ValidationIssue::new(
+                    &cfg.section,
+                    Some(&cfg.key),
+                    IssueKind::MalformedUrl,
+                    format!("'{}' is not a valid URL: {}", shown, e),
+                ));
+            }
+        }
+
+        if is_port {
+            match cfg.value.parse::<u16>() {
+                Ok(port) if port > 0 => (),
+                _ => issues.push(ValidationIssue::new(
+                    &cfg.section,
+                    Some(&cfg.key),
+                    IssueKind::BadPort,
+                    format!("'{}' is not a port between 1 and 65535", shown),
+                )),
+            }
+        }
+    }
+
+    for section in opts.enabled_sections() {
+        let has_values = cfgs
+            .iter()
+            .any(|cfg| cfg.section == section && !cfg.value.trim().is_empty());
+        if !has_values {
+            issues.push(ValidationIssue::new(
+                &section,
+                None,
+                IssueKind::EmptySection,
+                "the section is enabled by a feature flag but has no values".to_string(),
+            ));
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cfg(section: &str, key: &str, value: &str) -> ConfigurationValue {
+        ConfigurationValue {
+            id: 0,
+            section: section.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: "string".to_string(),
+        }
+    }
+
+    fn complete() -> Vec<ConfigurationValue> {
+        let mut cfgs: Vec<ConfigurationValue> = REQUIRED
+            .iter()
+            .map(|(section, key)| {
+                let value = if is_url_key(section, key) {
+                    "https://example.org/"
+                } else {
+                    "value"
+                };
+                cfg(section, key, value)
+            })
+            .collect();
+
+        for database in REQUIRED_DATABASES {
+            for key in REQUIRED_DATABASE_KEYS {
+                cfgs.push(cfg(database, key, "value"));
+            }
+        }
+
+        cfgs
+    }
+
+    #[test]
+    fn test_complete_values_pass() {
+        let issues = validate_values(&complete(), &SectionOptions::default());
+        assert!(issues.is_empty(), "unexpected issues: {:?}", issues);
+    }
+
+    #[test]
+    fn test_missing_value() {
+        let mut cfgs = complete();
+        cfgs.retain(|c| !(c.section == "Keycloak" && c.key == "Realm"));
+        cfgs.iter_mut()
+            .filter(|c| c.section == "Email" && c.key == "Src")
+            .for_each(|c| c.value = String::new());
+
+        let issues = validate_values(&cfgs, &SectionOptions::default());
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().all(|i| i.kind == IssueKind::Missing));
+    }
+
+    #[test]
+    fn test_malformed_url_and_bad_port() {
+        let mut cfgs = complete();
+        cfgs.push(cfg("BaseURLs", "Apps", "not a url"));
+        cfgs.push(cfg("DEDB", "Port", "70000"));
+        cfgs.push(cfg("ICAT", "Port", "5432"));
+
+        let issues = validate_values(&cfgs, &SectionOptions::default());
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].kind, IssueKind::MalformedUrl);
+        assert_eq!(issues[1].kind, IssueKind::BadPort);
+    }
+
+    #[test]
+    fn test_secrets() {
+        let secret = |section: &str, key: &str, value: &str| ConfigurationValue {
+            value_type: secrets::SECRET_TYPE.to_string(),
+            ..cfg(section, key, value)
+        };
+
+        let mut cfgs = complete();
+        cfgs.push(secret("BaseURLs", "Apps", "not a url"));
+        cfgs.push(secret("BaseURLs", "Analyses", "age:c2VjcmV0"));
+
+        let issues = validate_values(&cfgs, &SectionOptions::default());
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].kind, IssueKind::MalformedUrl);
+        assert!(!issues[0].message.contains("not a url"));
+        assert!(issues[0].message.contains(secrets::MASK));
+        assert_eq!(issues[1].kind, IssueKind::Encrypted);
+    }
+
+    #[test]
+    fn test_enabled_section_without_values() {
+        let mut opts = SectionOptions::default();
+        opts.set_all(true).unwrap();
+
+        let mut cfgs = complete();
+        for key in REQUIRED_DATABASE_KEYS {
+            cfgs.push(cfg("UnleashDB", key, "value"));
+        }
+        for section in opts.enabled_sections() {
+            if section != "Jaeger" {
+                cfgs.push(cfg(&section, "Key", "https://example.org/"));
+            }
+        }
+        cfgs.push(cfg("Jaeger", "Endpoint", ""));
+
+        let issues = validate_values(&cfgs, &opts);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].section, "Jaeger");
+        assert_eq!(issues[0].kind, IssueKind::EmptySection);
+    }
+}
@@ -2,8 +2,13 @@
 //!
 //! Contains the functions needed for loading configs and secrets in mgmt.
 
-use anyhow::{Context, Result};
+use crate::{manifest::MANIFEST_FILE, settings};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose, Engine as _};
 use duct::cmd;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -172,50 +177,171 @@ pub fn generate_all(defaults_path: &str) -> Result<bool> {
     Ok(success)
 }
 
+// Returns the files directly inside a directory that were rendered or copied
+// there, leaving out subdirectories and the render manifest.
+fn rendered_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.metadata()?.is_file() && entry.file_name() != MANIFEST_FILE {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
 /// Load the configuration values at a given path for a provided namespace
-/// and environment.
-pub fn load_configs(ns: &str, configmap_name: &str, cfg_dir: &PathBuf) -> Result<bool> {
-    Ok(cmd!(
-        "kubectl",
+/// and environment. The render manifest isn't loaded.
+pub fn load_configs(
+    ns: &str,
+    configmap_name: &str,
+    cfg_dir: &PathBuf,
+    kube_context: Option<&str>,
+) -> Result<bool> {
+    let mut create_args: Vec<String> = [
         "-n",
         ns,
         "create",
         "secret",
         "generic",
         configmap_name,
-        format!(
-            "--from-file={}",
-            cfg_dir
-                .to_str()
-                .context("failed to get the absolute path to the configs dir")?
-        ),
         "--dry-run",
         "-o",
-        "yaml"
+        "yaml",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    for file in rendered_files(cfg_dir)? {
+        create_args.push(format!(
+            "--from-file={}",
+            file.to_str()
+                .context("failed to get the absolute path to the configs dir")?
+        ));
+    }
+    let create_args: Vec<&str> = create_args.iter().map(|arg| arg.as_str()).collect();
+
+    Ok(kubectl(kube_context, &create_args)
+        .pipe(kubectl(kube_context, &["-n", ns, "apply", "-f", "-"]))
+        .run()?
+        .status
+        .success())
+}
+
+// Builds a kubectl command that uses a Kubernetes context, if one is given.
+fn kubectl(kube_context: Option<&str>, args: &[&str]) -> duct::Expression {
+    let mut all_args = settings::kubectl_context_args(kube_context);
+    all_args.extend(args.iter().map(|arg| arg.to_string()));
+    duct::cmd("kubectl", all_args)
+}
+
+// Decodes the entries of a secret. Entries in `data` are base64 encoded and
+// entries in `stringData` aren't. Contents that aren't UTF-8 are converted
+// lossily, since they're only compared and shown.
+fn secret_data(secret: &Value) -> Result<BTreeMap<String, String>> {
+    let mut entries = BTreeMap::new();
+
+    if let Some(data) = secret.get("data").and_then(|d| d.as_object()) {
+        for (key, value) in data {
+            let encoded = value.as_str().unwrap_or_default();
+            let decoded = general_purpose::STANDARD
+                .decode(encoded)
+                .with_context(|| format!("failed to decode the {} entry", key))?;
+            entries.insert(key.clone(), String::from_utf8_lossy(&decoded).into_owned());
+        }
+    }
+
+    if let Some(data) = secret.get("stringData").and_then(|d| d.as_object()) {
+        for (key, value) in data {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            entries.insert(key.clone(), value);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Returns the decoded entries of a secret in a namespace, or None if the
+/// secret doesn't exist.
+///
+/// # Examples
+/// ```ignore
+/// let current = mgmt::configs::get_secret("qa", "service-configs", None)?.unwrap_or_default();
+/// ```
+pub fn get_secret(
+    ns: &str,
+    name: &str,
+    kube_context: Option<&str>,
+) -> Result<Option<BTreeMap<String, String>>> {
+    let json = kubectl(
+        kube_context,
+        &[
+            "-n",
+            ns,
+            "get",
+            "secret",
+            name,
+            "--ignore-not-found",
+            "-o",
+            "json",
+        ],
     )
-    .pipe(cmd!("kubectl", "-n", ns, "apply", "-f", "-"))
-    .run()?
-    .status
-    .success())
+    .read()
+    .with_context(|| format!("failed to get the {} secret from {}", name, ns))?;
+
+    if json.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let secret: Value = serde_json::from_str(&json)?;
+    Ok(Some(secret_data(&secret)?))
 }
 
-pub fn load_secrets(ns: &str, secrets_dir: &PathBuf) -> Result<bool> {
-    let contents = fs::read_dir(secrets_dir)?;
-    for entry in contents.into_iter() {
-        let entry = entry?;
-        if entry.metadata()?.is_file() {
-            let path = entry.path();
-            cmd!(
-                "kubectl",
+/// Returns the secrets defined in a manifest as (name, entries) pairs. The
+/// manifest can hold more than one document; anything that isn't a secret is
+/// skipped.
+///
+/// # Examples
+/// ```ignore
+/// let secrets = mgmt::configs::manifest_secrets(&fs::read_to_string("secrets/irods.yaml")?)?;
+/// ```
+pub fn manifest_secrets(manifest: &str) -> Result<Vec<(String, BTreeMap<String, String>)>> {
+    let mut secrets = Vec::new();
+
+    for document in serde_yaml::Deserializer::from_str(manifest) {
+        let object = Value::deserialize(document)?;
+        if object.get("kind").and_then(|k| k.as_str()) != Some("Secret") {
+            continue;
+        }
+
+        let name = object
+            .pointer("/metadata/name")
+            .and_then(|n| n.as_str())
+            .ok_or_else(|| anyhow!("found a secret without a name"))?;
+        secrets.push((name.to_string(), secret_data(&object)?));
+    }
+
+    Ok(secrets)
+}
+
+pub fn load_secrets(ns: &str, secrets_dir: &Path, kube_context: Option<&str>) -> Result<bool> {
+    for path in rendered_files(secrets_dir)? {
+        kubectl(
+            kube_context,
+            &[
                 "-n",
                 ns,
                 "apply",
                 "-f",
                 path.to_str()
-                    .context("failed to get the absolute path to the secret file")?
-            )
-            .run()?;
-        }
+                    .context("failed to get the absolute path to the secret file")?,
+            ],
+        )
+        .run()?;
     }
 
     Ok(true)
@@ -231,4 +357,33 @@ mod test {
         let result = values_path(env).unwrap();
         assert_eq!(result, "config_values/prod.yaml");
     }
+
+    #[test]
+    fn test_manifest_secrets() {
+        let manifest = r#"
+apiVersion: v1
+kind: Secret
+metadata:
+  name: irods
+data:
+  password: aHVudGVyMg==
+stringData:
+  user: rods
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: other
+data:
+  key: value
+"#;
+        let secrets = manifest_secrets(manifest).unwrap();
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(secrets[0].0, "irods");
+        assert_eq!(secrets[0].1["password"], "hunter2");
+        assert_eq!(secrets[0].1["user"], "rods");
+
+        assert!(manifest_secrets("kind: Secret\ndata: {}\n").is_err());
+        assert!(manifest_secrets("").unwrap().is_empty());
+    }
 }
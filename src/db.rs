@@ -1,6 +1,16 @@
 //! # Database Access
 //!
 //! This module contains all the database access code for the application.
+//! The queries here are written for Postgres. The `backend` and `dialect`
+//! modules cover the operations that also have to work against MySQL, which is
+//! what a site's Dolt database speaks. The `migrate` module applies the
+//! schema migrations embedded in the binary, and the `dump` module exports
+//! and imports the contents of the database.
+pub mod backend;
+pub mod dialect;
+pub mod dump;
+pub mod migrate;
+
 use anyhow::Context;
 use sqlx::{Postgres, Row, Transaction};
 use std::path::PathBuf;
@@ -139,6 +149,85 @@ pub async fn get_env_id(
     Ok(env_id.id)
 }
 
+/// Returns whether the environment exists in the database.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::has_env(&mut tx, "dev").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn has_env(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<bool> {
+    let env = sqlx::query!(
+        r#"
+                SELECT id FROM environments WHERE name = $1
+        "#,
+        environment
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(env.is_some())
+}
+
+/// Returns the name of an environment's parent environment, if it has one.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::get_env_parent(&mut tx, "dev").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn get_env_parent(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<Option<String>> {
+    let parent = sqlx::query!(
+        r#"
+                SELECT p.name AS name
+                FROM environments e
+                JOIN environments p ON e.parent_id = p.id
+                WHERE e.name = $1
+        "#,
+        environment
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    Ok(parent.map(|p| p.name))
+}
+
+/// Sets or clears an environment's parent environment.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// db::set_env_parent(&mut tx, "dev", Some("qa")).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn set_env_parent(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+    parent: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+                UPDATE environments
+                SET parent_id = (SELECT id FROM environments WHERE name = $2)
+                WHERE name = $1
+        "#,
+        environment,
+        parent
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
 /// Returns a listing of the url and name of the repositories stored in the
 /// database.
 ///
@@ -340,6 +429,69 @@ pub async fn set_default_config_value(
     .id)
 }
 
+/// Marks a default configuration value as sensitive or not. The values of
+/// sensitive settings are masked when they're displayed.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// db::set_default_sensitive(&mut tx, "DEDB", "Password", true).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn set_default_sensitive(
+    tx: &mut Transaction<'_, Postgres>,
+    section: &str,
+    key: &str,
+    sensitive: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE config_defaults
+            SET sensitive = $1
+            WHERE section_id = (SELECT id FROM config_sections WHERE name = $2)
+            AND cfg_key = $3
+        "#,
+        sensitive,
+        section,
+        key
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the (section, key) pairs of the default configuration values that
+/// are marked as sensitive.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::list_sensitive_keys(&mut tx).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn list_sensitive_keys(
+    tx: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let keys = sqlx::query!(
+        r#"
+            SELECT
+                config_sections.name AS section,
+                config_defaults.cfg_key AS key
+            FROM config_defaults
+            INNER JOIN config_sections ON config_defaults.section_id = config_sections.id
+            WHERE config_defaults.sensitive
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .map(|r| (r.section, r.key))
+    .collect();
+
+    Ok(keys)
+}
+
 /// Returns whether a default configuration value exists in the database
 /// associated with the given section and key.
 ///
@@ -732,7 +884,7 @@ INNER JOIN config_value_types ON config_values.value_type_id = config_value_type
     }
 
     if let Some(key) = key {
-        if builder.sql().contains("WHERE") {
+        if !builder.sql().contains("WHERE") {
             builder.push("\nWHERE config_values.cfg_key = ");
         } else {
             builder.push(" AND config_values.cfg_key = ");
@@ -819,6 +971,182 @@ pub async fn update_env_cfg_value(
     Ok(())
 }
 
+/// Returns all of the configuration values with the secret value type, across
+/// all environments.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::list_secret_config_values(&mut tx).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn list_secret_config_values(
+    tx: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<Vec<ConfigurationValue>> {
+    let cfgs = sqlx::query_as!(
+        ConfigurationValue,
+        r#"
+            SELECT
+                config_values.id AS id,
+                config_sections.name AS section,
+                config_values.cfg_key AS key,
+                config_values.cfg_value AS value,
+                config_value_types.name AS value_type
+            FROM config_values
+            INNER JOIN config_sections ON config_values.section_id = config_sections.id
+            INNER JOIN config_value_types ON config_values.value_type_id = config_value_types.id
+            WHERE config_value_types.name = 'secret'
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(cfgs)
+}
+
+/// Returns all of the default configuration values with the secret value type.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::list_secret_default_values(&mut tx).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn list_secret_default_values(
+    tx: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<Vec<ConfigurationValue>> {
+    let cfgs = sqlx::query_as!(
+        ConfigurationValue,
+        r#"
+            SELECT
+                config_defaults.id AS id,
+                config_sections.name AS section,
+                config_defaults.cfg_key AS key,
+                config_defaults.cfg_value AS value,
+                config_value_types.name AS value_type
+            FROM config_defaults
+            INNER JOIN config_sections ON config_defaults.section_id = config_sections.id
+            INNER JOIN config_value_types ON config_defaults.value_type_id = config_value_types.id
+            WHERE config_value_types.name = 'secret'
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(cfgs)
+}
+
+/// Replaces the stored value of a configuration value, looked up by its ID.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// db::update_config_value_by_id(&mut tx, 1, "new value").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn update_config_value_by_id(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    value: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE config_values SET cfg_value = $1 WHERE id = $2
+        "#,
+        value,
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Replaces the stored value and value type of a configuration value, looked
+/// up by its ID.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// db::update_typed_config_value_by_id(&mut tx, 1, "5432", "int").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn update_typed_config_value_by_id(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    value: &str,
+    val_type: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE config_values
+            SET
+                cfg_value = $1,
+                value_type_id = (SELECT id FROM config_value_types WHERE name = $2)
+            WHERE id = $3
+        "#,
+        value,
+        val_type,
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes a configuration value, looked up by its ID. The link to its
+/// environment is removed along with it.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// db::delete_config_value_by_id(&mut tx, 1).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn delete_config_value_by_id(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            DELETE FROM config_values WHERE id = $1
+        "#,
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Replaces the stored value of a default configuration value, looked up by
+/// its ID.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// db::update_default_value_by_id(&mut tx, 1, "new value").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn update_default_value_by_id(
+    tx: &mut Transaction<'_, Postgres>,
+    id: i32,
+    value: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE config_defaults SET cfg_value = $1 WHERE id = $2
+        "#,
+        value,
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
 /// Represents a single service as stored in the database.
 #[derive(sqlx::FromRow, Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct Service {
@@ -848,18 +1176,17 @@ pub async fn list_affected_services(
     let services = sqlx::query_as!(
         Service,
         r#"
-                SELECT 
+                SELECT DISTINCT
                     services.name AS name,
                     services.id AS id,
                     services.repo_id AS repo_id
-                FROM environments
-                INNER JOIN environments_services ON environments.id = environments_services.environment_id
+                FROM environments_services_config_values
+                INNER JOIN environments_services ON environments_services_config_values.environment_service_id = environments_services.id
+                INNER JOIN environments ON environments_services.environment_id = environments.id
                 INNER JOIN services ON environments_services.service_id = services.id
-                INNER JOIN environments_config_values ON environments.id = environments_config_values.environment_id
-                INNER JOIN config_values ON environments_config_values.config_value_id = config_values.id
-                INNER JOIN environments_services_config_values a ON environments_services.id = a.environment_service_id
-                INNER JOIN environments_services_config_values b ON environments_config_values.id = b.environment_config_value_id
-                WHERE environments.name = $1 AND config_values.id = $2
+                INNER JOIN environments_config_values ON environments_services_config_values.environment_config_value_id = environments_config_values.id
+                WHERE environments.name = $1 AND environments_config_values.config_value_id = $2
+                ORDER BY services.name
         "#,
         environment,
         cfg_id
@@ -870,35 +1197,40 @@ pub async fn list_affected_services(
     Ok(services)
 }
 
-/// Lists services in the database.
+/// Lists the services in an environment that use the default value of a
+/// config setting, because nothing in the environment overrides it.
 ///
 /// # Examples
 /// ```ignore
 /// let mut tx = db.begin().await?;
-/// let result = db::list_services(&mut tx).await?;
+/// let result = db::list_default_affected_services(&mut tx, "dev", 1).await?;
 /// tx.commit().await?;
 ///
 /// for service in result {
 ///    println!("{}", service);
 /// }
 /// ```
-pub async fn list_services(
+pub async fn list_default_affected_services(
     tx: &mut Transaction<'_, Postgres>,
     environment: &str,
+    default_id: i32,
 ) -> anyhow::Result<Vec<Service>> {
     let services = sqlx::query_as!(
         Service,
         r#"
-                SELECT 
+                SELECT DISTINCT
                     services.name AS name,
                     services.id AS id,
                     services.repo_id AS repo_id
-                FROM environments
-                INNER JOIN environments_services ON environments.id = environments_services.environment_id
+                FROM environments_services_config_defaults
+                INNER JOIN environments_services ON environments_services_config_defaults.environment_service_id = environments_services.id
+                INNER JOIN environments ON environments_services.environment_id = environments.id
                 INNER JOIN services ON environments_services.service_id = services.id
-                WHERE environments.name = $1
+                WHERE environments.name = $1 AND environments_services_config_defaults.config_default_id = $2
+                ORDER BY services.name
         "#,
-        environment
+        environment,
+        default_id
     )
     .fetch_all(&mut **tx)
     .await?;
@@ -906,7 +1238,43 @@ pub async fn list_services(
     Ok(services)
 }
 
-/// Returns the services for an environment.
+/// Lists services in the database.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::list_services(&mut tx).await?;
+/// tx.commit().await?;
+///
+/// for service in result {
+///    println!("{}", service);
+/// }
+/// ```
+pub async fn list_services(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<Vec<Service>> {
+    let services = sqlx::query_as!(
+        Service,
+        r#"
+                SELECT 
+                    services.name AS name,
+                    services.id AS id,
+                    services.repo_id AS repo_id
+                FROM environments
+                INNER JOIN environments_services ON environments.id = environments_services.environment_id
+                INNER JOIN services ON environments_services.service_id = services.id
+                WHERE environments.name = $1
+        "#,
+        environment
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(services)
+}
+
+/// Returns the services for an environment.
 ///
 /// # Examples
 /// ```ignore
@@ -1102,195 +1470,186 @@ pub async fn get_namespace(
     Ok(namespace.namespace)
 }
 
-/// Represents a set of feature flags for an environment as returned from
-/// the database.
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct FeatureFlags {
-    pub administration: bool,
-    pub analytics: bool,
-    pub agave: bool,
-    pub base_urls: bool,
-    pub cas: bool,
-    pub docker: bool,
-    pub infosquito: bool,
-    pub intercom: bool,
-    pub jaeger: bool,
-    pub jobs: bool,
-    pub jvmopts: bool,
-    pub permanent_id: bool,
-    pub qa: bool,
-    pub qms: bool,
-    pub unleash: bool,
-}
-
-/// Returns the feature flag settings for the provided environment.
+/// A feature flag in the registry. Each flag turns an optional config section
+/// on or off.
+#[derive(
+    sqlx::FromRow, tabled::Tabled, Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq,
+)]
+pub struct FeatureFlag {
+    pub name: String,
+    pub section: String,
+    pub description: String,
+}
+
+/// A feature flag's setting in an environment.
+#[derive(
+    sqlx::FromRow, tabled::Tabled, Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq,
+)]
+pub struct EnvFeatureFlag {
+    pub name: String,
+    pub section: String,
+    pub enabled: bool,
+}
+
+/// Returns the feature flags in the registry, sorted by name.
 ///
 /// # Examples
 /// ```ignore
 /// let mut tx = db.begin().await?;
-/// let result = db::get_feature_flags(&mut tx, "dev").await?;
+/// let result = db::list_feature_flags(&mut tx).await?;
 /// tx.commit().await?;
-///
-/// for flag in result {
-///    println!("{}", flag);
-/// }
 /// ```
-pub async fn get_feature_flags(
+pub async fn list_feature_flags(
     tx: &mut Transaction<'_, Postgres>,
-    env: &str,
-) -> anyhow::Result<FeatureFlags> {
+) -> anyhow::Result<Vec<FeatureFlag>> {
     Ok(sqlx::query_as!(
-        FeatureFlags,
+        FeatureFlag,
         r#"
-            SELECT 
-                environments_features.administration AS administration,
-                environments_features.analytics AS analytics,
-                environments_features.agave AS agave,
-                environments_features.base_urls AS base_urls,
-                environments_features.cas AS cas,
-                environments_features.docker AS docker,
-                environments_features.infosquito AS infosquito,
-                environments_features.intercom AS intercom,
-                environments_features.jaeger AS jaeger,
-                environments_features.jobs AS jobs,
-                environments_features.jvmopts AS jvmopts,
-                environments_features.permanent_id AS permanent_id,
-                environments_features.qa AS qa,
-                environments_features.qms AS qms,
-                environments_features.unleash AS unleash
-            FROM environments_features
-            INNER JOIN environments ON environments.id = environments_features.environment_id
-            WHERE environments.name = $1
-        "#,
-        env
+            SELECT
+                feature_flags.name AS name,
+                config_sections.name AS section,
+                feature_flags.description AS description
+            FROM feature_flags
+            INNER JOIN config_sections ON config_sections.id = feature_flags.section_id
+            ORDER BY feature_flags.name
+        "#
     )
-    .fetch_one(&mut **tx)
+    .fetch_all(&mut **tx)
     .await?)
 }
 
-/// Upserts feature flags for an environment.
+/// Adds a feature flag to the registry, or updates the section and
+/// description of an existing one.
 ///
 /// # Examples
 /// ```ignore
 /// let mut tx = db.begin().await?;
-/// let result = db::upsert_feature_flags(tx, "dev", &FeatureFlags).await?;
+/// db::upsert_feature_flag(&mut tx, "unleash", "Unleash", "The Unleash feature toggle service.").await?;
 /// tx.commit().await?;
 /// ```
-pub async fn upsert_feature_flags(
+pub async fn upsert_feature_flag(
     tx: &mut Transaction<'_, Postgres>,
-    env: &str,
-    flags: &FeatureFlags,
-) -> anyhow::Result<bool> {
+    name: &str,
+    section: &str,
+    description: &str,
+) -> anyhow::Result<i32> {
     Ok(sqlx::query!(
         r#"
-            INSERT INTO environments_features (
-                environment_id, 
-                administration, 
-                analytics, 
-                agave, 
-                base_urls, 
-                cas, docker, 
-                infosquito, 
-                intercom, 
-                jaeger, 
-                jobs, 
-                jvmopts, 
-                permanent_id, 
-                qa, 
-                qms, 
-                unleash
-            ) VALUES (
-                (SELECT id FROM environments WHERE name = $1),
-                $2,
-                $3,
-                $4,
-                $5,
-                $6,
-                $7,
-                $8,
-                $9,
-                $10,
-                $11,
-                $12,
-                $13,
-                $14,
-                $15,
-                $16
-            ) ON CONFLICT (environment_id) DO UPDATE
-            SET
-                administration = $2,
-                analytics = $3,
-                agave = $4,
-                base_urls = $5,
-                cas = $6,
-                docker = $7,
-                infosquito = $8,
-                intercom = $9,
-                jaeger = $10,
-                jobs = $11,
-                jvmopts = $12,
-                permanent_id = $13,
-                qa = $14,
-                qms = $15,
-                unleash = $16
+            INSERT INTO feature_flags (name, section_id, description)
+            VALUES ($1, (SELECT id FROM config_sections WHERE name = $2), $3)
+            ON CONFLICT (name) DO UPDATE
+            SET section_id = EXCLUDED.section_id, description = EXCLUDED.description
+            RETURNING id
         "#,
-        env,
-        flags.administration,
-        flags.analytics,
-        flags.agave,
-        flags.base_urls,
-        flags.cas,
-        flags.docker,
-        flags.infosquito,
-        flags.intercom,
-        flags.jaeger,
-        flags.jobs,
-        flags.jvmopts,
-        flags.permanent_id,
-        flags.qa,
-        flags.qms,
-        flags.unleash
+        name,
+        section,
+        description
     )
-    .execute(&mut **tx)
-    .await?
-    .rows_affected()
-        > 0)
+    .fetch_one(&mut **tx)
+    .await
+    .with_context(|| {
+        format!(
+            "failed to add feature flag {} for section {}",
+            name, section
+        )
+    })?
+    .id)
 }
 
-/// Sets a value for a feature flag. Returns a result with the number of rows affected.
+/// Returns every feature flag in the registry with its setting in the
+/// provided environment. Flags that haven't been set are turned off.
 ///
 /// # Examples
 /// ```ignore
 /// let mut tx = db.begin().await?;
-/// let result = db::set_feature_flag(&mut tx, "dev", "administration", true).await?;
+/// let result = db::get_feature_flags(&mut tx, "dev").await?;
 /// tx.commit().await?;
+///
+/// for flag in result {
+///    println!("{} = {}", flag.name, flag.enabled);
+/// }
 /// ```
-pub async fn set_feature_flag(
+pub async fn get_feature_flags(
     tx: &mut Transaction<'_, Postgres>,
     env: &str,
-    flag: &str,
-    value: &str,
-) -> anyhow::Result<u64> {
-    // I'm unsure about this, but it should be safe for now since the flag and the value are checked against a whitelist
-    // set in clap. If this changes, this should be revisited.
-    let flag = flag.to_lowercase();
+) -> anyhow::Result<Vec<EnvFeatureFlag>> {
+    let env_id = get_env_id(tx, env)
+        .await
+        .with_context(|| format!("no environment named {}", env))?;
 
-    let query = format!(
+    Ok(sqlx::query_as!(
+        EnvFeatureFlag,
         r#"
-            UPDATE environments_features
-            INNER JOIN environments ON environments.id = environments_features.environment_id
-            SET environments_features.{} = "#,
-        flag,
-    );
+            SELECT
+                feature_flags.name AS name,
+                config_sections.name AS section,
+                COALESCE(environments_feature_flags.enabled, FALSE) AS "enabled!"
+            FROM feature_flags
+            INNER JOIN config_sections ON config_sections.id = feature_flags.section_id
+            LEFT JOIN environments_feature_flags
+                ON environments_feature_flags.feature_flag_id = feature_flags.id
+                AND environments_feature_flags.environment_id = $1
+            ORDER BY feature_flags.name
+        "#,
+        env_id
+    )
+    .fetch_all(&mut **tx)
+    .await?)
+}
 
-    let mut builder: sqlx::QueryBuilder<Postgres> = sqlx::QueryBuilder::new(query);
-    builder.push_bind(value);
-    builder.push("WHERE environments.name = ");
-    builder.push_bind(env);
+/// Parses the value of a feature flag. Accepts true/false, yes/no, on/off and
+/// 1/0, ignoring case.
+pub fn parse_flag_value(value: &str) -> anyhow::Result<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(anyhow::anyhow!(
+            "{} is not a valid feature flag value. Use true or false.",
+            value
+        )),
+    }
+}
+
+/// Turns the feature flags for optional sections on or off in an environment.
+/// The sections are mapped to whether they're included; every flag tied to
+/// one of the sections is set. Returns the number of flags set.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::set_section_flags(&mut tx, "dev", opts.sections()).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn set_section_flags(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    sections: &std::collections::BTreeMap<String, bool>,
+) -> anyhow::Result<u64> {
+    let env_id = get_env_id(tx, env)
+        .await
+        .with_context(|| format!("no environment named {}", env))?;
 
-    let result = builder.build().execute(&mut **tx).await?;
+    let mut count = 0;
+    for (section, enabled) in sections {
+        count += sqlx::query!(
+            r#"
+                INSERT INTO environments_feature_flags (environment_id, feature_flag_id, enabled)
+                SELECT $1, feature_flags.id, $3
+                FROM feature_flags
+                INNER JOIN config_sections ON config_sections.id = feature_flags.section_id
+                WHERE config_sections.name = $2
+                ON CONFLICT (environment_id, feature_flag_id) DO UPDATE SET enabled = EXCLUDED.enabled
+            "#,
+            env_id,
+            section,
+            enabled
+        )
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+    }
 
-    Ok(result.rows_affected())
+    Ok(count)
 }
 
 /// Returns a listing of the configuration templates in use by services
@@ -1398,7 +1757,7 @@ pub async fn copy_service_template_to_env(
                 environments_services_config_templates.id AS id,
                 environments_services_config_templates.environment_service_id AS environment_service_id,
                 environments_services_config_templates.config_template_id AS config_template_id,
-                config_templates.path AS path
+                environments_services_config_templates.path AS path
             FROM environments_services_config_templates
             JOIN environments_services ON environments_services.id = environments_services_config_templates.environment_service_id
             JOIN environments ON environments.id = environments_services.environment_id
@@ -1505,6 +1864,213 @@ pub async fn add_template_to_service(
     .id)
 }
 
+/// Returns the ID of the template with a path in a repo, if there is one. If
+/// more than one template has the path, the oldest one is returned.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::get_template_id(&mut tx, 4, "templates/apps.properties").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn get_template_id(
+    tx: &mut Transaction<'_, Postgres>,
+    repo_id: i32,
+    path: &str,
+) -> anyhow::Result<Option<i32>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT id FROM config_templates WHERE repo_id = $1 AND path = $2 ORDER BY id LIMIT 1
+        "#,
+        repo_id,
+        path
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .map(|t| t.id))
+}
+
+/// Returns whether a template is associated with a service in an environment.
+pub async fn has_service_template(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    service_name: &str,
+    template_id: i32,
+) -> anyhow::Result<bool> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT esct.id AS id
+            FROM environments_services_config_templates esct
+            JOIN environments_services es ON es.id = esct.environment_service_id
+            JOIN environments e ON e.id = es.environment_id
+            JOIN services s ON s.id = es.service_id
+            WHERE e.name = $1 AND s.name = $2 AND esct.config_template_id = $3
+        "#,
+        env,
+        service_name,
+        template_id
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .is_some())
+}
+
+/// Removes the association between a template in a repo and a service in an
+/// environment. Returns the number of associations that were removed.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::remove_template_from_service(&mut tx, "dev", "apps", 4, "templates/apps.properties").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn remove_template_from_service(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    service_name: &str,
+    repo_id: i32,
+    template_path: &str,
+) -> anyhow::Result<u64> {
+    Ok(sqlx::query!(
+        r#"
+            DELETE FROM environments_services_config_templates esct
+            USING environments_services es, environments e, services s, config_templates ct
+            WHERE es.id = esct.environment_service_id
+            AND e.id = es.environment_id
+            AND s.id = es.service_id
+            AND ct.id = esct.config_template_id
+            AND e.name = $1
+            AND s.name = $2
+            AND ct.repo_id = $3
+            AND ct.path = $4
+        "#,
+        env,
+        service_name,
+        repo_id,
+        template_path
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected())
+}
+
+/// Returns the number of services, across every environment, that a template
+/// in a repo is associated with.
+pub async fn count_template_uses(
+    tx: &mut Transaction<'_, Postgres>,
+    repo_id: i32,
+    template_path: &str,
+) -> anyhow::Result<i64> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT COUNT(esct.id) AS "count!"
+            FROM environments_services_config_templates esct
+            JOIN config_templates ct ON ct.id = esct.config_template_id
+            WHERE ct.repo_id = $1 AND ct.path = $2
+        "#,
+        repo_id,
+        template_path
+    )
+    .fetch_one(&mut **tx)
+    .await?
+    .count)
+}
+
+/// Deletes every template with a path in a repo, along with the settings
+/// recorded for them. Returns the number of templates that were deleted.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::delete_template(&mut tx, 4, "templates/old.properties").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn delete_template(
+    tx: &mut Transaction<'_, Postgres>,
+    repo_id: i32,
+    template_path: &str,
+) -> anyhow::Result<u64> {
+    Ok(sqlx::query!(
+        r#"
+            DELETE FROM config_templates WHERE repo_id = $1 AND path = $2
+        "#,
+        repo_id,
+        template_path
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected())
+}
+
+/// Changes the path of every template with a path in a repo. The services the
+/// templates are associated with, and their render paths, are left alone.
+/// Returns the number of templates that were changed.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::move_template(&mut tx, 4, "templates/apps.properties", "templates/apps/apps.properties").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn move_template(
+    tx: &mut Transaction<'_, Postgres>,
+    repo_id: i32,
+    from_path: &str,
+    to_path: &str,
+) -> anyhow::Result<u64> {
+    Ok(sqlx::query!(
+        r#"
+            UPDATE config_templates SET path = $3 WHERE repo_id = $1 AND path = $2
+        "#,
+        repo_id,
+        from_path,
+        to_path
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected())
+}
+
+/// A template that isn't associated with any service in any environment.
+#[derive(tabled::Tabled, Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct OrphanedTemplate {
+    pub template_id: i32,
+    pub repo_name: String,
+    pub template_path: String,
+}
+
+/// Returns the templates that aren't associated with any service.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::list_orphaned_templates(&mut tx).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn list_orphaned_templates(
+    tx: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<Vec<OrphanedTemplate>> {
+    Ok(sqlx::query_as!(
+        OrphanedTemplate,
+        r#"
+            SELECT
+                ct.id AS template_id,
+                r.name AS repo_name,
+                ct.path AS template_path
+            FROM config_templates ct
+            JOIN repos r ON r.id = ct.repo_id
+            WHERE NOT EXISTS (
+                SELECT 1
+                FROM environments_services_config_templates esct
+                WHERE esct.config_template_id = ct.id
+            )
+            ORDER BY ct.path, ct.id
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
 #[derive(tabled::Tabled, Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct TemplateInfo {
     pub environment: String,
@@ -1593,6 +2159,227 @@ pub async fn list_template_info(
     Ok(results)
 }
 
+/// A template that's associated with a service in an environment.
+#[derive(tabled::Tabled, Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct ServiceTemplate {
+    pub service_name: String,
+    pub template_id: i32,
+    pub template_path: String,
+    pub render_path: String,
+}
+
+/// Returns the templates associated with the services in an environment.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::list_env_service_templates(&mut tx, "dev").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn list_env_service_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+) -> anyhow::Result<Vec<ServiceTemplate>> {
+    Ok(sqlx::query_as!(
+        ServiceTemplate,
+        r#"
+            SELECT
+                s.name AS service_name,
+                ct.id AS template_id,
+                ct.path AS template_path,
+                esct.path AS render_path
+            FROM environments e
+            JOIN environments_services es ON es.environment_id = e.id
+            JOIN services s ON s.id = es.service_id
+            JOIN environments_services_config_templates esct ON esct.environment_service_id = es.id
+            JOIN config_templates ct ON ct.id = esct.config_template_id
+            WHERE e.name = $1
+            ORDER BY s.name, ct.path
+        "#,
+        env
+    )
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
+/// Replaces the list of config settings that a template refers to. The
+/// references are (section, key) pairs.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// db::set_template_references(&mut tx, 1, &[("DEDB".to_string(), "Host".to_string())]).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn set_template_references(
+    tx: &mut Transaction<'_, Postgres>,
+    template_id: i32,
+    references: &[(String, String)],
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            DELETE FROM config_template_references WHERE config_template_id = $1
+        "#,
+        template_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    for (section, key) in references {
+        sqlx::query!(
+            r#"
+                INSERT INTO config_template_references (config_template_id, section_id, cfg_key)
+                SELECT $1, id, $3 FROM config_sections WHERE name = $2
+                ON CONFLICT DO NOTHING
+            "#,
+            template_id,
+            section,
+            key
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Rebuilds the links between the services in an environment and the config
+/// values they use, based on the settings their templates refer to. The
+/// values are the ones that apply to the environment: `value_ids` are the
+/// config_values rows that are used, which may belong to a parent
+/// environment, and `default_ids` are the config_defaults rows used for the
+/// settings that no environment overrides. Returns the number of links
+/// created.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let count = db::link_env_service_cfg_values(&mut tx, "dev", &value_ids, &default_ids).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn link_env_service_cfg_values(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    value_ids: &[i32],
+    default_ids: &[i32],
+) -> anyhow::Result<u64> {
+    sqlx::query!(
+        r#"
+            DELETE FROM environments_services_config_values
+            WHERE environment_service_id IN (
+                SELECT es.id
+                FROM environments_services es
+                JOIN environments e ON e.id = es.environment_id
+                WHERE e.name = $1
+            )
+        "#,
+        env
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            DELETE FROM environments_services_config_defaults
+            WHERE environment_service_id IN (
+                SELECT es.id
+                FROM environments_services es
+                JOIN environments e ON e.id = es.environment_id
+                WHERE e.name = $1
+            )
+        "#,
+        env
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    let values = sqlx::query!(
+        r#"
+            INSERT INTO environments_services_config_values
+                (environment_service_id, environment_config_value_id)
+            SELECT DISTINCT es.id, ecv.id
+            FROM environments e
+            JOIN environments_services es ON es.environment_id = e.id
+            JOIN environments_services_config_templates esct ON esct.environment_service_id = es.id
+            JOIN config_template_references ctr ON ctr.config_template_id = esct.config_template_id
+            JOIN config_values cv ON cv.section_id = ctr.section_id
+                AND cv.cfg_key = ctr.cfg_key
+            JOIN environments_config_values ecv ON ecv.config_value_id = cv.id
+            WHERE e.name = $1 AND cv.id = ANY($2)
+            ON CONFLICT DO NOTHING
+        "#,
+        env,
+        value_ids
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    let defaults = sqlx::query!(
+        r#"
+            INSERT INTO environments_services_config_defaults
+                (environment_service_id, config_default_id)
+            SELECT DISTINCT es.id, cd.id
+            FROM environments e
+            JOIN environments_services es ON es.environment_id = e.id
+            JOIN environments_services_config_templates esct ON esct.environment_service_id = es.id
+            JOIN config_template_references ctr ON ctr.config_template_id = esct.config_template_id
+            JOIN config_defaults cd ON cd.section_id = ctr.section_id
+                AND cd.cfg_key = ctr.cfg_key
+            WHERE e.name = $1 AND cd.id = ANY($2)
+            ON CONFLICT DO NOTHING
+        "#,
+        env,
+        default_ids
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    Ok(values + defaults)
+}
+
+/// Returns the templates for services in an environment that refer to a
+/// config setting. Only templates that have been scanned are included.
+///
+/// # Examples
+/// ```ignore
+/// let mut tx = db.begin().await?;
+/// let result = db::list_impacted_templates(&mut tx, "dev", "DE", "AMQP.URI").await?;
+/// tx.commit().await?;
+/// ```
+pub async fn list_impacted_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    section: &str,
+    key: &str,
+) -> anyhow::Result<Vec<ServiceTemplate>> {
+    Ok(sqlx::query_as!(
+        ServiceTemplate,
+        r#"
+            SELECT DISTINCT
+                s.name AS service_name,
+                ct.id AS template_id,
+                ct.path AS template_path,
+                esct.path AS render_path
+            FROM environments e
+            JOIN environments_services es ON es.environment_id = e.id
+            JOIN services s ON s.id = es.service_id
+            JOIN environments_services_config_templates esct ON esct.environment_service_id = es.id
+            JOIN config_templates ct ON ct.id = esct.config_template_id
+            JOIN config_template_references ctr ON ctr.config_template_id = ct.id
+            JOIN config_sections cs ON cs.id = ctr.section_id
+            WHERE e.name = $1 AND cs.name = $2 AND ctr.cfg_key = $3
+            ORDER BY s.name, ct.path
+        "#,
+        env,
+        section,
+        key
+    )
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
 #[derive(sqlx::FromRow, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Repo {
     pub id: i32,
@@ -1702,3 +2489,22 @@ pub async fn delete_repo(tx: &mut Transaction<'_, Postgres>, id: i32) -> anyhow:
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_flag_value() {
+        assert!(parse_flag_value("true").unwrap());
+        assert!(parse_flag_value("Yes").unwrap());
+        assert!(parse_flag_value("on").unwrap());
+        assert!(parse_flag_value("1").unwrap());
+        assert!(!parse_flag_value("FALSE").unwrap());
+        assert!(!parse_flag_value("no").unwrap());
+        assert!(!parse_flag_value("off").unwrap());
+        assert!(!parse_flag_value("0").unwrap());
+        assert!(parse_flag_value("maybe").is_err());
+        assert!(parse_flag_value("").is_err());
+    }
+}
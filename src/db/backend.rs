@@ -0,0 +1,471 @@
+//! # Database backends
+//!
+//! Most of `mgmt` works against Postgres through the compile-time checked
+//! queries in the parent module. A site's database is a Dolt repository,
+//! though, and `dolt sql-server` speaks MySQL. `Database` connects to either,
+//! based on the scheme of the URL, and implements the operations that have to
+//! work on both:
+//!
+//! * the site commands: listing repositories and services, writing out the
+//!   defaults, and setting up an environment through `Connection`
+//! * `mgmt env feature-flags list` and `mgmt env feature-flags set`
+//!
+//! Every other command is only implemented for Postgres and gets its pool
+//! from `Database::postgres`, which fails for MySQL databases. The SQL that
+//! differs between the two lives in the `dialect` module.
+use super::dialect::Dialect;
+use super::{ConfigurationValue, EnvFeatureFlag};
+use anyhow::{anyhow, Context, Result};
+use sqlx::mysql::MySqlPoolOptions;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{MySql, MySqlConnection, PgConnection, Pool, Postgres};
+use std::collections::BTreeMap;
+
+/// A connection pool for one of the supported backends.
+#[derive(Debug, Clone)]
+pub enum Database {
+    Postgres(Pool<Postgres>),
+    MySql(Pool<MySql>),
+}
+
+// Runs the same query against whichever backend is in use, converting the
+// placeholders for the dialect first. Each arm has to be written out because
+// the query types are specific to the driver.
+macro_rules! on_backend {
+    ($db:expr, $query:expr, |$q:ident, $pool:ident| $body:expr) => {
+        match $db {
+            Database::Postgres($pool) => {
+                let sql = Dialect::Postgres.sql($query);
+                let $q = sqlx::query_as(&sql);
+                $body
+            }
+            Database::MySql($pool) => {
+                let sql = Dialect::MySql.sql($query);
+                let $q = sqlx::query_as(&sql);
+                $body
+            }
+        }
+    };
+}
+
+// Runs an INSERT statement and returns the id of the new row. Postgres
+// returns it from the statement, MySQL has to be asked for it afterwards.
+macro_rules! insert_returning_id {
+    ($conn:expr, $query:expr, |$q:ident| $binds:expr) => {
+        match $conn {
+            Connection::Postgres(conn) => {
+                let sql = format!("{} RETURNING id", $query);
+                let $q = sqlx::query_scalar::<_, i32>(&sql);
+                $binds.fetch_one(&mut **conn).await?
+            }
+            Connection::MySql(conn) => {
+                let sql = Dialect::MySql.sql($query);
+                let $q = sqlx::query(&sql);
+                i32::try_from($binds.execute(&mut **conn).await?.last_insert_id())?
+            }
+        }
+    };
+}
+
+// Like on_backend!, but for a connection that's part of a transaction.
+macro_rules! on_connection {
+    ($conn:expr, $query:expr, |$q:ident, $c:ident| $body:expr) => {
+        match $conn {
+            Connection::Postgres($c) => {
+                let sql = Dialect::Postgres.sql($query);
+                let $q = sqlx::query_as(&sql);
+                let $c = &mut **$c;
+                $body
+            }
+            Connection::MySql($c) => {
+                let sql = Dialect::MySql.sql($query);
+                let $q = sqlx::query_as(&sql);
+                let $c = &mut **$c;
+                $body
+            }
+        }
+    };
+}
+
+impl Database {
+    /// Connects to the database at the URL, using the backend for the URL's
+    /// scheme.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let db = Database::connect("mysql://root@127.0.0.1:3306/de_releases").await?;
+    /// ```
+    pub async fn connect(url: &str) -> Result<Self> {
+        let db = match Dialect::from_url(url)? {
+            Dialect::Postgres => {
+                Database::Postgres(PgPoolOptions::new().max_connections(5).connect(url).await?)
+            }
+            Dialect::MySql => Database::MySql(
+                MySqlPoolOptions::new()
+                    .max_connections(5)
+                    .connect(url)
+                    .await?,
+            ),
+        };
+
+        Ok(db)
+    }
+
+    /// Returns the SQL dialect of the backend.
+    pub fn dialect(&self) -> Dialect {
+        match self {
+            Database::Postgres(_) => Dialect::Postgres,
+            Database::MySql(_) => Dialect::MySql,
+        }
+    }
+
+    /// Returns the Postgres pool, for the operations that are only
+    /// implemented for Postgres. `operation` names what was being attempted
+    /// for the error message.
+    pub fn postgres(&self, operation: &str) -> Result<&Pool<Postgres>> {
+        match self {
+            Database::Postgres(pool) => Ok(pool),
+            Database::MySql(_) => Err(anyhow!(
+                "{} needs a Postgres database; it isn't supported for MySQL (Dolt) databases",
+                operation
+            )),
+        }
+    }
+
+    /// Returns the (url, name) pairs for the repositories in the database,
+    /// skipping any with an empty URL or name.
+    pub async fn get_repos(&self) -> Result<Vec<(String, String)>> {
+        let query = "SELECT url, name FROM repos ORDER BY name";
+        let repos: Vec<(String, String)> =
+            on_backend!(self, query, |q, pool| q.fetch_all(pool).await?);
+
+        Ok(repos
+            .into_iter()
+            .filter(|(url, name)| !url.is_empty() && !name.is_empty())
+            .collect())
+    }
+
+    /// Returns the names of the services in an environment.
+    pub async fn list_service_names(&self, environment: &str) -> Result<Vec<String>> {
+        let query = r#"
+            SELECT services.name
+            FROM environments
+            INNER JOIN environments_services ON environments.id = environments_services.environment_id
+            INNER JOIN services ON environments_services.service_id = services.id
+            WHERE environments.name = $1
+            ORDER BY services.name
+        "#;
+        let names: Vec<(String,)> = on_backend!(self, query, |q, pool| {
+            q.bind(environment).fetch_all(pool).await?
+        });
+
+        Ok(names.into_iter().map(|(name,)| name).collect())
+    }
+
+    /// Returns the default configuration values, sorted by section and key.
+    pub async fn list_default_config_values(&self) -> Result<Vec<ConfigurationValue>> {
+        let query = r#"
+            SELECT
+                config_defaults.id,
+                config_sections.name,
+                config_defaults.cfg_key,
+                config_defaults.cfg_value,
+                config_value_types.name
+            FROM config_defaults
+            INNER JOIN config_sections ON config_defaults.section_id = config_sections.id
+            INNER JOIN config_value_types ON config_defaults.value_type_id = config_value_types.id
+            ORDER BY config_sections.name, config_defaults.cfg_key
+        "#;
+        let rows: Vec<(i32, String, String, String, String)> =
+            on_backend!(self, query, |q, pool| q.fetch_all(pool).await?);
+
+        Ok(rows
+            .into_iter()
+            .map(|(id, section, key, value, value_type)| ConfigurationValue {
+                id,
+                section,
+                key,
+                value,
+                value_type,
+            })
+            .collect())
+    }
+
+    /// Returns the settings of the feature flags in the registry for an
+    /// environment, sorted by flag name. Flags that haven't been set for the
+    /// environment are turned off.
+    pub async fn get_feature_flags(&self, environment: &str) -> Result<Vec<EnvFeatureFlag>> {
+        if !self.has_env(environment).await? {
+            return Err(anyhow!("no environment named {}", environment));
+        }
+
+        // The COALESCE is done here rather than in the query because MySQL
+        // doesn't keep the boolean column type for it.
+        let query = r#"
+            SELECT feature_flags.name, config_sections.name, environments_feature_flags.enabled
+            FROM feature_flags
+            INNER JOIN config_sections ON config_sections.id = feature_flags.section_id
+            LEFT JOIN environments_feature_flags
+                ON environments_feature_flags.feature_flag_id = feature_flags.id
+                AND environments_feature_flags.environment_id = (
+                    SELECT id FROM environments WHERE name = $1
+                )
+            ORDER BY feature_flags.name
+        "#;
+        let flags: Vec<(String, String, Option<bool>)> = on_backend!(self, query, |q, pool| {
+            q.bind(environment).fetch_all(pool).await?
+        });
+
+        Ok(flags
+            .into_iter()
+            .map(|(name, section, enabled)| EnvFeatureFlag {
+                name,
+                section,
+                enabled: enabled.unwrap_or(false),
+            })
+            .collect())
+    }
+
+    /// Sets a feature flag for an environment. Returns false if the
+    /// environment or the flag doesn't exist.
+    pub async fn set_feature_flag(
+        &self,
+        environment: &str,
+        flag: &str,
+        value: bool,
+    ) -> Result<bool> {
+        match self {
+            Database::Postgres(pool) => {
+                let mut conn = pool.acquire().await?;
+                set_feature_flag(
+                    &mut Connection::Postgres(&mut conn),
+                    environment,
+                    flag,
+                    value,
+                )
+                .await
+            }
+            Database::MySql(pool) => {
+                let mut conn = pool.acquire().await?;
+                set_feature_flag(&mut Connection::MySql(&mut conn), environment, flag, value).await
+            }
+        }
+    }
+
+    /// Sets several feature flags for an environment in a single transaction.
+    /// Fails without setting any of them if the environment doesn't exist or
+    /// one of the flags isn't in the registry.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let flags = BTreeMap::from([("qa".to_string(), true), ("agave".to_string(), false)]);
+    /// db.set_feature_flags("dev", &flags).await?;
+    /// ```
+    pub async fn set_feature_flags(
+        &self,
+        environment: &str,
+        flags: &BTreeMap<String, bool>,
+    ) -> Result<()> {
+        let known: Vec<String> = self
+            .get_feature_flags(environment)
+            .await?
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+        let unknown: Vec<&str> = flags
+            .keys()
+            .filter(|f| !known.contains(f))
+            .map(|f| f.as_str())
+            .collect();
+        if !unknown.is_empty() {
+            return Err(anyhow!(
+                "unknown feature flag(s): {}. The known flags are: {}",
+                unknown.join(", "),
+                known.join(", ")
+            ));
+        }
+
+        match self {
+            Database::Postgres(pool) => {
+                let mut tx = pool.begin().await?;
+                for (flag, value) in flags {
+                    set_feature_flag(
+                        &mut Connection::Postgres(&mut tx),
+                        environment,
+                        flag,
+                        *value,
+                    )
+                    .await?;
+                }
+                tx.commit().await?;
+            }
+            Database::MySql(pool) => {
+                let mut tx = pool.begin().await?;
+                for (flag, value) in flags {
+                    set_feature_flag(&mut Connection::MySql(&mut tx), environment, flag, *value)
+                        .await?;
+                }
+                tx.commit().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns whether there's an environment with the name.
+    async fn has_env(&self, environment: &str) -> Result<bool> {
+        let query = "SELECT id FROM environments WHERE name = $1";
+        let ids: Vec<(i32,)> = on_backend!(self, query, |q, pool| {
+            q.bind(environment).fetch_all(pool).await?
+        });
+
+        Ok(!ids.is_empty())
+    }
+
+    /// Closes the connections in the pool.
+    pub async fn close(&self) {
+        match self {
+            Database::Postgres(pool) => pool.close().await,
+            Database::MySql(pool) => pool.close().await,
+        }
+    }
+}
+
+/// A connection to either backend, usually one that a transaction was started
+/// on. The environment setup prompts write through it, so that a site's Dolt
+/// database can be set up the same way as a Postgres one.
+///
+/// # Example
+/// ```ignore
+/// let mut tx = pool.begin().await?;
+/// env_config.ask_for_info(&mut Connection::Postgres(&mut tx)).await?;
+/// tx.commit().await?;
+/// ```
+pub enum Connection<'a> {
+    Postgres(&'a mut PgConnection),
+    MySql(&'a mut MySqlConnection),
+}
+
+/// Adds an environment if there isn't one with the name already. Returns the
+/// environment's id either way.
+pub async fn upsert_environment(
+    conn: &mut Connection<'_>,
+    environment: &str,
+    namespace: &str,
+) -> Result<i32> {
+    let query = "SELECT id FROM environments WHERE name = $1";
+    let existing: Option<(i32,)> = on_connection!(conn, query, |q, c| {
+        q.bind(environment).fetch_optional(c).await?
+    });
+    if let Some((id,)) = existing {
+        return Ok(id);
+    }
+
+    let query = "INSERT INTO environments (name, namespace) VALUES ($1, $2)";
+    Ok(insert_returning_id!(conn, query, |q| q
+        .bind(environment)
+        .bind(namespace)))
+}
+
+/// Adds a configuration value, tying it to the default for the same section
+/// and key if there is one. Returns the id of the new value.
+pub async fn set_config_value(
+    conn: &mut Connection<'_>,
+    section: &str,
+    key: &str,
+    value: &str,
+    value_type: &str,
+) -> Result<i32> {
+    let query = "SELECT id FROM config_sections WHERE name = $1";
+    let (section_id,): (i32,) = on_connection!(conn, query, |q, c| {
+        q.bind(section).fetch_optional(c).await?
+    })
+    .with_context(|| format!("no config section named {}", section))?;
+
+    let query = r#"
+        INSERT INTO config_values
+            (section_id, cfg_key, cfg_value, value_type_id, default_id)
+        VALUES (
+            $1,
+            $2,
+            $3,
+            (SELECT id FROM config_value_types WHERE name = $4),
+            (SELECT id FROM config_defaults WHERE cfg_key = $5 AND section_id = $6)
+        )
+    "#;
+    Ok(insert_returning_id!(conn, query, |q| q
+        .bind(section_id)
+        .bind(key)
+        .bind(value)
+        .bind(value_type)
+        .bind(key)
+        .bind(section_id)))
+}
+
+/// Adds a configuration value to an environment. Returns the id of the new
+/// association.
+pub async fn add_env_cfg_value(conn: &mut Connection<'_>, env_id: i32, cfg_id: i32) -> Result<i32> {
+    let query =
+        "INSERT INTO environments_config_values (environment_id, config_value_id) VALUES ($1, $2)";
+    Ok(insert_returning_id!(conn, query, |q| q
+        .bind(env_id)
+        .bind(cfg_id)))
+}
+
+/// Sets a feature flag for an environment. Returns false if the environment or
+/// the flag doesn't exist.
+pub async fn set_feature_flag(
+    conn: &mut Connection<'_>,
+    environment: &str,
+    flag: &str,
+    value: bool,
+) -> Result<bool> {
+    let result = match conn {
+        Connection::Postgres(c) => sqlx::query(&Dialect::Postgres.set_feature_flag())
+            .bind(value)
+            .bind(environment)
+            .bind(flag)
+            .execute(&mut **c)
+            .await?
+            .rows_affected(),
+        Connection::MySql(c) => sqlx::query(&Dialect::MySql.set_feature_flag())
+            .bind(value)
+            .bind(environment)
+            .bind(flag)
+            .execute(&mut **c)
+            .await?
+            .rows_affected(),
+    };
+
+    Ok(result > 0)
+}
+
+/// Turns the feature flags for optional sections on or off in an environment.
+/// Returns the number of flags that were set.
+pub async fn set_section_flags(
+    conn: &mut Connection<'_>,
+    environment: &str,
+    sections: &BTreeMap<String, bool>,
+) -> Result<u64> {
+    let mut count = 0;
+    for (section, enabled) in sections {
+        count += match conn {
+            Connection::Postgres(c) => sqlx::query(&Dialect::Postgres.set_section_flag())
+                .bind(enabled)
+                .bind(environment)
+                .bind(section)
+                .execute(&mut **c)
+                .await?
+                .rows_affected(),
+            Connection::MySql(c) => sqlx::query(&Dialect::MySql.set_section_flag())
+                .bind(enabled)
+                .bind(environment)
+                .bind(section)
+                .execute(&mut **c)
+                .await?
+                .rows_affected(),
+        };
+    }
+
+    Ok(count)
+}
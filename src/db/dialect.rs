@@ -0,0 +1,162 @@
+//! # SQL dialects
+//!
+//! Keeps the SQL that differs between Postgres and MySQL (which Dolt speaks)
+//! in one place. The backend is chosen by the scheme of the database URL.
+use anyhow::{anyhow, Result};
+
+/// The SQL dialect spoken by a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Postgres,
+    /// MySQL, including Dolt's `dolt sql-server`.
+    MySql,
+}
+
+impl Dialect {
+    /// Picks the dialect from the scheme of a database URL.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let dialect = Dialect::from_url("mysql://root@127.0.0.1:3306/de_releases")?;
+    /// ```
+    pub fn from_url(url: &str) -> Result<Self> {
+        let scheme = url
+            .split_once("://")
+            .map(|(scheme, _)| scheme.to_lowercase())
+            .ok_or_else(|| anyhow!("the database URL {} has no scheme", url))?;
+
+        match scheme.as_str() {
+            "postgres" | "postgresql" => Ok(Dialect::Postgres),
+            "mysql" | "mariadb" | "dolt" => Ok(Dialect::MySql),
+            _ => Err(anyhow!(
+                "unsupported database URL scheme {}; use postgres:// or mysql://",
+                scheme
+            )),
+        }
+    }
+
+    /// Converts a query written with Postgres-style `$1, $2, ...` placeholders
+    /// to the dialect. MySQL only has positional `?` placeholders, so each
+    /// parameter has to appear once and in order.
+    pub fn sql(&self, query: &str) -> String {
+        match self {
+            Dialect::Postgres => query.to_string(),
+            Dialect::MySql => {
+                let mut converted = String::with_capacity(query.len());
+                let mut chars = query.chars().peekable();
+                while let Some(c) = chars.next() {
+                    if c == '$' && chars.peek().is_some_and(|n| n.is_ascii_digit()) {
+                        while chars.peek().is_some_and(|n| n.is_ascii_digit()) {
+                            chars.next();
+                        }
+                        converted.push('?');
+                    } else {
+                        converted.push(c);
+                    }
+                }
+                converted
+            }
+        }
+    }
+
+    /// Returns the statement that sets a feature flag for an environment,
+    /// adding the setting if the environment doesn't have one yet. The
+    /// parameters are the new value, the environment name, and the flag name.
+    /// Nothing is changed if either name is unknown.
+    pub fn set_feature_flag(&self) -> String {
+        let upsert = self.upsert_enabled();
+
+        self.sql(&format!(
+            r#"
+            INSERT INTO environments_feature_flags (environment_id, feature_flag_id, enabled)
+            SELECT environments.id, feature_flags.id, $1
+            FROM environments, feature_flags
+            WHERE environments.name = $2
+            AND feature_flags.name = $3
+            {upsert}
+            "#
+        ))
+    }
+
+    /// Returns the statement that turns the feature flags tied to a config
+    /// section on or off for an environment. The parameters are the new
+    /// value, the environment name, and the section name.
+    pub fn set_section_flag(&self) -> String {
+        self.sql(&format!(
+            r#"
+            INSERT INTO environments_feature_flags (environment_id, feature_flag_id, enabled)
+            SELECT environments.id, feature_flags.id, $1
+            FROM environments, feature_flags
+            INNER JOIN config_sections ON config_sections.id = feature_flags.section_id
+            WHERE environments.name = $2
+            AND config_sections.name = $3
+            {}
+            "#,
+            self.upsert_enabled()
+        ))
+    }
+
+    // The clause that updates an existing environment_feature_flags row.
+    fn upsert_enabled(&self) -> &'static str {
+        match self {
+            Dialect::Postgres => {
+                "ON CONFLICT (environment_id, feature_flag_id) DO UPDATE SET enabled = EXCLUDED.enabled"
+            }
+            Dialect::MySql => "ON DUPLICATE KEY UPDATE enabled = VALUES(enabled)",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_url() {
+        assert_eq!(
+            Dialect::from_url("postgresql://root@127.0.0.1:5432/de_releases").unwrap(),
+            Dialect::Postgres
+        );
+        assert_eq!(
+            Dialect::from_url("MySQL://root@127.0.0.1:3306/de_releases").unwrap(),
+            Dialect::MySql
+        );
+        assert!(Dialect::from_url("sqlite://de_releases.db").is_err());
+        assert!(Dialect::from_url("de_releases").is_err());
+    }
+
+    #[test]
+    fn test_sql() {
+        let query = "SELECT name FROM environments WHERE name = $1 AND namespace = $12";
+        assert_eq!(Dialect::Postgres.sql(query), query);
+        assert_eq!(
+            Dialect::MySql.sql(query),
+            "SELECT name FROM environments WHERE name = ? AND namespace = ?"
+        );
+        assert_eq!(Dialect::MySql.sql("SELECT '$'"), "SELECT '$'");
+    }
+
+    #[test]
+    fn test_set_feature_flag() {
+        let postgres = Dialect::Postgres.set_feature_flag();
+        assert!(postgres.contains("WHERE environments.name = $2"));
+        assert!(postgres.contains("ON CONFLICT"));
+
+        let mysql = Dialect::MySql.set_feature_flag();
+        assert!(mysql.contains("SELECT environments.id, feature_flags.id, ?"));
+        assert!(mysql.contains("ON DUPLICATE KEY UPDATE"));
+        assert!(!mysql.contains('$'));
+    }
+
+    #[test]
+    fn test_set_section_flag() {
+        let postgres = Dialect::Postgres.set_section_flag();
+        assert!(postgres.contains("AND config_sections.name = $3"));
+        assert!(postgres.contains("ON CONFLICT"));
+
+        let mysql = Dialect::MySql.set_section_flag();
+        assert!(mysql.contains("SELECT environments.id, feature_flags.id, ?"));
+        assert!(mysql.contains("ON DUPLICATE KEY UPDATE"));
+        assert!(!mysql.contains('$'));
+    }
+}
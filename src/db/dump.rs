@@ -0,0 +1,935 @@
+//! # Exports and imports
+//!
+//! Dumps the contents of the database to a file and loads them back, for
+//! backing up Postgres-backed installs and seeding test databases. Rows refer
+//! to each other by name rather than by ID, and every list is sorted, so a dump
+//! reads well in a diff and can be loaded into a database whose IDs differ.
+//!
+//! Secret values are written as they're stored, encrypted, so a dump can only
+//! be read back with the same secrets key.
+use super::backend::{self, Connection};
+use super::{migrate, ConfigurationValue, FeatureFlag};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use std::collections::BTreeMap;
+
+/// The contents of a database.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dump {
+    /// The schema version of the database the dump was taken from.
+    pub schema_version: Option<i64>,
+    pub value_types: Vec<String>,
+    pub sections: Vec<String>,
+    /// The registry of feature flags and the sections they turn on.
+    #[serde(default)]
+    pub feature_flags: Vec<FeatureFlag>,
+    pub repos: Vec<DumpRepo>,
+    pub images: Vec<DumpImage>,
+    pub services: Vec<DumpService>,
+    pub templates: Vec<DumpTemplate>,
+    pub defaults: Vec<DumpDefault>,
+    pub environments: Vec<DumpEnvironment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpRepo {
+    pub name: String,
+    pub url: String,
+    pub revision: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpImage {
+    pub name: String,
+    pub tag: String,
+    pub repo: String,
+    pub dockerfile: String,
+    pub digest: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpService {
+    pub name: String,
+    pub repo: String,
+    pub skaffold_path: String,
+    /// The service's container images, as `name:tag`.
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpTemplate {
+    pub repo: String,
+    pub path: String,
+    /// The settings the template refers to, as `Section.Key`.
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpDefault {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+    pub value_type: String,
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpEnvironment {
+    pub name: String,
+    pub namespace: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub features: BTreeMap<String, bool>,
+    #[serde(default)]
+    pub services: Vec<DumpEnvService>,
+    #[serde(default)]
+    pub values: Vec<DumpEnvValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpEnvService {
+    pub name: String,
+    #[serde(default)]
+    pub templates: Vec<DumpEnvTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpEnvTemplate {
+    pub repo: String,
+    pub path: String,
+    pub render_path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpEnvValue {
+    pub section: String,
+    pub key: String,
+    pub value: String,
+    pub value_type: String,
+    /// The services in the environment that use the value.
+    #[serde(default)]
+    pub services: Vec<String>,
+}
+
+/// How an import treats what's already in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Loads the dump into a database that has no environment config values
+    /// yet, such as one that has only had the migrations applied. Nothing is
+    /// deleted; rows with the same names as ones in the dump are updated.
+    Restore,
+    /// Replaces the contents of the database, whatever is in it.
+    Replace,
+    /// Adds to the contents of the database, updating rows that have the same
+    /// names as ones in the dump.
+    Merge,
+}
+
+// Splits a `Section.Key` reference. Keys can contain dots, sections can't.
+fn split_setting(setting: &str) -> Result<(&str, &str)> {
+    setting
+        .split_once('.')
+        .ok_or_else(|| anyhow!("{} is not a Section.Key setting", setting))
+}
+
+// Splits a `name:tag` image reference. Image names can contain a registry
+// port, so the tag is whatever follows the last colon.
+fn split_image(image: &str) -> Result<(&str, &str)> {
+    image
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("{} is not a name:tag image", image))
+}
+
+async fn export_services(tx: &mut Transaction<'_, Postgres>) -> Result<Vec<DumpService>> {
+    let images = sqlx::query!(
+        r#"
+            SELECT s.name AS service, ci.name AS name, ci.tag AS tag
+            FROM services_images si
+            JOIN services s ON s.id = si.service_id
+            JOIN container_images ci ON ci.id = si.image_id
+            ORDER BY s.name, ci.name, ci.tag
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut service_images: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for image in images {
+        service_images
+            .entry(image.service)
+            .or_default()
+            .push(format!("{}:{}", image.name, image.tag));
+    }
+
+    let services = sqlx::query!(
+        r#"
+            SELECT s.name AS name, r.name AS repo, s.skaffold_path AS skaffold_path
+            FROM services s
+            JOIN repos r ON r.id = s.repo_id
+            ORDER BY s.name
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(services
+        .into_iter()
+        .map(|s| DumpService {
+            images: service_images.remove(&s.name).unwrap_or_default(),
+            name: s.name,
+            repo: s.repo,
+            skaffold_path: s.skaffold_path,
+        })
+        .collect())
+}
+
+async fn export_templates(tx: &mut Transaction<'_, Postgres>) -> Result<Vec<DumpTemplate>> {
+    let references = sqlx::query!(
+        r#"
+            SELECT r.name AS repo, ct.path AS path, cs.name AS section, ctr.cfg_key AS key
+            FROM config_template_references ctr
+            JOIN config_templates ct ON ct.id = ctr.config_template_id
+            JOIN repos r ON r.id = ct.repo_id
+            JOIN config_sections cs ON cs.id = ctr.section_id
+            ORDER BY r.name, ct.path, cs.name, ctr.cfg_key
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut template_refs: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for reference in references {
+        template_refs
+            .entry((reference.repo, reference.path))
+            .or_default()
+            .push(format!("{}.{}", reference.section, reference.key));
+    }
+
+    let templates = sqlx::query!(
+        r#"
+            SELECT r.name AS repo, ct.path AS path
+            FROM config_templates ct
+            JOIN repos r ON r.id = ct.repo_id
+            ORDER BY r.name, ct.path
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(templates
+        .into_iter()
+        .map(|t| DumpTemplate {
+            references: template_refs
+                .remove(&(t.repo.clone(), t.path.clone()))
+                .unwrap_or_default(),
+            repo: t.repo,
+            path: t.path,
+        })
+        .collect())
+}
+
+async fn export_environment(
+    tx: &mut Transaction<'_, Postgres>,
+    name: String,
+    namespace: String,
+    parent: Option<String>,
+) -> Result<DumpEnvironment> {
+    let features = super::get_feature_flags(tx, &name)
+        .await?
+        .into_iter()
+        .map(|flag| (flag.name, flag.enabled))
+        .collect();
+
+    let templates = sqlx::query!(
+        r#"
+            SELECT s.name AS service, r.name AS repo, ct.path AS path, esct.path AS render_path
+            FROM environments_services_config_templates esct
+            JOIN environments_services es ON es.id = esct.environment_service_id
+            JOIN environments e ON e.id = es.environment_id
+            JOIN services s ON s.id = es.service_id
+            JOIN config_templates ct ON ct.id = esct.config_template_id
+            JOIN repos r ON r.id = ct.repo_id
+            WHERE e.name = $1
+            ORDER BY s.name, r.name, ct.path, esct.path
+        "#,
+        name
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut service_templates: BTreeMap<String, Vec<DumpEnvTemplate>> = BTreeMap::new();
+    for t in templates {
+        service_templates
+            .entry(t.service)
+            .or_default()
+            .push(DumpEnvTemplate {
+                repo: t.repo,
+                path: t.path,
+                render_path: t.render_path,
+            });
+    }
+
+    let services = super::list_services(tx, &name)
+        .await?
+        .into_iter()
+        .map(|s| s.name)
+        .collect::<std::collections::BTreeSet<String>>()
+        .into_iter()
+        .map(|service| DumpEnvService {
+            templates: service_templates.remove(&service).unwrap_or_default(),
+            name: service,
+        })
+        .collect();
+
+    let users = sqlx::query!(
+        r#"
+            SELECT ecv.config_value_id AS value_id, s.name AS service
+            FROM environments_services_config_values escv
+            JOIN environments_config_values ecv ON ecv.id = escv.environment_config_value_id
+            JOIN environments_services es ON es.id = escv.environment_service_id
+            JOIN environments e ON e.id = es.environment_id
+            JOIN services s ON s.id = es.service_id
+            WHERE e.name = $1
+            ORDER BY s.name
+        "#,
+        name
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut value_users: BTreeMap<i32, Vec<String>> = BTreeMap::new();
+    for user in users {
+        value_users
+            .entry(user.value_id)
+            .or_default()
+            .push(user.service);
+    }
+
+    let mut cfgs: Vec<ConfigurationValue> =
+        super::list_config_values(tx, Some(&name), None, None).await?;
+    cfgs.sort_by(|a, b| (&a.section, &a.key).cmp(&(&b.section, &b.key)));
+
+    let values = cfgs
+        .into_iter()
+        .map(|cfg| DumpEnvValue {
+            services: value_users.remove(&cfg.id).unwrap_or_default(),
+            section: cfg.section,
+            key: cfg.key,
+            value: cfg.value,
+            value_type: cfg.value_type,
+        })
+        .collect();
+
+    Ok(DumpEnvironment {
+        name,
+        namespace,
+        parent,
+        features,
+        services,
+        values,
+    })
+}
+
+/// Reads the contents of the database into a dump.
+///
+/// # Example
+/// ```ignore
+/// let mut tx = pool.begin().await?;
+/// let dump = dump::export(&mut tx, migrate::current_version(&pool).await?.map(|v| v.version)).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn export(
+    tx: &mut Transaction<'_, Postgres>,
+    schema_version: Option<i64>,
+) -> Result<Dump> {
+    let value_types = sqlx::query!("SELECT name FROM config_value_types ORDER BY name")
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|r| r.name)
+        .collect();
+
+    let sections = sqlx::query!("SELECT name FROM config_sections ORDER BY name")
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|r| r.name)
+        .collect();
+
+    let feature_flags = super::list_feature_flags(tx).await?;
+
+    let repos = sqlx::query_as!(
+        DumpRepo,
+        "SELECT name, url, revision FROM repos ORDER BY name"
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let images = sqlx::query_as!(
+        DumpImage,
+        r#"
+            SELECT ci.name AS name, ci.tag AS tag, r.name AS repo, ci.dockerfile AS dockerfile, ci.digest AS digest
+            FROM container_images ci
+            JOIN repos r ON r.id = ci.repo_id
+            ORDER BY ci.name, ci.tag
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let services = export_services(tx).await?;
+    let templates = export_templates(tx).await?;
+
+    let defaults = sqlx::query_as!(
+        DumpDefault,
+        r#"
+            SELECT
+                cs.name AS section,
+                cd.cfg_key AS key,
+                cd.cfg_value AS value,
+                cvt.name AS value_type,
+                cd.sensitive AS sensitive
+            FROM config_defaults cd
+            JOIN config_sections cs ON cs.id = cd.section_id
+            JOIN config_value_types cvt ON cvt.id = cd.value_type_id
+            ORDER BY cs.name, cd.cfg_key
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let envs = sqlx::query!(
+        r#"
+            SELECT e.name AS name, e.namespace AS namespace, p.name AS "parent?"
+            FROM environments e
+            LEFT JOIN environments p ON p.id = e.parent_id
+            ORDER BY e.name
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut environments = Vec::new();
+    for env in envs {
+        environments.push(export_environment(tx, env.name, env.namespace, env.parent).await?);
+    }
+
+    Ok(Dump {
+        schema_version,
+        value_types,
+        sections,
+        feature_flags,
+        repos,
+        images,
+        services,
+        templates,
+        defaults,
+        environments,
+    })
+}
+
+// Empties the tables that a dump covers.
+async fn clear(tx: &mut Transaction<'_, Postgres>) -> Result<()> {
+    sqlx::query!(
+        r#"
+            TRUNCATE
+                environments_services_config_values,
+                environments_services_config_defaults,
+                environments_services_config_templates,
+                environments_services,
+                environments_config_values,
+                environments_feature_flags,
+                environments,
+                feature_flags,
+                config_values,
+                config_defaults,
+                config_template_references,
+                config_templates,
+                config_sections,
+                config_value_types,
+                services_images,
+                services,
+                container_images,
+                repos
+            RESTART IDENTITY CASCADE
+        "#
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn import_service(tx: &mut Transaction<'_, Postgres>, service: &DumpService) -> Result<()> {
+    let existing = sqlx::query!(
+        "SELECT id FROM services WHERE name = $1 ORDER BY id LIMIT 1",
+        service.name
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let service_id = match existing {
+        Some(existing) => {
+            sqlx::query!(
+                r#"
+                    UPDATE services
+                    SET repo_id = (SELECT id FROM repos WHERE name = $2), skaffold_path = $3
+                    WHERE id = $1
+                "#,
+                existing.id,
+                service.repo,
+                service.skaffold_path
+            )
+            .execute(&mut **tx)
+            .await?;
+            existing.id
+        }
+        None => {
+            sqlx::query!(
+                r#"
+                    INSERT INTO services (repo_id, name, skaffold_path)
+                    VALUES ((SELECT id FROM repos WHERE name = $1), $2, $3)
+                    RETURNING id
+                "#,
+                service.repo,
+                service.name,
+                service.skaffold_path
+            )
+            .fetch_one(&mut **tx)
+            .await?
+            .id
+        }
+    };
+
+    for image in &service.images {
+        let (name, tag) = split_image(image)?;
+        let image_id = sqlx::query!(
+            "SELECT id FROM container_images WHERE name = $1 AND tag = $2",
+            name,
+            tag
+        )
+        .fetch_optional(&mut **tx)
+        .await?
+        .ok_or_else(|| anyhow!("service {} uses unknown image {}", service.name, image))?
+        .id;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO services_images (service_id, image_id)
+                SELECT $1, $2
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM services_images WHERE service_id = $1 AND image_id = $2
+                )
+            "#,
+            service_id,
+            image_id
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+// Returns the ID of a template, adding it if it doesn't exist.
+async fn template_id(tx: &mut Transaction<'_, Postgres>, repo: &str, path: &str) -> Result<i32> {
+    let existing = sqlx::query!(
+        r#"
+            SELECT ct.id
+            FROM config_templates ct
+            JOIN repos r ON r.id = ct.repo_id
+            WHERE r.name = $1 AND ct.path = $2
+            ORDER BY ct.id
+            LIMIT 1
+        "#,
+        repo,
+        path
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    if let Some(existing) = existing {
+        return Ok(existing.id);
+    }
+
+    Ok(sqlx::query!(
+        r#"
+            INSERT INTO config_templates (repo_id, path)
+            VALUES ((SELECT id FROM repos WHERE name = $1), $2)
+            RETURNING id
+        "#,
+        repo,
+        path
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .with_context(|| format!("template {} is in unknown repository {}", path, repo))?
+    .id)
+}
+
+async fn import_template(
+    tx: &mut Transaction<'_, Postgres>,
+    template: &DumpTemplate,
+) -> Result<()> {
+    let id = template_id(tx, &template.repo, &template.path).await?;
+
+    let references = template
+        .references
+        .iter()
+        .map(|r| split_setting(r).map(|(s, k)| (s.to_string(), k.to_string())))
+        .collect::<Result<Vec<_>>>()?;
+    super::set_template_references(tx, id, &references).await
+}
+
+async fn import_default(tx: &mut Transaction<'_, Postgres>, default: &DumpDefault) -> Result<()> {
+    let updated = sqlx::query!(
+        r#"
+            UPDATE config_defaults
+            SET
+                cfg_value = $3,
+                value_type_id = (SELECT id FROM config_value_types WHERE name = $4),
+                sensitive = $5
+            WHERE section_id = (SELECT id FROM config_sections WHERE name = $1)
+            AND cfg_key = $2
+        "#,
+        default.section,
+        default.key,
+        default.value,
+        default.value_type,
+        default.sensitive
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        sqlx::query!(
+            r#"
+                INSERT INTO config_defaults (section_id, cfg_key, cfg_value, value_type_id, sensitive)
+                VALUES (
+                    (SELECT id FROM config_sections WHERE name = $1),
+                    $2,
+                    $3,
+                    (SELECT id FROM config_value_types WHERE name = $4),
+                    $5
+                )
+            "#,
+            default.section,
+            default.key,
+            default.value,
+            default.value_type,
+            default.sensitive
+        )
+        .execute(&mut **tx)
+        .await
+        .with_context(|| format!("failed to add the default for {}.{}", default.section, default.key))?;
+    }
+
+    Ok(())
+}
+
+async fn import_environment(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &DumpEnvironment,
+) -> Result<()> {
+    let env_id = sqlx::query!(
+        r#"
+            INSERT INTO environments (name, namespace) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET namespace = $2
+            RETURNING id
+        "#,
+        env.name,
+        env.namespace
+    )
+    .fetch_one(&mut **tx)
+    .await?
+    .id;
+
+    for (flag, enabled) in &env.features {
+        let mut conn = Connection::Postgres(tx);
+        if !backend::set_feature_flag(&mut conn, &env.name, flag, *enabled).await? {
+            return Err(anyhow!("{} is not a feature flag", flag));
+        }
+    }
+
+    for service in &env.services {
+        sqlx::query!(
+            r#"
+                INSERT INTO environments_services (environment_id, service_id)
+                VALUES ($1, (SELECT id FROM services WHERE name = $2 ORDER BY id LIMIT 1))
+                ON CONFLICT DO NOTHING
+            "#,
+            env_id,
+            service.name
+        )
+        .execute(&mut **tx)
+        .await
+        .with_context(|| format!("{} uses unknown service {}", env.name, service.name))?;
+
+        for template in &service.templates {
+            let template_id = template_id(tx, &template.repo, &template.path).await?;
+            sqlx::query!(
+                r#"
+                    INSERT INTO environments_services_config_templates
+                        (environment_service_id, config_template_id, path)
+                    SELECT es.id, $3, $4
+                    FROM environments_services es
+                    WHERE es.environment_id = $1
+                    AND es.service_id = (SELECT id FROM services WHERE name = $2 ORDER BY id LIMIT 1)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM environments_services_config_templates
+                        WHERE environment_service_id = es.id
+                        AND config_template_id = $3
+                        AND path = $4
+                    )
+                "#,
+                env_id,
+                service.name,
+                template_id,
+                template.render_path
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+    }
+
+    for value in &env.values {
+        if super::has_config_value(tx, &env.name, &value.section, &value.key).await? {
+            super::update_env_cfg_value(
+                tx,
+                &env.name,
+                &value.section,
+                &value.key,
+                &value.value,
+                &value.value_type,
+            )
+            .await?;
+        } else {
+            let cfg_id = super::set_config_value(
+                tx,
+                &value.section,
+                &value.key,
+                &value.value,
+                &value.value_type,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to add {}.{} to {}; values need a default",
+                    value.section, value.key, env.name
+                )
+            })?;
+            super::add_env_cfg_value(tx, env_id, cfg_id).await?;
+        }
+
+        for service in &value.services {
+            sqlx::query!(
+                r#"
+                    INSERT INTO environments_services_config_values
+                        (environment_service_id, environment_config_value_id)
+                    SELECT es.id, ecv.id
+                    FROM environments_services es
+                    JOIN services s ON s.id = es.service_id
+                    JOIN environments_config_values ecv ON ecv.environment_id = es.environment_id
+                    JOIN config_values cv ON cv.id = ecv.config_value_id
+                    JOIN config_sections cs ON cs.id = cv.section_id
+                    WHERE es.environment_id = $1
+                    AND s.name = $2
+                    AND cs.name = $3
+                    AND cv.cfg_key = $4
+                    ON CONFLICT DO NOTHING
+                "#,
+                env_id,
+                service,
+                value.section,
+                value.key
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads a dump into the database. Rows are matched to existing ones by name,
+/// so the same dump can be merged more than once. The parents of the
+/// environments aren't checked for cycles.
+///
+/// # Example
+/// ```ignore
+/// let mut tx = pool.begin().await?;
+/// dump::import(&mut tx, &dump, ImportMode::Merge).await?;
+/// tx.commit().await?;
+/// ```
+pub async fn import(
+    tx: &mut Transaction<'_, Postgres>,
+    dump: &Dump,
+    mode: ImportMode,
+) -> Result<()> {
+    if let (Some(version), Some(latest)) = (dump.schema_version, migrate::latest_version()) {
+        if version > latest {
+            return Err(anyhow!(
+                "the dump is from schema version {}, which is newer than this binary's version {}",
+                version,
+                latest
+            ));
+        }
+    }
+
+    if mode == ImportMode::Restore {
+        let values = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM environments_config_values"#)
+            .fetch_one(&mut **tx)
+            .await?
+            .count;
+        if values > 0 {
+            return Err(anyhow!(
+                "the database already has config values for its environments. Use --merge to add the dump to it, or --replace to overwrite it"
+            ));
+        }
+    }
+
+    if mode == ImportMode::Replace {
+        clear(tx).await?;
+    }
+
+    for value_type in &dump.value_types {
+        sqlx::query!(
+            "INSERT INTO config_value_types (name) VALUES ($1) ON CONFLICT (name) DO NOTHING",
+            value_type
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    for section in &dump.sections {
+        sqlx::query!(
+            "INSERT INTO config_sections (name) VALUES ($1) ON CONFLICT (name) DO NOTHING",
+            section
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    for flag in &dump.feature_flags {
+        super::upsert_feature_flag(tx, &flag.name, &flag.section, &flag.description).await?;
+    }
+
+    for repo in &dump.repos {
+        sqlx::query!(
+            r#"
+                INSERT INTO repos (name, url, revision) VALUES ($1, $2, $3)
+                ON CONFLICT (name) DO UPDATE SET url = $2, revision = $3
+            "#,
+            repo.name,
+            repo.url,
+            repo.revision
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    for image in &dump.images {
+        sqlx::query!(
+            r#"
+                INSERT INTO container_images (repo_id, dockerfile, name, tag, digest)
+                VALUES ((SELECT id FROM repos WHERE name = $1), $2, $3, $4, $5)
+                ON CONFLICT (name, tag) DO UPDATE
+                SET repo_id = EXCLUDED.repo_id, dockerfile = $2, digest = $5
+            "#,
+            image.repo,
+            image.dockerfile,
+            image.name,
+            image.tag,
+            image.digest
+        )
+        .execute(&mut **tx)
+        .await
+        .with_context(|| format!("failed to add image {}:{}", image.name, image.tag))?;
+    }
+
+    for service in &dump.services {
+        import_service(tx, service)
+            .await
+            .with_context(|| format!("failed to add service {}", service.name))?;
+    }
+
+    for template in &dump.templates {
+        import_template(tx, template).await?;
+    }
+
+    for default in &dump.defaults {
+        import_default(tx, default).await?;
+    }
+
+    for env in &dump.environments {
+        import_environment(tx, env)
+            .await
+            .with_context(|| format!("failed to import environment {}", env.name))?;
+    }
+
+    // Parents are set once every environment exists.
+    for env in &dump.environments {
+        if let Some(parent) = &env.parent {
+            super::get_env_id(tx, parent)
+                .await
+                .with_context(|| format!("{} has unknown parent {}", env.name, parent))?;
+            super::set_env_parent(tx, &env.name, Some(parent)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split_setting("DE.Coge.BaseURI").unwrap(),
+            ("DE", "Coge.BaseURI")
+        );
+        assert!(split_setting("DE").is_err());
+        assert_eq!(
+            split_image("harbor.cyverse.org:443/de/apps:latest").unwrap(),
+            ("harbor.cyverse.org:443/de/apps", "latest")
+        );
+        assert!(split_image("apps").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dump = Dump {
+            schema_version: Some(29),
+            sections: vec!["DE".to_string(), "QA".to_string()],
+            feature_flags: vec![FeatureFlag {
+                name: "qa".to_string(),
+                section: "QA".to_string(),
+                description: "The accounts used by the QA tests.".to_string(),
+            }],
+            environments: vec![DumpEnvironment {
+                name: "de".to_string(),
+                namespace: "prod".to_string(),
+                parent: None,
+                features: [("qa".to_string(), true)].into_iter().collect(),
+                services: Vec::new(),
+                values: vec![DumpEnvValue {
+                    section: "DE".to_string(),
+                    key: "BaseURI".to_string(),
+                    value: "https://de.example.org".to_string(),
+                    value_type: "url".to_string(),
+                    services: vec!["apps".to_string()],
+                }],
+            }],
+            ..Default::default()
+        };
+
+        let yaml = serde_yaml::to_string(&dump).unwrap();
+        assert_eq!(serde_yaml::from_str::<Dump>(&yaml).unwrap(), dump);
+    }
+}
@@ -0,0 +1,337 @@
+//! # Schema migrations
+//!
+//! The migrations in `db/migrations` are embedded in the binary and applied by
+//! `mgmt db migrate`. Applied versions are tracked the same way golang-migrate
+//! tracks them: a `schema_migrations` table holding a single row with the
+//! current version and whether the last migration failed part way through
+//! (`dirty`). A database can be moved between `mgmt` and the `migrate` CLI.
+//!
+//! Like golang-migrate, each migration file is run as-is, outside of a
+//! transaction, so a failure leaves the database dirty at that version until
+//! it's fixed by hand and the version is forced.
+use anyhow::{anyhow, Context, Result};
+use sqlx::migrate::{Migration, MigrationType, Migrator};
+use sqlx::{Executor, Pool, Postgres};
+
+/// The version golang-migrate records when a failed down migration leaves no
+/// migrations applied.
+const NIL_VERSION: i64 = -1;
+
+/// The migrations in `db/migrations`, embedded at build time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("db/migrations");
+
+/// The state of a single migration in a database.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, tabled::Tabled)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+/// The version recorded in `schema_migrations`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct SchemaVersion {
+    pub version: i64,
+    pub dirty: bool,
+}
+
+// Returns the embedded migrations of one type, ordered by version.
+fn migrations(migration_type: MigrationType) -> Vec<&'static Migration> {
+    let mut found: Vec<&Migration> = MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type == migration_type)
+        .collect();
+    found.sort_by_key(|m| m.version);
+    found
+}
+
+/// Returns the versions of the embedded migrations, oldest first.
+pub fn versions() -> Vec<i64> {
+    migrations(MigrationType::ReversibleUp)
+        .iter()
+        .map(|m| m.version)
+        .collect()
+}
+
+/// Returns the schema version this binary expects, which is the version of
+/// the newest embedded migration.
+pub fn latest_version() -> Option<i64> {
+    versions().last().copied()
+}
+
+/// Returns the versions to apply, oldest first, to move up from the current
+/// version. All of the pending versions are returned if `steps` is None.
+pub fn plan_up(available: &[i64], current: Option<i64>, steps: Option<usize>) -> Vec<i64> {
+    available
+        .iter()
+        .filter(|v| current.is_none_or(|c| **v > c))
+        .take(steps.unwrap_or(usize::MAX))
+        .copied()
+        .collect()
+}
+
+/// Returns the versions to revert, newest first, to move down from the
+/// current version, each paired with the version the database is at once it
+/// has been reverted. None means no migrations are applied.
+pub fn plan_down(
+    available: &[i64],
+    current: Option<i64>,
+    steps: Option<usize>,
+) -> Result<Vec<(i64, Option<i64>)>> {
+    let current = match current {
+        Some(current) => current,
+        None => return Ok(Vec::new()),
+    };
+
+    let position = available
+        .iter()
+        .position(|v| *v == current)
+        .ok_or_else(|| {
+            anyhow!(
+                "the database is at version {}, which this binary doesn't have a migration for",
+                current
+            )
+        })?;
+
+    Ok(available[..=position]
+        .iter()
+        .enumerate()
+        .rev()
+        .take(steps.unwrap_or(usize::MAX))
+        .map(|(i, v)| (*v, i.checked_sub(1).map(|prev| available[prev])))
+        .collect())
+}
+
+/// Creates the `schema_migrations` table if it doesn't exist.
+pub async fn ensure_table(pool: &Pool<Postgres>) -> Result<()> {
+    pool.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (version bigint NOT NULL PRIMARY KEY, dirty boolean NOT NULL)",
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Returns whether the database has a `schema_migrations` table.
+pub async fn has_table(pool: &Pool<Postgres>) -> Result<bool> {
+    let (exists,): (bool,) = sqlx::query_as("SELECT to_regclass('schema_migrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(exists)
+}
+
+/// Returns the version recorded in `schema_migrations`, or None if no
+/// migrations have been applied.
+///
+/// # Example
+/// ```ignore
+/// if let Some(v) = migrate::current_version(&pool).await? {
+///     println!("{}{}", v.version, if v.dirty { " (dirty)" } else { "" });
+/// }
+/// ```
+pub async fn current_version(pool: &Pool<Postgres>) -> Result<Option<SchemaVersion>> {
+    if !has_table(pool).await? {
+        return Ok(None);
+    }
+
+    let row: Option<(i64, bool)> =
+        sqlx::query_as("SELECT version, dirty FROM schema_migrations LIMIT 1")
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row.map(|(version, dirty)| SchemaVersion { version, dirty }))
+}
+
+// Replaces the row in schema_migrations. golang-migrate keeps at most one row,
+// and none at all when every migration has been reverted.
+async fn set_version(pool: &Pool<Postgres>, version: Option<i64>, dirty: bool) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM schema_migrations")
+        .execute(&mut *tx)
+        .await?;
+    if let Some(version) = version {
+        sqlx::query("INSERT INTO schema_migrations (version, dirty) VALUES ($1, $2)")
+            .bind(version)
+            .bind(dirty)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// Returns the current version, refusing to go on if it's dirty.
+async fn clean_version(pool: &Pool<Postgres>) -> Result<Option<i64>> {
+    match current_version(pool).await? {
+        Some(SchemaVersion {
+            version,
+            dirty: true,
+        }) => Err(anyhow!(
+            "the database is dirty at version {}. Fix the schema by hand, then use `mgmt db migrate force <version>`",
+            version
+        )),
+        current => Ok(current.map(|v| v.version)),
+    }
+}
+
+// Finds the embedded migration of a type for a version.
+fn find(migration_type: MigrationType, version: i64) -> Result<&'static Migration> {
+    migrations(migration_type)
+        .into_iter()
+        .find(|m| m.version == version)
+        .ok_or_else(|| anyhow!("no migration found for version {}", version))
+}
+
+/// Applies pending migrations, oldest first. All of them are applied if
+/// `steps` is None. Returns the versions that were applied.
+///
+/// # Example
+/// ```ignore
+/// let applied = migrate::up(&pool, None).await?;
+/// ```
+pub async fn up(pool: &Pool<Postgres>, steps: Option<usize>) -> Result<Vec<i64>> {
+    ensure_table(pool).await?;
+    let current = clean_version(pool).await?;
+
+    let planned = plan_up(&versions(), current, steps);
+    for version in &planned {
+        let migration = find(MigrationType::ReversibleUp, *version)?;
+
+        set_version(pool, Some(*version), true).await?;
+        pool.execute(&*migration.sql)
+            .await
+            .with_context(|| format!("migration {} failed", version))?;
+        set_version(pool, Some(*version), false).await?;
+
+        println!("Applied {} {}", version, migration.description);
+    }
+
+    Ok(planned)
+}
+
+/// Reverts applied migrations, newest first. All of them are reverted if
+/// `steps` is None. Returns the versions that were reverted.
+///
+/// # Example
+/// ```ignore
+/// let reverted = migrate::down(&pool, Some(1)).await?;
+/// ```
+pub async fn down(pool: &Pool<Postgres>, steps: Option<usize>) -> Result<Vec<i64>> {
+    ensure_table(pool).await?;
+    let current = clean_version(pool).await?;
+
+    let planned = plan_down(&versions(), current, steps)?;
+    for (version, previous) in &planned {
+        let migration = find(MigrationType::ReversibleDown, *version)?;
+
+        set_version(pool, Some(previous.unwrap_or(NIL_VERSION)), true).await?;
+        pool.execute(&*migration.sql)
+            .await
+            .with_context(|| format!("reverting migration {} failed", version))?;
+        set_version(pool, *previous, false).await?;
+
+        println!("Reverted {} {}", version, migration.description);
+    }
+
+    Ok(planned.into_iter().map(|(version, _)| version).collect())
+}
+
+/// Records a version as applied without running any migrations and clears
+/// the dirty flag. Used to recover from a failed migration, or to start
+/// tracking a database whose schema was set up some other way.
+pub async fn force(pool: &Pool<Postgres>, version: i64) -> Result<()> {
+    if !versions().contains(&version) {
+        return Err(anyhow!("no migration found for version {}", version));
+    }
+
+    ensure_table(pool).await?;
+    set_version(pool, Some(version), false).await
+}
+
+/// Returns the state of each embedded migration in the database.
+pub async fn status(pool: &Pool<Postgres>) -> Result<Vec<MigrationStatus>> {
+    let current = current_version(pool).await?;
+
+    Ok(migrations(MigrationType::ReversibleUp)
+        .into_iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: current
+                .is_some_and(|c| m.version < c.version || (m.version == c.version && !c.dirty)),
+        })
+        .collect())
+}
+
+/// Fails unless the database schema is at least as new as the newest
+/// embedded migration. Run before any command that uses the database.
+pub async fn check(pool: &Pool<Postgres>) -> Result<()> {
+    let latest = match latest_version() {
+        Some(latest) => latest,
+        None => return Ok(()),
+    };
+
+    match current_version(pool).await? {
+        None => Err(anyhow!(
+            "the database has no schema version; this binary expects version {}. Use `mgmt db migrate up` to set up the schema, or `mgmt db migrate force <version>` if it was set up without tracking",
+            latest
+        )),
+        Some(SchemaVersion {
+            version,
+            dirty: true,
+        }) => Err(anyhow!(
+            "the database is dirty at version {}. Fix the schema by hand, then use `mgmt db migrate force <version>`",
+            version
+        )),
+        Some(SchemaVersion { version, .. }) if version < latest => Err(anyhow!(
+            "the database schema is at version {}, but this binary expects version {}. Use `mgmt db migrate up` to update it",
+            version,
+            latest
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_embedded() {
+        let up = versions();
+        let down: Vec<i64> = migrations(MigrationType::ReversibleDown)
+            .iter()
+            .map(|m| m.version)
+            .collect();
+
+        assert!(!up.is_empty());
+        assert_eq!(up, down);
+        assert_eq!(latest_version(), up.last().copied());
+    }
+
+    #[test]
+    fn test_plan_up() {
+        let available = [1, 2, 3, 4];
+        assert_eq!(plan_up(&available, None, None), vec![1, 2, 3, 4]);
+        assert_eq!(plan_up(&available, Some(2), None), vec![3, 4]);
+        assert_eq!(plan_up(&available, Some(2), Some(1)), vec![3]);
+        assert!(plan_up(&available, Some(4), None).is_empty());
+    }
+
+    #[test]
+    fn test_plan_down() {
+        let available = [1, 2, 3, 4];
+        assert_eq!(
+            plan_down(&available, Some(3), Some(1)).unwrap(),
+            vec![(3, Some(2))]
+        );
+        assert_eq!(
+            plan_down(&available, Some(2), None).unwrap(),
+            vec![(2, Some(1)), (1, None)]
+        );
+        assert!(plan_down(&available, None, None).unwrap().is_empty());
+        assert!(plan_down(&available, Some(7), None).is_err());
+    }
+}
@@ -2,13 +2,13 @@
 //!
 //! This module contains the functions that can be reused across the mgmt
 //! commands to deploy the Discovery Environment.
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use sqlx::{Pool, Postgres, Transaction};
 use std::path::PathBuf;
 use std::process::Command;
 
 use crate::handlers::templates;
-use crate::{configs, db, ops};
+use crate::{configs, db, ops, settings};
 
 #[derive(Debug, Clone)]
 pub struct DeploymentOptions {
@@ -47,6 +47,31 @@ pub struct DeploymentOptions {
 
     // List of services to deploy before the rest.
     pub pre_deploy: Vec<String>,
+
+    // Whether to skip validating the config values before deploying.
+    pub no_validate: bool,
+
+    // The Kubernetes context kubectl and skaffold use, if not the current one.
+    pub kube_context: Option<String>,
+}
+
+/// Options for syncing the rendered configs for an environment.
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    // The directory the template paths in the database are relative to.
+    pub templates_dir: PathBuf,
+
+    // The base directory for the configurations.
+    pub configdir: PathBuf,
+
+    // Whether to only report what would be changed.
+    pub dry_run: bool,
+
+    // Whether to skip restarting the affected services.
+    pub no_restart: bool,
+
+    // The Kubernetes context kubectl uses, if not the current one.
+    pub kube_context: Option<String>,
 }
 
 async fn get_services(
@@ -63,7 +88,12 @@ async fn get_namespace(tx: &mut Transaction<'_, Postgres>, env: &str) -> Result<
     Ok(db::get_namespace(tx, &env).await?)
 }
 
-pub fn deploy_service(releases_dir: &PathBuf, ns: &str, svc: &db::Service) -> Result<bool> {
+pub fn deploy_service(
+    releases_dir: &PathBuf,
+    ns: &str,
+    svc: &db::Service,
+    kube_context: Option<&str>,
+) -> Result<bool> {
     let svc_json = releases_dir
         .join("builds")
         .join(format!("{}.json", svc.name));
@@ -79,10 +109,118 @@ pub fn deploy_service(releases_dir: &PathBuf, ns: &str, svc: &db::Service) -> Re
                 .context("couldn't get service json path")?,
             "--force",
         ])
+        .args(settings::skaffold_context_args(kube_context))
+        .status()?
+        .success())
+}
+
+pub fn restart_service(ns: &str, svc_name: &str, kube_context: Option<&str>) -> Result<bool> {
+    Ok(Command::new("kubectl")
+        .args(settings::kubectl_context_args(kube_context))
+        .args([
+            "-n",
+            ns,
+            "rollout",
+            "restart",
+            &format!("deployment/{}", svc_name),
+        ])
         .status()?
         .success())
 }
 
+/// Re-renders the config templates for an environment whose output has
+/// changed, reloads the configs, and restarts only the services that use the
+/// changed templates. If `candidates` is given, only those templates are
+/// checked, along with any template that hasn't been rendered yet. Returns the
+/// names of the services that were affected.
+pub async fn sync(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    candidates: Option<&[String]>,
+    opts: &SyncOptions,
+) -> Result<Vec<String>> {
+    let namespace = get_namespace(tx, env).await?;
+    let env_configdir = opts.configdir.join(env);
+    let env_templates = db::list_env_service_templates(tx, env).await?;
+
+    let mut template_paths: Vec<String> = Vec::new();
+    for t in &env_templates {
+        let rendered = templates::db_output_file(env, &env_configdir, &t.template_path)?.exists();
+        let is_candidate = candidates.is_none_or(|c| c.contains(&t.template_path));
+        if (is_candidate || !rendered) && !template_paths.contains(&t.template_path) {
+            template_paths.push(t.template_path.clone());
+        }
+    }
+
+    let changed = templates::changed_db_templates(
+        tx,
+        env,
+        &opts.templates_dir,
+        &env_configdir,
+        &template_paths,
+    )
+    .await?;
+
+    if changed.is_empty() {
+        println!("The configs for {} are up to date.", env);
+        return Ok(vec![]);
+    }
+
+    let mut services: Vec<String> = env_templates
+        .iter()
+        .filter(|t| changed.contains(&t.template_path))
+        .map(|t| t.service_name.clone())
+        .collect();
+    services.dedup();
+
+    println!(
+        "templates with changes:{}",
+        changed
+            .iter()
+            .fold(String::new(), |acc, t| format!("{}\n\t{}", acc, t))
+    );
+    println!(
+        "services affected:{}",
+        services
+            .iter()
+            .fold(String::new(), |acc, svc| format!("{}\n\t{}", acc, svc))
+    );
+
+    if opts.dry_run {
+        println!("Dry run, nothing was changed.");
+        return Ok(services);
+    }
+
+    templates::render_db_templates(tx, env, &opts.templates_dir, &env_configdir, &changed).await?;
+
+    let kube_context = opts.kube_context.as_deref();
+    if !configs::load_configs(&namespace, "service-configs", &env_configdir, kube_context)? {
+        return Err(anyhow!("failed to load the configs for {}", env));
+    }
+
+    if !opts.no_restart {
+        for svc in &services {
+            if !restart_service(&namespace, svc, kube_context)? {
+                return Err(anyhow!("failed to restart {}", svc));
+            }
+        }
+    }
+
+    println!(
+        "Rendered {} template(s) and {} {} service(s) in {}.",
+        changed.len(),
+        if opts.no_restart {
+            "skipped restarting"
+        } else {
+            "restarted"
+        },
+        services.len(),
+        env
+    );
+
+    Ok(services)
+}
+
 pub async fn deploy(
     pool: &Pool<Postgres>,
     env: &str,
@@ -116,6 +254,12 @@ pub async fn deploy(
     let namespace = get_namespace(&mut tx, &env).await?;
     println!("namespace: {}", namespace);
 
+    // Make sure the config values are usable before touching the cluster.
+    if !opts.no_validate {
+        let issues = ops::validate_env(&mut tx, env).await?;
+        ops::report_issues(env, &issues)?;
+    }
+
     // Get all of the services in the environments.
     let all_services = get_services(&mut tx, &env, &opts.skips).await?;
 
@@ -172,34 +316,49 @@ pub async fn deploy(
         // Render the secrets templates
         templates::render_template_dir_from_db(
             &mut tx,
-            &release_repo_dir.join("templates").join("secrets").join("*"),
+            &release_repo_dir.join("templates").join("secrets"),
             &env,
+            None,
+            false,
             &secrets_dir,
         )
         .await?;
 
-        // Render the configuration templates
-        templates::render_db(&mut tx, &env, &release_repo_dir, &env_configdir).await?;
+        // Render the configuration templates into the layout the
+        // service-configs secret is loaded from.
+        let template_paths = db::list_templates(&mut tx, env).await?;
+        templates::render_db_templates(
+            &mut tx,
+            env,
+            release_repo_dir,
+            &env_configdir,
+            &template_paths,
+        )
+        .await?;
     }
 
+    let kube_context = opts.kube_context.as_deref();
+
     // Load the configs.
     if !opts.no_load_configs {
-        configs::load_configs(&namespace, "service-configs", &env_configdir)?;
+        configs::load_configs(&namespace, "service-configs", &env_configdir, kube_context)?;
     }
 
     // Load the secrets.
     if !opts.no_load_secrets {
-        configs::load_secrets(&namespace, &secrets_dir)?;
+        configs::load_secrets(&namespace, &secrets_dir, kube_context)?;
     }
 
     // Deploy the services.
     if !opts.no_deploy {
         pre_deploy_services.iter().for_each(|svc| {
-            deploy_service(&release_repo_dir, &namespace, svc).expect("failed to deploy service");
+            deploy_service(&release_repo_dir, &namespace, svc, kube_context)
+                .expect("failed to deploy service");
         });
 
         services.iter().for_each(|svc| {
-            deploy_service(&release_repo_dir, &namespace, svc).expect("failed to deploy service");
+            deploy_service(&release_repo_dir, &namespace, svc, kube_context)
+                .expect("failed to deploy service");
         });
     }
 
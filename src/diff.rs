@@ -0,0 +1,210 @@
+//! # Diffs
+//!
+//! Produces unified diffs of text files, in the same format as `diff -u`.
+//! Lines are matched up with a longest common subsequence, after the lines the
+//! two files start and end with are set aside, so config files that only
+//! differ in a few places are cheap to compare.
+
+// A single line in the edit script that turns the old text into the new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+// Returns the edit script that turns the old lines into the new lines.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Op<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // old_mid[i..] and new_mid[j..].
+    let (n, m) = (old_mid.len(), new_mid.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_mid[i] == new_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops: Vec<Op> = old[..prefix].iter().map(|l| Op::Equal(l)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_mid[i] == new_mid[j] {
+            ops.push(Op::Equal(old_mid[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(Op::Delete(old_mid[i]));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(new_mid[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old_mid[i..].iter().map(|l| Op::Delete(l)));
+    ops.extend(new_mid[j..].iter().map(|l| Op::Insert(l)));
+    ops.extend(old[old.len() - suffix..].iter().map(|l| Op::Equal(l)));
+
+    ops
+}
+
+// Formats the line range of a hunk the way `diff -u` does.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Returns the unified diff between two texts as a list of lines, starting
+/// with the `---` and `+++` headers, or None if the texts have the same lines.
+/// `context` is the number of unchanged lines shown around each change.
+///
+/// # Example
+/// ```ignore
+/// if let Some(lines) = diff::unified("cluster/apps.properties", "rendered/apps.properties", &old, &new, 3) {
+///     println!("{}", lines.join("\n"));
+/// }
+/// ```
+pub fn unified(
+    old_label: &str,
+    new_label: &str,
+    old: &str,
+    new: &str,
+    context: usize,
+) -> Option<Vec<String>> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return None;
+    }
+
+    // The old and new line numbers before each op.
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for op in &ops {
+        positions.push((o, n));
+        match op {
+            Op::Equal(_) => {
+                o += 1;
+                n += 1;
+            }
+            Op::Delete(_) => o += 1,
+            Op::Insert(_) => n += 1,
+        }
+    }
+    positions.push((o, n));
+
+    let mut lines = vec![format!("--- {}", old_label), format!("+++ {}", new_label)];
+    let mut k = 0;
+    while k < changes.len() {
+        let start = changes[k].saturating_sub(context);
+        let mut end = changes[k] + 1;
+        while k + 1 < changes.len() && changes[k + 1] - end <= 2 * context {
+            k += 1;
+            end = changes[k] + 1;
+        }
+        let end = (end + context).min(ops.len());
+
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        lines.push(format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+        lines.extend(ops[start..end].iter().map(|op| match op {
+            Op::Equal(l) => format!(" {}", l),
+            Op::Delete(l) => format!("-{}", l),
+            Op::Insert(l) => format!("+{}", l),
+        }));
+
+        k += 1;
+    }
+
+    Some(lines)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_same() {
+        assert!(unified("a", "b", "x\ny\n", "x\ny\n", 3).is_none());
+        assert!(unified("a", "b", "", "", 3).is_none());
+    }
+
+    #[test]
+    fn test_unified() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        let lines = unified("old", "new", old, new, 1).unwrap();
+
+        assert_eq!(
+            lines,
+            vec![
+                "--- old",
+                "+++ new",
+                "@@ -1,3 +1,3 @@",
+                " a",
+                "-b",
+                "+B",
+                " c",
+                "@@ -10 +10,2 @@",
+                " j",
+                "+k",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_hunks() {
+        let lines = unified("old", "new", "a\nb\nc\nd\n", "A\nb\nc\nD\n", 1).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "--- old",
+                "+++ new",
+                "@@ -1,4 +1,4 @@",
+                "-a",
+                "+A",
+                " b",
+                " c",
+                "-d",
+                "+D"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_new_file() {
+        let lines = unified("old", "new", "", "a\nb\n", 3).unwrap();
+        assert_eq!(
+            lines,
+            vec!["--- old", "+++ new", "@@ -0,0 +1,2 @@", "+a", "+b"]
+        );
+    }
+}
@@ -1,9 +1,10 @@
 //! # Git
 //!
 //! This module provides functions for interacting with git.
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 pub fn add(repodir: &PathBuf, path: &str) -> Result<bool> {
@@ -179,3 +180,164 @@ pub fn check_in_changes(project_path: &str) -> Result<bool> {
 
     Ok(true)
 }
+
+// Runs a git command in a directory and returns what it printed, failing if
+// the command does.
+fn output(repodir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repodir)
+        .output()
+        .with_context(|| format!("git {} failed", args.join(" ")))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// A temporary checkout of a single revision of a repository. The directory
+/// is removed when the checkout is dropped.
+pub struct RevisionCheckout {
+    /// The directory the revision is checked out in.
+    pub path: PathBuf,
+
+    /// The full hash of the commit that's checked out.
+    pub commit: String,
+}
+
+impl RevisionCheckout {
+    /// Clones a repository into a new temporary directory and checks out a
+    /// revision, which can be a branch, a tag, or a commit.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let checkout = mgmt::git::RevisionCheckout::new("https://github.com/cyverse-de/de-releases", "main")?;
+    /// println!("{} is checked out in {}", checkout.commit, checkout.path.display());
+    /// ```
+    pub fn new(url: &str, revision: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "mgmt-checkout-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_nanos()
+        ));
+        let path_str = path.to_str().context("failed to get the checkout path")?;
+
+        // The checkout is created first so that the directory is cleaned up
+        // if anything after the clone fails.
+        let mut checkout = RevisionCheckout {
+            path: path.clone(),
+            commit: String::new(),
+        };
+        // The URL and revision come from the database or the command line, so
+        // they're kept from being read as options. git rev-parse would take
+        // anything after `--` as a path, so it's given --end-of-options
+        // instead. The commit that's checked out is the hash it prints.
+        output(
+            &std::env::temp_dir(),
+            &["clone", "--quiet", "--no-checkout", "--", url, path_str],
+        )?;
+
+        // Branches other than the default one only exist as remote branches
+        // in a fresh clone.
+        let rev_parse = |rev: &str| {
+            output(
+                &path,
+                &["rev-parse", "--verify", "--quiet", "--end-of-options", rev],
+            )
+        };
+        let commit = rev_parse(&format!("{}^{{commit}}", revision))
+            .or_else(|_| rev_parse(&format!("origin/{}^{{commit}}", revision)))
+            .map_err(|_| anyhow!("{} has no revision named {}", url, revision))?;
+
+        output(&path, &["checkout", "--quiet", "--detach", &commit])?;
+        checkout.commit = commit;
+
+        Ok(checkout)
+    }
+}
+
+impl Drop for RevisionCheckout {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Commits a file in a repository, returning the new commit's hash.
+    fn commit_file(repodir: &Path, name: &str, contents: &str) -> String {
+        fs::write(repodir.join(name), contents).unwrap();
+        output(repodir, &["add", name]).unwrap();
+        output(
+            repodir,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.org",
+                "commit",
+                "--quiet",
+                "-m",
+                name,
+            ],
+        )
+        .unwrap();
+        output(repodir, &["rev-parse", "HEAD"]).unwrap()
+    }
+
+    #[test]
+    fn test_revision_checkout() {
+        let repodir = std::env::temp_dir().join(format!("mgmt-git-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repodir);
+        fs::create_dir_all(&repodir).unwrap();
+        output(&repodir, &["init", "--quiet", "--initial-branch=main"]).unwrap();
+
+        let first = commit_file(&repodir, "a.conf", "one");
+        output(&repodir, &["tag", "v1"]).unwrap();
+        output(&repodir, &["checkout", "--quiet", "-b", "next"]).unwrap();
+        let second = commit_file(&repodir, "a.conf", "two");
+        output(&repodir, &["checkout", "--quiet", "main"]).unwrap();
+        let url = repodir.to_str().unwrap();
+
+        let checkout = RevisionCheckout::new(url, "next").unwrap();
+        assert_eq!(checkout.commit, second);
+        assert_eq!(
+            fs::read_to_string(checkout.path.join("a.conf")).unwrap(),
+            "two"
+        );
+
+        for revision in ["v1", "main", first.as_str()] {
+            let checkout = RevisionCheckout::new(url, revision).unwrap();
+            assert_eq!(checkout.commit, first);
+            assert_eq!(
+                fs::read_to_string(checkout.path.join("a.conf")).unwrap(),
+                "one"
+            );
+        }
+
+        let path = checkout.path.clone();
+        drop(checkout);
+        assert!(!path.exists());
+        assert!(RevisionCheckout::new(url, "nope").is_err());
+
+        // Neither the URL nor the revision can pass options to git.
+        let marker = repodir.join("marker");
+        let upload_pack = format!("--upload-pack=touch {}", marker.display());
+        assert!(RevisionCheckout::new(&upload_pack, "main").is_err());
+        let output_option = format!("--output={}", marker.display());
+        assert!(RevisionCheckout::new(url, &output_option).is_err());
+        assert!(!marker.exists());
+
+        fs::remove_dir_all(&repodir).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 use crate::{
     config_values::config::{self, ConfigValues},
-    db, ops,
+    db, deploy, ops,
+    output::OutputFormat,
+    settings,
 };
 use anyhow::{anyhow, Result};
 use clap::ArgMatches;
@@ -27,7 +29,11 @@ async fn section_delete(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
     Ok(())
 }
 
-pub async fn sections(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+pub async fn sections(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let section_cmd = sub_m
         .subcommand()
         .ok_or_else(|| anyhow::anyhow!("bad command"))?;
@@ -35,7 +41,7 @@ pub async fn sections(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     match section_cmd {
         ("add", sub_m) => section_add(&pool, &sub_m).await,
         ("delete", sub_m) => section_delete(&pool, &sub_m).await,
-        ("list", _) => ops::list_sections(&pool).await,
+        ("list", _) => ops::list_sections(&pool, output).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
 }
@@ -57,12 +63,18 @@ pub async fn defaults_set(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<(
         .get_one::<String>("type")
         .ok_or_else(|| anyhow!("No type specified. Use --type <type> to specify a type."))?;
 
-    ops::set_default_value(&pool, &section, &key, &value, &value_type).await?;
+    let sensitive = sub_m.get_flag("sensitive");
+
+    ops::set_default_value(&pool, &section, &key, &value, &value_type, sensitive).await?;
 
     Ok(())
 }
 
-async fn defaults_get(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn defaults_get(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let section = sub_m.get_one::<String>("section").ok_or_else(|| {
         anyhow!("No section specified. Use --section <section> to specify a section.")
     })?;
@@ -71,7 +83,9 @@ async fn defaults_get(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         .get_one::<String>("key")
         .ok_or_else(|| anyhow!("No key specified. Use --key <key> to specify a key."))?;
 
-    ops::get_default_value(&pool, &section, &key).await?;
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::get_default_value(&pool, &section, &key, show_secrets, output).await?;
 
     Ok(())
 }
@@ -90,7 +104,11 @@ async fn defaults_delete(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()
     Ok(())
 }
 
-async fn defaults_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn defaults_list(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let section = match sub_m.get_one::<String>("section") {
         Some(section) => Some(section.as_str()),
         None => None,
@@ -101,28 +119,35 @@ async fn defaults_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
         None => None,
     };
 
-    ops::list_default_values(&pool, section, key).await?;
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::list_default_values(&pool, section, key, show_secrets, output).await?;
 
     Ok(())
 }
 
 async fn defaults_render(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     let output_file = sub_m.get_one::<PathBuf>("file").cloned();
-    ops::render_default_values(&pool, output_file).await?;
+    let show_secrets = sub_m.get_flag("show-secrets");
+    ops::render_default_values(&pool, output_file, show_secrets).await?;
 
     Ok(())
 }
 
-pub async fn defaults(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+pub async fn defaults(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let defaults_cmd = sub_m
         .subcommand()
         .ok_or_else(|| anyhow::anyhow!("bad command"))?;
 
     match defaults_cmd {
         ("set", sub_m) => defaults_set(&pool, &sub_m).await,
-        ("get", sub_m) => defaults_get(&pool, &sub_m).await,
+        ("get", sub_m) => defaults_get(&pool, &sub_m, output).await,
         ("delete", sub_m) => defaults_delete(&pool, &sub_m).await,
-        ("list", sub_m) => defaults_list(&pool, &sub_m).await,
+        ("list", sub_m) => defaults_list(&pool, &sub_m, output).await,
         ("render", sub_m) => defaults_render(&pool, &sub_m).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
@@ -151,12 +176,111 @@ async fn values_set(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         .get_one::<String>("type")
         .ok_or_else(|| anyhow!("No type specified. Use --type <type> to specify a type."))?;
 
-    ops::set_value(&pool, &environment, &section, &key, &value, &value_type).await?;
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::set_value(
+        &pool,
+        &environment,
+        &section,
+        &key,
+        &value,
+        &value_type,
+        show_secrets,
+    )
+    .await?;
+
+    if sub_m.get_flag("apply") {
+        let templates_dir = sub_m.get_one::<PathBuf>("templates").ok_or_else(|| {
+            anyhow!(
+                "No templates directory specified. Use --templates <dir> to specify a directory."
+            )
+        })?;
+
+        let configdir = sub_m.get_one::<PathBuf>("configs").ok_or_else(|| {
+            anyhow!(
+                "No config directory provided. Use --configs <dir> to specify a config directory."
+            )
+        })?;
+
+        let opts = deploy::SyncOptions {
+            templates_dir: templates_dir.clone(),
+            configdir: configdir.clone(),
+            dry_run: false,
+            no_restart: false,
+            kube_context: settings::kube_context(sub_m),
+        };
+
+        let mut tx = pool.begin().await?;
+        let affected: Vec<String> = db::list_impacted_templates(&mut tx, environment, section, key)
+            .await?
+            .into_iter()
+            .map(|t| t.template_path)
+            .collect();
+
+        // Nothing is known to use the setting until `mgmt templates scan` has
+        // been run, so every template is checked for changes instead.
+        let candidates = match affected.is_empty() {
+            true => None,
+            false => Some(affected.as_slice()),
+        };
+        deploy::sync(&mut tx, environment, candidates, &opts).await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+async fn values_get(pool: &Pool<Postgres>, sub_m: &ArgMatches, output: OutputFormat) -> Result<()> {
+    let environment = sub_m.get_one::<String>("environment").ok_or_else(|| {
+        anyhow!(
+            "No environment specified. Use --environment <environment> to specify an environment."
+        )
+    })?;
+
+    let section = sub_m.get_one::<String>("section").ok_or_else(|| {
+        anyhow!("No section specified. Use --section <section> to specify a section.")
+    })?;
+
+    let key = sub_m
+        .get_one::<String>("key")
+        .ok_or_else(|| anyhow!("No key specified. Use --key <key> to specify a key."))?;
+
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::get_value(&pool, &environment, &section, &key, show_secrets, output).await?;
+
+    Ok(())
+}
+
+async fn values_impact(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
+    let environment = sub_m.get_one::<String>("environment").ok_or_else(|| {
+        anyhow!(
+            "No environment specified. Use --environment <environment> to specify an environment."
+        )
+    })?;
+
+    let section = sub_m.get_one::<String>("section").ok_or_else(|| {
+        anyhow!("No section specified. Use --section <section> to specify a section.")
+    })?;
+
+    let key = sub_m
+        .get_one::<String>("key")
+        .ok_or_else(|| anyhow!("No key specified. Use --key <key> to specify a key."))?;
+
+    ops::value_impact(pool, environment, section, key, output).await?;
 
     Ok(())
 }
 
-async fn values_get(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn values_explain(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let environment = sub_m.get_one::<String>("environment").ok_or_else(|| {
         anyhow!(
             "No environment specified. Use --environment <environment> to specify an environment."
@@ -171,7 +295,9 @@ async fn values_get(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         .get_one::<String>("key")
         .ok_or_else(|| anyhow!("No key specified. Use --key <key> to specify a key."))?;
 
-    ops::get_value(&pool, &environment, &section, &key).await?;
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::explain_value(pool, environment, section, key, show_secrets, output).await?;
 
     Ok(())
 }
@@ -196,7 +322,11 @@ async fn values_delete(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
     Ok(())
 }
 
-async fn values_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn values_list(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let environment = match sub_m.get_one::<String>("environment") {
         Some(env) => Some(env.as_str()),
         None => None,
@@ -212,7 +342,9 @@ async fn values_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         None => None,
     };
 
-    ops::list_values(&pool, environment, section, key).await?;
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::list_values(&pool, environment, section, key, show_secrets, output).await?;
 
     Ok(())
 }
@@ -229,8 +361,51 @@ async fn values_render(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
         None => None,
     };
 
-    let opts = config::SectionOptions::new_from_db(&pool, &environment).await?;
-    ops::render_values(&pool, &environment, &opts, output_file).await?;
+    let provenance = sub_m.get_flag("provenance");
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    let mut opts = config::SectionOptions::new_from_db(&pool, &environment).await?;
+    opts.include_from_args(sub_m)?;
+    ops::render_values(
+        &pool,
+        &environment,
+        &opts,
+        output_file,
+        provenance,
+        show_secrets,
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn values_check(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let environment = sub_m.get_one::<String>("environment").ok_or_else(|| {
+        anyhow!(
+            "No environment specified. Use --environment <environment> to specify an environment."
+        )
+    })?;
+
+    ops::check_values(pool, environment).await?;
+
+    Ok(())
+}
+
+async fn values_apply(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let environment = sub_m.get_one::<String>("environment").ok_or_else(|| {
+        anyhow!(
+            "No environment specified. Use --environment <environment> to specify an environment."
+        )
+    })?;
+
+    let path = sub_m
+        .get_one::<PathBuf>("file")
+        .ok_or_else(|| anyhow!("No file specified. Use --file <file> to specify a file."))?;
+
+    let dry_run = sub_m.get_flag("dry-run");
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::apply_values(pool, environment, path, dry_run, show_secrets).await?;
 
     Ok(())
 }
@@ -246,7 +421,10 @@ async fn values_import(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
         )
     })?;
 
-    ops::import_yaml_file(&pool, path.to_path_buf(), environment).await?;
+    let prune = sub_m.get_flag("prune");
+    let show_secrets = sub_m.get_flag("show-secrets");
+
+    ops::import_yaml_file(&pool, path.to_path_buf(), environment, prune, show_secrets).await?;
 
     println!(
         "Imported values from {} for the {} environment.",
@@ -261,7 +439,7 @@ async fn values_import(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
             .await?
             .into();
     let new_ops = imported_cfgs.generate_section_options();
-    db::upsert_feature_flags(&mut tx, &environment, &new_ops.into()).await?;
+    db::set_section_flags(&mut tx, &environment, new_ops.sections()).await?;
     tx.commit().await?;
 
     println!("Set up feature flags for the {} environment.", environment);
@@ -269,18 +447,52 @@ async fn values_import(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()>
     Ok(())
 }
 
-pub async fn values(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+pub async fn values(pool: &Pool<Postgres>, sub_m: &ArgMatches, output: OutputFormat) -> Result<()> {
     let values_cmd = sub_m
         .subcommand()
         .ok_or_else(|| anyhow::anyhow!("bad command"))?;
 
     match values_cmd {
         ("set", sub_m) => values_set(&pool, &sub_m).await,
-        ("get", sub_m) => values_get(&pool, &sub_m).await,
+        ("get", sub_m) => values_get(&pool, &sub_m, output).await,
+        ("explain", sub_m) => values_explain(pool, sub_m, output).await,
+        ("impact", sub_m) => values_impact(pool, sub_m, output).await,
         ("delete", sub_m) => values_delete(&pool, &sub_m).await,
-        ("list", sub_m) => values_list(&pool, &sub_m).await,
+        ("list", sub_m) => values_list(&pool, &sub_m, output).await,
         ("render", sub_m) => values_render(&pool, &sub_m).await,
+        ("check", sub_m) => values_check(pool, sub_m).await,
+        ("apply", sub_m) => values_apply(pool, sub_m).await,
         ("import", sub_m) => values_import(&pool, &sub_m).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
 }
+
+async fn secrets_generate_key(sub_m: &ArgMatches) -> Result<()> {
+    let file = sub_m.get_one::<PathBuf>("file").cloned();
+
+    ops::generate_secrets_key(file)?;
+
+    Ok(())
+}
+
+async fn secrets_rotate_key(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let new_key_file = sub_m.get_one::<PathBuf>("new-key-file").ok_or_else(|| {
+        anyhow!("No key file specified. Use --new-key-file <file> to specify a key file.")
+    })?;
+
+    ops::rotate_secrets_key(pool, new_key_file).await?;
+
+    Ok(())
+}
+
+pub async fn secrets(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let secrets_cmd = sub_m
+        .subcommand()
+        .ok_or_else(|| anyhow::anyhow!("bad command"))?;
+
+    match secrets_cmd {
+        ("generate-key", sub_m) => secrets_generate_key(sub_m).await,
+        ("rotate-key", sub_m) => secrets_rotate_key(pool, sub_m).await,
+        (name, _) => unreachable!("Bad subcommand: {name}"),
+    }
+}
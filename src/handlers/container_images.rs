@@ -1,6 +1,7 @@
+use crate::output::{self, OutputFormat};
 use anyhow::{anyhow, Result};
 use clap::ArgMatches;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sqlx::Postgres;
 use sqlx::{Pool, Transaction};
 use std::fs;
@@ -156,11 +157,26 @@ pub async fn insert_image(
     Ok(image_id)
 }
 
+/**
+ * Contains a container image and the service that uses it, as listed by
+ * `mgmt container-images list`.
+ */
+#[derive(Debug, Serialize, tabled::Tabled)]
+pub struct ImageListing {
+    pub id: i32,
+    pub name: String,
+    pub tag: String,
+    pub digest: String,
+    pub dockerfile: String,
+    pub service_name: String,
+}
+
 /**
  * Lists the images in the database.
  */
-pub async fn list_images(pool: &Pool<Postgres>) -> Result<()> {
-    let images = sqlx::query!(
+pub async fn list_images(pool: &Pool<Postgres>, output: OutputFormat) -> Result<()> {
+    let images = sqlx::query_as!(
+        ImageListing,
         r#"
         SELECT 
             ci.id, ci.name, ci.tag, ci.digest, ci.dockerfile, s.name as service_name
@@ -175,15 +191,7 @@ pub async fn list_images(pool: &Pool<Postgres>) -> Result<()> {
     .fetch_all(pool)
     .await?;
 
-    println!("Images:");
-    for image in images {
-        println!(
-            "  id: {}, name: {}, tag: {}, digest: {}, dockerfile: {}, service: {}",
-            image.id, image.name, image.tag, image.digest, image.dockerfile, image.service_name,
-        );
-    }
-
-    Ok(())
+    output::print_table(output, &images)
 }
 
 /**
@@ -420,8 +428,8 @@ pub async fn delete(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
-pub async fn list_all_images(pool: &Pool<Postgres>) -> Result<()> {
-    list_images(&pool).await?;
+pub async fn list_all_images(pool: &Pool<Postgres>, output: OutputFormat) -> Result<()> {
+    list_images(&pool, output).await?;
 
     Ok(())
 }
@@ -0,0 +1,153 @@
+use crate::db::dump::{self, Dump, ImportMode};
+use crate::db::migrate;
+use crate::ops;
+use crate::output::{self, OutputFormat};
+use anyhow::{anyhow, Context, Result};
+use clap::ArgMatches;
+use sqlx::{Pool, Postgres};
+use std::path::PathBuf;
+
+async fn migrate_up(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let steps = sub_m.get_one::<usize>("steps").copied();
+
+    if migrate::up(pool, steps).await?.is_empty() {
+        println!("No migrations to apply");
+    }
+
+    Ok(())
+}
+
+async fn migrate_down(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let steps = if sub_m.get_flag("all") {
+        None
+    } else {
+        Some(*sub_m.get_one::<usize>("steps").ok_or_else(|| {
+            anyhow!("No steps specified. Use --steps <steps> to specify the number of migrations to revert.")
+        })?)
+    };
+
+    if migrate::down(pool, steps).await?.is_empty() {
+        println!("No migrations to revert");
+    }
+
+    Ok(())
+}
+
+async fn migrate_status(pool: &Pool<Postgres>, output: OutputFormat) -> Result<()> {
+    let statuses = migrate::status(pool).await?;
+    output::print_table(output, &statuses)
+}
+
+// The database's schema version alongside the one the binary expects.
+#[derive(serde::Serialize)]
+struct VersionListing {
+    version: Option<i64>,
+    dirty: bool,
+    expected: Option<i64>,
+}
+
+async fn migrate_version(pool: &Pool<Postgres>, output: OutputFormat) -> Result<()> {
+    let current = migrate::current_version(pool).await?;
+    let listing = VersionListing {
+        version: current.map(|v| v.version),
+        dirty: current.is_some_and(|v| v.dirty),
+        expected: migrate::latest_version(),
+    };
+
+    output::print_with(output, &listing, || {
+        match listing.version {
+            Some(version) if listing.dirty => println!("{} (dirty)", version),
+            Some(version) => println!("{}", version),
+            None => println!("no migrations applied"),
+        }
+        if let Some(expected) = listing.expected {
+            println!("expected: {}", expected);
+        }
+        Ok(())
+    })
+}
+
+async fn migrate_force(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let version = sub_m
+        .get_one::<i64>("VERSION")
+        .ok_or_else(|| anyhow!("No version specified. Pass the version to record as applied."))?;
+
+    migrate::force(pool, *version).await?;
+    println!("Set the schema version to {}", version);
+
+    Ok(())
+}
+
+async fn export(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let version = migrate::current_version(pool).await?.map(|v| v.version);
+
+    let mut tx = pool.begin().await?;
+    let dump = dump::export(&mut tx, version).await?;
+    tx.commit().await?;
+
+    let yaml = serde_yaml::to_string(&dump)?;
+    match sub_m.get_one::<PathBuf>("out") {
+        Some(out) => {
+            std::fs::write(out, yaml)
+                .with_context(|| format!("failed to write {}", out.display()))?;
+            println!(
+                "Exported {} environments to {}",
+                dump.environments.len(),
+                out.display()
+            );
+        }
+        None => print!("{}", yaml),
+    }
+
+    Ok(())
+}
+
+async fn import(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+    let path = sub_m.get_one::<PathBuf>("in").ok_or_else(|| {
+        anyhow!("No file specified. Use --in <file> to specify the file to import.")
+    })?;
+
+    let mode = if sub_m.get_flag("merge") {
+        ImportMode::Merge
+    } else if sub_m.get_flag("replace") {
+        ImportMode::Replace
+    } else {
+        ImportMode::Restore
+    };
+
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let dump: Dump = serde_yaml::from_str(&contents)
+        .with_context(|| format!("{} is not a valid export", path.display()))?;
+
+    let mut tx = pool.begin().await?;
+    dump::import(&mut tx, &dump, mode).await?;
+    for env in &dump.environments {
+        ops::env_layers(&mut tx, &env.name).await?;
+    }
+    tx.commit().await?;
+
+    println!(
+        "Imported {} environments from {}",
+        dump.environments.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+pub async fn db(pool: &Pool<Postgres>, sub_m: &ArgMatches, output: OutputFormat) -> Result<()> {
+    match sub_m.subcommand() {
+        Some(("migrate", sub_m)) => match sub_m.subcommand() {
+            Some(("up", sub_m)) => migrate_up(pool, sub_m).await,
+            Some(("down", sub_m)) => migrate_down(pool, sub_m).await,
+            Some(("status", _)) => migrate_status(pool, output).await,
+            Some(("version", _)) => migrate_version(pool, output).await,
+            Some(("force", sub_m)) => migrate_force(pool, sub_m).await,
+            _ => unreachable!("Bad migrate subcommand"),
+        },
+        Some(("export", sub_m)) => export(pool, sub_m).await,
+        Some(("import", sub_m)) => import(pool, sub_m).await,
+        _ => unreachable!("Bad db subcommand"),
+    }
+}
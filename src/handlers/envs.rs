@@ -1,7 +1,14 @@
-use crate::{db, ops};
-use anyhow::{anyhow, Result};
+use crate::{
+    db::{self, backend::Database},
+    deploy, ops,
+    output::{self, OutputFormat},
+    settings,
+};
+use anyhow::{anyhow, Context, Result};
 use clap::ArgMatches;
 use sqlx::{Pool, Postgres, Transaction};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 pub async fn populate_env_templates(
     tx: &mut Transaction<'_, Postgres>,
@@ -49,10 +56,24 @@ async fn env_create(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
         anyhow!("No environment specified for --from. Use --from <environment> to specify a basis environment.")
     })?;
 
+    let parent = sub_m.get_one::<String>("parent");
+
     let mut tx = pool.begin().await?;
+    if let Some(parent) = parent {
+        db::get_env_id(&mut tx, parent)
+            .await
+            .with_context(|| format!("no parent environment named {}", parent))?;
+    }
+
     db::upsert_environment(&mut tx, &env, &namespace).await?;
     println!("Created environment: {}", env);
 
+    if let Some(parent) = parent {
+        db::set_env_parent(&mut tx, env, Some(parent)).await?;
+        ops::env_layers(&mut tx, env).await?;
+        println!("Inheriting config values from: {}", parent);
+    }
+
     println!("Setting up environment...");
 
     // Get the list of services available in the --from environment.
@@ -65,15 +86,31 @@ async fn env_create(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
-async fn env_list(pool: &Pool<Postgres>) -> Result<()> {
+// An environment and its parent, as listed by `mgmt env list`.
+#[derive(serde::Serialize)]
+struct EnvListing {
+    name: String,
+    parent: Option<String>,
+}
+
+async fn env_list(pool: &Pool<Postgres>, output: OutputFormat) -> Result<()> {
     let mut tx = pool.begin().await?;
-    let envs = db::list_envs(&mut tx).await?;
-    tx.commit().await?;
-    for env in envs {
-        println!("{}", env);
+    let mut envs = Vec::new();
+    for name in db::list_envs(&mut tx).await? {
+        let parent = db::get_env_parent(&mut tx, &name).await?;
+        envs.push(EnvListing { name, parent });
     }
+    tx.commit().await?;
 
-    Ok(())
+    output::print_with(output, &envs, || {
+        for env in &envs {
+            match &env.parent {
+                Some(parent) => println!("{} (parent: {})", env.name, parent),
+                None => println!("{}", env.name),
+            }
+        }
+        Ok(())
+    })
 }
 
 async fn env_delete(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
@@ -130,7 +167,11 @@ async fn env_services_remove(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Resul
     Ok(())
 }
 
-async fn env_services_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn env_services_list(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let env = sub_m.get_one::<String>("env").ok_or_else(|| {
         anyhow!("No environment specified. Use --env <env> to specify an environment.")
     })?;
@@ -139,14 +180,15 @@ async fn env_services_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<
     let services = db::get_services(&mut tx, &env).await?;
     tx.commit().await?;
 
-    for svc in services {
-        println!("{}", svc.name);
-    }
-
-    Ok(())
+    let names: Vec<String> = services.into_iter().map(|svc| svc.name).collect();
+    output::print_names(output, &names)
 }
 
-async fn env_services_handler(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn env_services_handler(
+    pool: &Pool<Postgres>,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let services_cmd = sub_m
         .subcommand()
         .ok_or_else(|| anyhow::anyhow!("bad command"))?;
@@ -154,64 +196,115 @@ async fn env_services_handler(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Resu
     match services_cmd {
         ("add", sub_m) => env_services_add(&pool, &sub_m).await,
         ("delete", sub_m) => env_services_remove(&pool, &sub_m).await,
-        ("list", sub_m) => env_services_list(&pool, &sub_m).await,
+        ("list", sub_m) => env_services_list(&pool, &sub_m, output).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
 }
 
-async fn env_feature_flags_set(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn env_sync(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     let env = sub_m.get_one::<String>("env").ok_or_else(|| {
         anyhow!("No environment specified. Use --env <env> to specify an environment.")
     })?;
 
-    let flag = sub_m
-        .get_one::<String>("flag")
-        .ok_or_else(|| anyhow!("No flag specified. Use --flag <flag> to specify a flag."))?;
+    let templates_dir = sub_m.get_one::<PathBuf>("templates").ok_or_else(|| {
+        anyhow!("No templates directory specified. Use --templates <dir> to specify a directory.")
+    })?;
 
-    let value = sub_m
-        .get_one::<String>("value")
-        .ok_or_else(|| anyhow!("No value specified. Use --value <value> to specify a value."))?;
+    let configdir = sub_m.get_one::<PathBuf>("configs").ok_or_else(|| {
+        anyhow!("No config directory provided. Use --configs <dir> to specify a config directory.")
+    })?;
+
+    let opts = deploy::SyncOptions {
+        templates_dir: templates_dir.clone(),
+        configdir: configdir.clone(),
+        dry_run: sub_m.get_flag("dry-run"),
+        no_restart: sub_m.get_flag("no-restart"),
+        kube_context: settings::kube_context(sub_m),
+    };
 
     let mut tx = pool.begin().await?;
-    db::set_feature_flag(&mut tx, &env, &flag, &value).await?;
+    deploy::sync(&mut tx, env, None, &opts).await?;
     tx.commit().await?;
 
-    println!(
-        "Set feature flag {} to {} for environment {}",
-        flag, value, env
-    );
-
     Ok(())
 }
 
-async fn env_feature_flags_list(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn env_feature_flags_set(database: &Database, sub_m: &ArgMatches) -> Result<()> {
     let env = sub_m.get_one::<String>("env").ok_or_else(|| {
         anyhow!("No environment specified. Use --env <env> to specify an environment.")
     })?;
 
-    let mut tx = pool.begin().await?;
-    let flags = db::get_feature_flags(&mut tx, &env).await?;
-    tx.commit().await?;
+    let mut flags: BTreeMap<String, bool> = BTreeMap::new();
+
+    if let Some(flag) = sub_m.get_one::<String>("flag") {
+        let value = sub_m.get_one::<String>("value").ok_or_else(|| {
+            anyhow!("No value specified. Use --value <value> to specify a value.")
+        })?;
+        flags.insert(flag.clone(), db::parse_flag_value(value)?);
+    }
 
-    println!("Feature flags for environment {}:", env);
-    println!("{:#?}", flags);
+    for (arg, value) in [("enable", true), ("disable", false)] {
+        for flag in sub_m.get_many::<String>(arg).unwrap_or_default() {
+            if flags
+                .insert(flag.clone(), value)
+                .is_some_and(|v| v != value)
+            {
+                return Err(anyhow!(
+                    "Feature flag {} can't be both turned on and off.",
+                    flag
+                ));
+            }
+        }
+    }
+
+    database.set_feature_flags(env, &flags).await?;
+
+    for (flag, value) in &flags {
+        println!(
+            "Set feature flag {} to {} for environment {}",
+            flag, value, env
+        );
+    }
 
     Ok(())
 }
 
-async fn env_feature_flags_handler(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+async fn env_feature_flags_list(
+    database: &Database,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
+    let env = sub_m.get_one::<String>("env").ok_or_else(|| {
+        anyhow!("No environment specified. Use --env <env> to specify an environment.")
+    })?;
+
+    let flags = database.get_feature_flags(env).await?;
+
+    if output == OutputFormat::Table {
+        println!("Feature flags for environment {}:", env);
+    }
+    output::print_table(output, &flags)
+}
+
+/// Handles the `mgmt env feature-flags` commands, which work against either
+/// database backend.
+pub async fn env_feature_flags_handler(
+    database: &Database,
+    sub_m: &ArgMatches,
+    output: OutputFormat,
+) -> Result<()> {
     let ff_cmd = sub_m
         .subcommand()
         .ok_or_else(|| anyhow::anyhow!("bad command"))?;
 
     match ff_cmd {
-        ("set", sub_m) => env_feature_flags_set(&pool, &sub_m).await,
-        ("list", sub_m) => env_feature_flags_list(&pool, &sub_m).await,
+        ("set", sub_m) => env_feature_flags_set(database, sub_m).await,
+        ("list", sub_m) => env_feature_flags_list(database, sub_m, output).await,
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
 }
 
-pub async fn env(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
+pub async fn env(pool: &Pool<Postgres>, sub_m: &ArgMatches, output: OutputFormat) -> Result<()> {
     let create_cmd = sub_m
         .subcommand()
         .ok_or_else(|| anyhow::anyhow!("bad command"))?;
@@ -219,10 +312,11 @@ pub async fn env(pool: &Pool<Postgres>, sub_m: &ArgMatches) -> Result<()> {
     match create_cmd {
         ("populate", _) => Ok(ops::populate_env(&pool, "de").await?),
         ("create", sub_m) => env_create(&pool, &sub_m).await,
-        ("list", _) => env_list(&pool).await,
+        ("list", _) => env_list(&pool, output).await,
+        ("sync", sub_m) => env_sync(pool, sub_m).await,
         ("delete", sub_m) => env_delete(&pool, &sub_m).await,
-        ("service", sub_m) => env_services_handler(&pool, &sub_m).await,
-        ("feature-flags", sub_m) => env_feature_flags_handler(&pool, &sub_m).await,
+        ("service", sub_m) => env_services_handler(&pool, &sub_m, output).await,
+        ("feature-flags", _) => unreachable!("feature-flags is handled before the database check"),
         (name, _) => unreachable!("Bad subcommand: {name}"),
     }
 }
@@ -1,5 +1,6 @@
 pub mod configs;
 pub mod container_images;
+pub mod db;
 pub mod envs;
 pub mod releases;
 pub mod services;
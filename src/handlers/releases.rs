@@ -1,4 +1,4 @@
-use crate::{db, deploy, git, ops};
+use crate::{db, deploy, git, ops, settings};
 use anyhow::{anyhow, Context, Result};
 use clap::ArgMatches;
 use flate2::read::GzDecoder;
@@ -417,6 +417,7 @@ pub async fn deploy(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
     let no_load_configs = matches.get_flag("no-load-configs");
     let no_load_secrets = matches.get_flag("no-load-secrets");
     let no_render_configs = matches.get_flag("no-render-configs");
+    let no_validate = matches.get_flag("no-validate");
 
     let skips = matches
         .get_many::<String>("skip")
@@ -443,6 +444,8 @@ pub async fn deploy(pool: &Pool<Postgres>, matches: &ArgMatches) -> Result<()> {
         no_load_secrets,
         no_render_configs,
         pre_deploy,
+        no_validate,
+        kube_context: settings::kube_context(matches),
     };
 
     deploy::deploy(pool, &env, repo_name, &repo_url, &repo_branch, &opts).await?;
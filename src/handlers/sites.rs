@@ -1,7 +1,10 @@
-use crate::{config_values::config, db, dolt, git, ops};
+use crate::{
+    config_values::config,
+    db::backend::{Connection, Database},
+    dolt, git, ops,
+};
 use anyhow::Result;
 use clap::ArgMatches;
-use sqlx::postgres::PgPoolOptions;
 use std::path::{Path, PathBuf};
 /**
  * Set up the CLI for the mgmt-site binary.
@@ -65,15 +68,12 @@ async fn init(opts: &InitOpts) -> anyhow::Result<()> {
     println!("Done staring the database.\n");
 
     println!("Connecting to the database...");
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name))
-        .await?;
-    let mut tx = pool.begin().await?;
+    let database =
+        Database::connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name)).await?;
     println!("Done connecting to the database.\n");
 
     // Get the list of repos.
-    let repos = db::get_repos(&mut tx).await?;
+    let repos = database.get_repos().await?;
 
     println!("Cloning the repos...");
     for repo in repos {
@@ -98,38 +98,50 @@ async fn init(opts: &InitOpts) -> anyhow::Result<()> {
 
     if !opts.no_env {
         println!("Setting up the environment...");
-        env_config.ask_for_info(&mut tx).await?;
+        match &database {
+            Database::Postgres(pool) => {
+                let mut tx = pool.begin().await?;
+                env_config
+                    .ask_for_info(&mut Connection::Postgres(&mut tx))
+                    .await?;
+                tx.commit().await?;
+            }
+            Database::MySql(pool) => {
+                let mut tx = pool.begin().await?;
+                env_config
+                    .ask_for_info(&mut Connection::MySql(&mut tx))
+                    .await?;
+                tx.commit().await?;
+            }
+        }
         println!("Done setting up the environment.\n");
     }
 
+    // The secrets are written out as they are, since the files are the site's
+    // copy of the configuration.
+    let defaults = ops::default_values(database.list_default_config_values().await?)?;
+
     // Write out the default config values into the site directory.
     if !opts.no_defaults {
         println!("Writing out the default values...");
         let defaults_filename = Path::new(&opts.dir).join(&opts.defaults_filename);
-        ops::render_default_values(&pool, Some(defaults_filename)).await?;
+        std::fs::write(&defaults_filename, serde_yaml::to_string(&defaults)?)?;
         println!("Done writing out the default values.\n");
     }
 
-    tx.commit().await?;
-
+    // The environment was just set up, so its values are the ones that were
+    // entered, on top of the defaults.
     if !opts.no_env && !opts.no_values {
         println!("Writing out the environment config values...");
         let values_filename = Path::new(&opts.dir).join(&opts.values_filename);
-        let mut section_option = config::SectionOptions::default();
-        section_option.set_all(true)?;
-        ops::render_values(
-            &pool,
-            &env_config.environment,
-            &section_option,
-            Some(values_filename),
-        )
-        .await?;
+        let values = defaults.merge_with(&env_config)?;
+        std::fs::write(&values_filename, serde_yaml::to_string(&values)?)?;
         println!("Done writing out the environment config values.\n");
     }
 
     // Clean up and shut down
     println!("Shutting down the database...");
-    pool.close().await;
+    database.close().await;
     db_handle.kill()?;
     println!("Done shutting down the database.\n");
 
@@ -144,6 +156,22 @@ struct DeployOpts {
     defaults_filepath: PathBuf,
     values_filepath: PathBuf,
     builds_dirpath: PathBuf,
+    no_validate: bool,
+}
+
+// Merges the defaults and values files for the site and validates the result.
+// The values file determines which optional sections are expected.
+fn validate_values_files(opts: &DeployOpts) -> anyhow::Result<()> {
+    let defaults_file = std::fs::File::open(&opts.defaults_filepath)?;
+    let defaults: config::ConfigValues = serde_yaml::from_reader(defaults_file)?;
+
+    let values_file = std::fs::File::open(&opts.values_filepath)?;
+    let values: config::ConfigValues = serde_yaml::from_reader(values_file)?;
+
+    let mut merged = defaults.merge_with(&values)?;
+    merged.set_section_options(values.generate_section_options());
+
+    ops::report_issues(&opts.env, &merged.validate())
 }
 
 async fn deploy(opts: &DeployOpts) -> anyhow::Result<()> {
@@ -152,6 +180,12 @@ async fn deploy(opts: &DeployOpts) -> anyhow::Result<()> {
     println!("Using defaults file {:?}...", opts.defaults_filepath);
     println!("Using values file {:?}...\n", opts.values_filepath);
 
+    if !opts.no_validate {
+        println!("Validating the configuration values...");
+        validate_values_files(opts)?;
+        println!();
+    }
+
     print!("Starting the database...");
     let db_dir = Path::new(&opts.site_dirpath).join(&opts.db_name);
     let db_dir_str = db_dir
@@ -162,21 +196,14 @@ async fn deploy(opts: &DeployOpts) -> anyhow::Result<()> {
 
     print!("Connecting to the database...");
     // Connect to the database.
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name))
-        .await?;
-    let mut tx = pool.begin().await?;
+    let database =
+        Database::connect(&format!("mysql://root@127.0.0.1:3306/{}", &opts.db_name)).await?;
     println!("DONE\n");
 
     let services_to_deploy: Vec<String>;
 
     if opts.services.is_empty() {
-        services_to_deploy = db::list_services(&mut tx, &opts.env)
-            .await?
-            .into_iter()
-            .map(|svc| svc.name)
-            .collect();
+        services_to_deploy = database.list_service_names(&opts.env).await?;
     } else {
         services_to_deploy = opts.services.clone();
     }
@@ -202,7 +229,7 @@ async fn deploy(opts: &DeployOpts) -> anyhow::Result<()> {
         // Deploy the service. See deploy_project in app.rs for a reference.
     }
 
-    tx.commit().await?;
+    database.close().await;
     dolt_handle.kill()?;
 
     Ok(())
@@ -291,6 +318,8 @@ pub async fn deploy_site(matches: &ArgMatches) -> Result<()> {
             )
         })?;
 
+    let no_validate = matches.get_flag("no-validate");
+
     let dir_canon = dir.canonicalize()?;
     let opts = DeployOpts {
         site_dirpath: dir_canon.clone(),
@@ -300,6 +329,7 @@ pub async fn deploy_site(matches: &ArgMatches) -> Result<()> {
         defaults_filepath: Path::new(&dir_canon).join(defaults_filename),
         values_filepath: Path::new(&dir_canon).join(values_filename),
         builds_dirpath: Path::new(&dir_canon).join("builds"),
+        no_validate,
     };
 
     deploy(&opts).await?;
@@ -1,50 +1,114 @@
+pub mod filters;
+pub mod lint;
+pub mod refs;
+pub mod scan;
+
 use crate::{
     config_values::config::{ConfigValues, SectionOptions},
-    db,
+    configs, db, diff,
+    git::RevisionCheckout,
+    manifest::{self, Manifest, ManifestEntry},
+    ops,
+    secrets::{self, TextMasker},
 };
 use anyhow::Context;
-use base64::{engine::general_purpose, Engine as _};
+use clap::ArgMatches;
+use refs::Resolvers;
 use sqlx::{Postgres, Transaction};
-use std::{collections::HashMap, fs, path::PathBuf};
-use tera::{to_value, try_get_value, Result as TeraResult, Tera, Value};
-
-/// A custom filter for Tera that base64 encodes a Value.
-fn base64_encode(value: &Value, _: &HashMap<String, Value>) -> TeraResult<Value> {
-    let s = try_get_value!("base64_encode", "value", String, value);
-    let encoded: String = general_purpose::STANDARD.encode(s.as_bytes());
-
-    Ok(to_value(encoded).unwrap())
-}
+use std::{
+    collections::{btree_map, BTreeMap, BTreeSet},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+use tera::Tera;
 
-/// Creates a new Tera instance with the base64_encode filter registered.
+/// Creates a new Tera instance with the template filters registered.
 fn new_tera() -> Tera {
     let mut tera = Tera::default();
-    tera.register_filter("base64_encode", base64_encode);
+    filters::register(&mut tera);
     tera
 }
 
-/// Creates a new Tera instance with the base64_encode filter registered and
-/// templates loaded from a directory.
-fn new_tera_dir(templates_path: &PathBuf) -> anyhow::Result<Tera> {
-    let mut tera = Tera::new(
-        templates_path
-            .to_str()
-            .context("failed to get the templates path")
-            .unwrap(),
-    )?;
-    tera.register_filter("base64_encode", base64_encode);
-    Ok(tera)
+/// Creates the Tera context used to render templates from the merged config
+/// values. References to secrets stored outside of the database are resolved
+/// here, so that they're only ever held in memory.
+async fn new_context(values: &ConfigValues) -> anyhow::Result<tera::Context> {
+    let mut json_values = serde_json::to_value(values)?;
+    Resolvers::with_defaults()
+        .resolve_all(&mut json_values)
+        .await?;
+    Ok(tera::Context::from_value(json_values)?)
 }
 
-// Internal function that renders the values out to a file.
-fn render_t(
+// What's recorded in the manifest about the values and templates a render
+// used.
+struct RenderInputs {
+    environment: Option<String>,
+    values_hash: Option<String>,
+    revision: Option<String>,
+}
+
+impl RenderInputs {
+    // Returns what's recorded for files that are copied instead of rendered,
+    // which don't use any values.
+    fn copied(&self) -> RenderInputs {
+        RenderInputs {
+            environment: None,
+            values_hash: None,
+            revision: self.revision.clone(),
+        }
+    }
+}
+
+// Returns what's recorded about a render. The JSON the values hash is taken
+// from has its keys sorted, so the same values always hash the same way. The
+// revision is the commit the templates were read from, if they came from a
+// repo.
+fn render_inputs(
+    context: &tera::Context,
+    env: Option<&str>,
+    revision: Option<&str>,
+) -> anyhow::Result<RenderInputs> {
+    Ok(RenderInputs {
+        environment: env.map(String::from),
+        values_hash: Some(manifest::hash(&serde_json::to_vec(
+            &context.clone().into_json(),
+        )?)),
+        revision: revision.map(String::from),
+    })
+}
+
+// Builds the manifest entry for a file written into a directory.
+fn manifest_entry(
+    out_dir: &Path,
+    out_file: &Path,
+    template: &str,
+    template_contents: &[u8],
+    output: &[u8],
+    inputs: &RenderInputs,
+) -> anyhow::Result<ManifestEntry> {
+    Ok(ManifestEntry {
+        output: manifest::relative_output(out_dir, out_file)?,
+        template: template.to_string(),
+        template_hash: manifest::hash(template_contents),
+        output_hash: manifest::hash(output),
+        environment: inputs.environment.clone(),
+        values_hash: inputs.values_hash.clone(),
+        revision: inputs.revision.clone(),
+    })
+}
+
+// Internal function that renders the values out to a file. Single files
+// aren't recorded in a manifest, since the directory they're written to isn't
+// one that was rendered.
+async fn render_t(
     template_path: &PathBuf,
     defaults_values: &ConfigValues,
     env_values: &ConfigValues,
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
     let merged_cv = defaults_values.merge_with(&env_values)?;
-    let defaults_context = tera::Context::from_serialize(merged_cv)?;
+    let defaults_context = new_context(&merged_cv).await?;
 
     let out_file = fs::File::create(out_path)?;
     let mut tera = new_tera();
@@ -53,30 +117,216 @@ fn render_t(
     Ok(tera.render_to("template", &defaults_context, out_file)?)
 }
 
+/// The suffix that marks a file in a directory render as a template. It's
+/// dropped from the name of the rendered file.
+pub const TEMPLATE_SUFFIX: &str = ".tera";
+
+/// Returns the files under a directory, sorted, skipping hidden files and
+/// directories.
+pub fn list_template_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'))
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            files.extend(list_template_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Fails if more than one source file would be written to the same output
+/// path. The outputs are (source, output path) pairs.
+pub fn check_collisions<S: AsRef<str>>(outputs: &[(S, PathBuf)]) -> anyhow::Result<()> {
+    let mut seen: BTreeMap<&PathBuf, &str> = BTreeMap::new();
+    let mut collisions = Vec::new();
+    for (source, out) in outputs {
+        match seen.get(out) {
+            Some(first) if *first != source.as_ref() => collisions.push(format!(
+                "{} and {} would both be written to {}",
+                first,
+                source.as_ref(),
+                out.display()
+            )),
+            _ => {
+                seen.insert(out, source.as_ref());
+            }
+        }
+    }
+
+    if collisions.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "output path collision(s):\n  {}",
+            collisions.join("\n  ")
+        ))
+    }
+}
+
+/// Renders every file under a directory into the output directory, keeping
+/// the directory layout. The files are rendered as templates, which can
+/// include or extend each other by their path relative to the directory, and
+/// a `.tera` suffix is dropped from the name of the output. If `tera_only` is
+/// set, only the files ending in `.tera` are rendered and everything else is
+/// copied as it is, with a warning for any copied file that looks like a
+/// template. The files are recorded in the output directory's manifest, along
+/// with the revision the templates were checked out at, if there is one.
+fn render_dir(
+    templates_dir: &Path,
+    context: &tera::Context,
+    env: Option<&str>,
+    revision: Option<&str>,
+    tera_only: bool,
+    out_path: &Path,
+) -> anyhow::Result<()> {
+    // Directories used to be passed as Tera globs, like `templates/*`, so
+    // those still work.
+    let mut templates_dir = templates_dir;
+    while templates_dir
+        .file_name()
+        .is_some_and(|n| n.to_string_lossy().contains('*'))
+    {
+        templates_dir = templates_dir
+            .parent()
+            .context("failed to get the parent directory")?;
+    }
+
+    let mut templates: Vec<(String, String)> = Vec::new();
+    let mut outputs: Vec<(String, PathBuf)> = Vec::new();
+    let mut assets: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for path in list_template_files(templates_dir)? {
+        let relative = path.strip_prefix(templates_dir)?;
+        let name = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let out_name = match name.strip_suffix(TEMPLATE_SUFFIX) {
+            Some(out_name) => Some(out_name),
+            None if tera_only => None,
+            None => Some(name.as_str()),
+        };
+        match out_name {
+            Some(out_name) => {
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read the template {}", path.display()))?;
+                outputs.push((name.clone(), out_path.join(out_name)));
+                templates.push((name, contents));
+            }
+            None => {
+                outputs.push((name, out_path.join(relative)));
+                assets.push((path.clone(), out_path.join(relative)));
+            }
+        }
+    }
+    check_collisions(&outputs)?;
+
+    let mut tera = new_tera();
+    tera.add_raw_templates(templates.clone())?;
+
+    // The render owns the whole output directory, so the manifest only lists
+    // what's written this time.
+    let inputs = render_inputs(context, env, revision)?;
+    let mut manifest = Manifest::default();
+    let mut unrendered = Vec::new();
+    for (name, out_file) in &outputs {
+        if let Some(parent) = out_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let entry = match assets.iter().find(|(_, out)| out == out_file) {
+            Some((source, _)) => {
+                let contents = fs::read(source)?;
+                if looks_like_template(&contents) {
+                    unrendered.push(name.as_str());
+                }
+                fs::write(out_file, &contents)?;
+                manifest_entry(
+                    out_path,
+                    out_file,
+                    name,
+                    &contents,
+                    &contents,
+                    &inputs.copied(),
+                )?
+            }
+            None => {
+                let rendered = tera.render(name, context)?;
+                fs::write(out_file, &rendered)?;
+                let contents = templates
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map_or("", |(_, c)| c.as_str());
+                manifest_entry(
+                    out_path,
+                    out_file,
+                    name,
+                    contents.as_bytes(),
+                    rendered.as_bytes(),
+                    &inputs,
+                )?
+            }
+        };
+        manifest.record(entry);
+    }
+
+    if !unrendered.is_empty() {
+        eprintln!(
+            "WARNING: these files contain Tera delimiters but were copied without being rendered, because their names don't end in {}:\n  {}",
+            TEMPLATE_SUFFIX,
+            unrendered.join("\n  ")
+        );
+    }
+
+    manifest.save(out_path)
+}
+
+// Returns whether a file is text containing Tera's delimiters, so it was
+// probably meant to be rendered.
+fn looks_like_template(contents: &[u8]) -> bool {
+    std::str::from_utf8(contents)
+        .is_ok_and(|text| ["{{", "{%", "{#"].iter().any(|d| text.contains(d)))
+}
+
 // Internal function that renders a directory of templates out to a directory.
-fn render_d(
+async fn render_d(
     templates_path: &PathBuf,
     defaults_values: &ConfigValues,
     env_values: &ConfigValues,
+    env: Option<&str>,
+    revision: Option<&str>,
+    tera_only: bool,
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
     let merged_cv = defaults_values.merge_with(&env_values)?;
-    let defaults_context = tera::Context::from_serialize(&merged_cv)?;
-
-    let tera = new_tera_dir(templates_path)?;
+    let defaults_context = new_context(&merged_cv).await?;
 
-    for name in tera.get_template_names() {
-        let out_file = out_path.join(name);
-        let out_writer = fs::File::create(&out_file)?;
-        tera.render_to(name, &defaults_context, out_writer)?;
-    }
-
-    Ok(())
+    render_dir(
+        templates_path,
+        &defaults_context,
+        env,
+        revision,
+        tera_only,
+        out_path,
+    )
 }
 
 /// Renders a template out to a file. Uses the defaults and values files to
 /// populate the template.
-pub fn render_template(
+pub async fn render_template(
     template_path: &PathBuf,
     defaults_path: &PathBuf,
     values_path: &PathBuf,
@@ -90,7 +340,7 @@ pub fn render_template(
     let mut values: ConfigValues = serde_yaml::from_reader(values_file)?;
     values.set_section_options(values.generate_section_options());
 
-    Ok(render_t(template_path, &default_values, &values, out_path)?)
+    Ok(render_t(template_path, &default_values, &values, out_path).await?)
 }
 
 /// Renders a template out to a file, using the defaults and values queried
@@ -102,29 +352,26 @@ pub async fn render_template_from_db(
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
     let mut default_values: ConfigValues =
-        db::list_default_config_values(tx, None, None).await?.into();
+        secrets::decrypt_values(db::list_default_config_values(tx, None, None).await?)?.into();
     default_values.set_section_options(default_values.generate_section_options());
 
-    let mut env_values: ConfigValues = db::list_config_values(tx, Some(env), None, None)
-        .await?
-        .into();
+    let mut env_values: ConfigValues =
+        secrets::decrypt_values(ops::layered_env_values(tx, env).await?)?.into();
     let section_options: SectionOptions = db::get_feature_flags(tx, env).await?.into();
     env_values.set_section_options(section_options);
 
-    Ok(render_t(
-        template_path,
-        &default_values,
-        &env_values,
-        out_path,
-    )?)
+    Ok(render_t(template_path, &default_values, &env_values, out_path).await?)
 }
 
 /// Renders a directory of templates out to a directory. Uses the defaults and
-/// values files to populate the templates.
-pub fn render_template_dir(
+/// values files to populate the templates. Subdirectories are rendered into
+/// matching subdirectories of the output directory. If `tera_only` is set,
+/// only `.tera` files are rendered and the rest are copied over unchanged.
+pub async fn render_template_dir(
     templates_path: &PathBuf,
     defaults_path: &PathBuf,
     values_path: &PathBuf,
+    tera_only: bool,
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
     let defaults_file = fs::File::open(defaults_path)?;
@@ -139,27 +386,34 @@ pub fn render_template_dir(
         templates_path,
         &defaults_values,
         &values,
+        None,
+        None,
+        tera_only,
         out_path,
-    )?)
+    )
+    .await?)
 }
 
 /// Renders a directory of templates out to a directory, using the defaults and
 /// values queried from the database for the provided environment to populate
-/// the templates.
+/// the templates. If `tera_only` is set, only `.tera` files are rendered.
+/// The revision is recorded in the manifest if the templates were checked out
+/// from a repo.
 pub async fn render_template_dir_from_db(
     tx: &mut Transaction<'_, Postgres>,
     templates_path: &PathBuf,
     env: &str,
+    revision: Option<&str>,
+    tera_only: bool,
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
     let default_values_list: Vec<db::ConfigurationValue> =
-        db::list_default_config_values(tx, None, None).await?;
+        secrets::decrypt_values(db::list_default_config_values(tx, None, None).await?)?;
     let mut default_values: ConfigValues = default_values_list.into();
     default_values.set_section_options(default_values.generate_section_options());
 
-    let mut env_values: ConfigValues = db::list_config_values(tx, Some(env), None, None)
-        .await?
-        .into();
+    let mut env_values: ConfigValues =
+        secrets::decrypt_values(ops::layered_env_values(tx, env).await?)?.into();
     let section_options: SectionOptions = db::get_feature_flags(tx, env).await?.into();
     env_values.set_section_options(section_options);
 
@@ -167,111 +421,1253 @@ pub async fn render_template_dir_from_db(
         templates_path,
         &default_values,
         &env_values,
+        Some(env),
+        revision,
+        tera_only,
         out_path,
-    )?)
+    )
+    .await?)
+}
+
+// Builds the Tera context for an environment from the defaults merged with
+// the environment's values, all read from the database.
+async fn db_context(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+) -> anyhow::Result<tera::Context> {
+    let mut default_values: ConfigValues =
+        secrets::decrypt_values(db::list_default_config_values(tx, None, None).await?)?.into();
+    default_values.set_section_options(default_values.generate_section_options());
+
+    let mut env_values: ConfigValues =
+        secrets::decrypt_values(ops::layered_env_values(tx, env).await?)?.into();
+    let section_options: SectionOptions = db::get_feature_flags(tx, env).await?.into();
+    env_values.set_section_options(section_options);
+
+    default_values = default_values.merge_with(&env_values)?;
+
+    new_context(&default_values).await
+}
+
+// Returns the directory an environment's configs are rendered into, given the
+// output path passed to `render_db()`.
+fn db_output_dir(env: &str, out_path: &Path) -> anyhow::Result<PathBuf> {
+    let mut out_dir = out_path
+        .parent()
+        .context("failed to get the parent directory")?
+        .to_path_buf();
+
+    // Make sure the output directory doesn't contain a template directory
+    // since that gets really confusing when inspecting the output.
+    if let Some(output_dir) = out_dir.file_name() {
+        if output_dir == "templates" {
+            out_dir = out_dir
+                .parent()
+                .context("failed to get the parent directory")?
+                .to_path_buf();
+        }
+    }
+
+    // Make sure the environment sub directory is appended to the output
+    // directory, so that configs for different environments don't
+    // overwrite each other.
+    if let Some(env_dir) = out_dir.file_name() {
+        if env_dir != env {
+            out_dir = out_dir.join(env);
+        }
+    }
+
+    Ok(out_dir)
+}
+
+/// Returns the path a template from the database is rendered to by
+/// `render_db_templates()`, given the output path passed to it. Every template
+/// ends up in the same directory under its file name, which is the layout the
+/// `service-configs` secret is loaded from.
+pub fn db_output_file(env: &str, out_path: &Path, template_path: &str) -> anyhow::Result<PathBuf> {
+    let output_filename = Path::new(template_path)
+        .file_name()
+        .context("failed to get the filename")?
+        .to_str()
+        .context("failed to convert the filename to a string")?;
+
+    Ok(db_output_dir(env, out_path)?.join(output_filename))
+}
+
+/// Turns the render path recorded for a service's template into a relative
+/// path. Absolute render paths, like `/etc/iplant/de/apps.properties`, are
+/// where the file ends up inside the service's container, so the root is
+/// dropped. Render paths that would climb out of a directory are rejected.
+pub fn render_path_file(render_path: &str) -> anyhow::Result<PathBuf> {
+    let mut file = PathBuf::new();
+    for component in Path::new(render_path).components() {
+        match component {
+            Component::Normal(part) => file.push(part),
+            Component::RootDir | Component::CurDir => {}
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "the render path {} can't contain '..'",
+                    render_path
+                ))
+            }
+        }
+    }
+
+    if file.as_os_str().is_empty() {
+        return Err(anyhow::anyhow!(
+            "the render path '{}' doesn't name a file",
+            render_path
+        ));
+    }
+
+    Ok(file)
 }
 
 /// Renders templates returned from the database with values returned
-/// from the database. It's database all the way down.
+/// from the database. It's database all the way down. Every template
+/// associated with the environment is written to the environment's directory,
+/// named by `db_output_file()`, which is the layout that deployments load into
+/// the `service-configs` secret. The revision is recorded in the manifest if
+/// the templates were checked out from a repo.
 pub async fn render_db(
     tx: &mut Transaction<'_, Postgres>,
     env: &str,
     templates_dir: &PathBuf,
+    revision: Option<&str>,
     out_path: &PathBuf,
 ) -> anyhow::Result<()> {
     println!("Rendering templates from values in the database.");
 
     println!("Getting template paths from the database...");
-    let template_paths = db::list_templates(tx, &env).await?;
+    let template_paths = db::list_templates(tx, env).await?;
+
+    write_db_templates(tx, env, templates_dir, revision, out_path, &template_paths).await
+}
+
+/// Renders some of the templates associated with an environment, populating
+/// them with values from the database. The output files are all written to the
+/// environment's directory, named by `db_output_file()`, so that they can be
+/// loaded into the `service-configs` secret.
+pub async fn render_db_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    templates_dir: &Path,
+    out_path: &Path,
+    template_paths: &[String],
+) -> anyhow::Result<()> {
+    write_db_templates(tx, env, templates_dir, None, out_path, template_paths).await
+}
 
+// Renders templates into the layout `db_output_file()` describes and records
+// them in the environment directory's manifest. The manifest's entries for
+// files that aren't rendered from one of the environment's templates any more
+// are dropped.
+async fn write_db_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    templates_dir: &Path,
+    revision: Option<&str>,
+    out_path: &Path,
+    template_paths: &[String],
+) -> anyhow::Result<()> {
     println!("Getting values from the database...");
-    let mut default_values: ConfigValues =
-        db::list_default_config_values(tx, None, None).await?.into();
-    default_values.set_section_options(default_values.generate_section_options());
+    println!("Merging defaults and values...");
+    let defaults_context = db_context(tx, env).await?;
+
+    let outputs = template_paths
+        .iter()
+        .map(|template_path| Ok((template_path, db_output_file(env, out_path, template_path)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    check_collisions(&outputs)?;
+
+    let inputs = render_inputs(&defaults_context, Some(env), revision)?;
+    let env_dir = db_output_dir(env, out_path)?;
+    println!("Creating {}...", env_dir.display());
+    fs::create_dir_all(&env_dir)?;
 
-    let mut env_values: ConfigValues = db::list_config_values(tx, Some(env), None, None)
+    let owned = db::list_templates(tx, env)
         .await?
-        .into();
-    let section_options: SectionOptions = db::get_feature_flags(tx, env).await?.into();
-    env_values.set_section_options(section_options);
+        .iter()
+        .map(|t| manifest::relative_output(&env_dir, &db_output_file(env, out_path, t)?))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut manifest = Manifest::load(&env_dir)?;
+    manifest.retain_outputs(&owned);
 
-    default_values = default_values.merge_with(&env_values)?;
+    let mut tera = new_tera();
+    for (template_path, out_file) in outputs {
+        println!("Creating {}...", out_file.display());
 
-    println!("Merging defaults and values...");
-    let defaults_context = tera::Context::from_serialize(default_values)?;
+        let out_file_str = out_file
+            .to_str()
+            .context("failed to get the output file path")?;
+        let full_template_path = templates_dir.join(template_path);
+        println!(
+            "Rendering {} from template {}...",
+            out_file.display(),
+            full_template_path.display()
+        );
+        let contents = fs::read_to_string(&full_template_path)?;
+        tera.add_raw_template(out_file_str, &contents)?;
+        let rendered = tera.render(out_file_str, &defaults_context)?;
+        fs::write(&out_file, &rendered)?;
+        manifest.record(manifest_entry(
+            &env_dir,
+            &out_file,
+            template_path,
+            contents.as_bytes(),
+            rendered.as_bytes(),
+            &inputs,
+        )?);
+    }
+
+    manifest.save(&env_dir)
+}
+
+/// Returns the templates, out of the ones given, whose rendered output would
+/// be different from the files that were rendered last time. Templates that
+/// haven't been rendered yet count as changed. Nothing is written out.
+pub async fn changed_db_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    templates_dir: &Path,
+    out_path: &Path,
+    template_paths: &[String],
+) -> anyhow::Result<Vec<String>> {
+    let context = db_context(tx, env).await?;
 
     let mut tera = new_tera();
+    let mut changed = Vec::new();
     for template_path in template_paths {
-        let tmpl_path = PathBuf::from(&template_path);
-        let mut out_dir = out_path
-            .parent()
-            .context("failed to get the parent directory")?
-            .to_path_buf();
-
-        // Make sure the output directory doesn't contain a template directory
-        // since that gets really confusing when inspecting the output.
-        if let Some(output_dir) = out_dir.file_name() {
-            if output_dir == "templates" {
-                out_dir = out_dir
-                    .parent()
-                    .context("failed to get the parent directory")?
-                    .to_path_buf();
-            }
+        let full_template_path = templates_dir.join(template_path);
+        tera.add_raw_template(template_path, &fs::read_to_string(&full_template_path)?)?;
+        let rendered = tera.render(template_path, &context)?;
+
+        let out_file = db_output_file(env, out_path, template_path)?;
+        let current = fs::read_to_string(&out_file).ok();
+        if current.as_deref() != Some(rendered.as_str()) {
+            changed.push(template_path.clone());
         }
+    }
 
-        // Make sure the environment sub directory is appended to the output
-        // directory, so that configs for different environments don't
-        // overwrite each other.
-        if let Some(env_dir) = out_dir.file_name() {
-            if env_dir != env {
-                out_dir = out_dir.join(env);
-            }
+    Ok(changed)
+}
+
+/// The name of the secret the rendered config files are loaded into.
+const SERVICE_CONFIGS: &str = "service-configs";
+
+// Returns the decrypted values of the sensitive settings in an environment,
+// with references to secrets stored elsewhere resolved.
+async fn sensitive_values(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+) -> anyhow::Result<Vec<String>> {
+    let masker = ops::new_masker(tx, false).await?;
+    let mut cfgs = db::list_default_config_values(tx, None, None).await?;
+    cfgs.extend(ops::layered_env_values(tx, env).await?);
+
+    let resolvers = Resolvers::with_defaults();
+    let mut values = Vec::new();
+    for cfg in secrets::decrypt_values(cfgs)? {
+        if masker.is_sensitive(&cfg) && !cfg.value.is_empty() {
+            values.push(resolvers.resolve(&cfg.value).await?.unwrap_or(cfg.value));
+        }
+    }
+
+    Ok(values)
+}
+
+// Prints a diff between two versions of a file, masking the secrets in it.
+// Returns whether they were different.
+fn print_diff(old_label: &str, new_label: &str, old: &str, new: &str, masker: &TextMasker) -> bool {
+    let lines = match diff::unified(old_label, new_label, old, new, 3) {
+        Some(lines) => lines,
+        None => return false,
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        if i < 2 || line.starts_with("@@") {
+            println!("{}", line);
+        } else {
+            let (marker, rest) = line.split_at(1);
+            println!("{}{}", marker, masker.mask_line(rest));
         }
+    }
+
+    true
+}
+
+// Diffs sets of named files against each other, labelling them with where
+// they came from. Returns the number of files that were different.
+fn print_diffs(
+    kind: &str,
+    current: &BTreeMap<String, String>,
+    rendered: &BTreeMap<String, String>,
+    masker: &TextMasker,
+) -> usize {
+    let names: BTreeSet<&String> = current.keys().chain(rendered.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| {
+            let old_label = match current.contains_key(*name) {
+                true => format!("cluster/{}/{}", kind, name),
+                false => "/dev/null".to_string(),
+            };
+            let new_label = match rendered.contains_key(*name) {
+                true => format!("rendered/{}/{}", kind, name),
+                false => "/dev/null".to_string(),
+            };
+            print_diff(
+                &old_label,
+                &new_label,
+                current.get(*name).map_or("", |s| s.as_str()),
+                rendered.get(*name).map_or("", |s| s.as_str()),
+                masker,
+            )
+        })
+        .count()
+}
+
+// Formats the entries of a secret as text that can be diffed.
+fn secret_text(entries: &BTreeMap<String, String>) -> String {
+    entries
+        .iter()
+        .map(|(key, value)| format!("{}: {}\n", key, value))
+        .collect()
+}
+
+// Formats the entries of the version of a secret in the cluster, if there is
+// one, and the rendered version for diffing. Everything in a secret is
+// sensitive, so when masking every value is hidden, however short, and the
+// entries whose values changed are marked instead.
+fn secret_texts(
+    current: Option<&BTreeMap<String, String>>,
+    rendered: &BTreeMap<String, String>,
+    mask: bool,
+) -> (String, String) {
+    let empty = BTreeMap::new();
+    let current = current.unwrap_or(&empty);
+    if !mask {
+        return (secret_text(current), secret_text(rendered));
+    }
+
+    let current_text = current
+        .keys()
+        .map(|key| format!("{}: {}\n", key, secrets::MASK))
+        .collect();
+    let rendered_text = rendered
+        .iter()
+        .map(|(key, value)| match current.get(key) {
+            Some(old) if old != value => format!("{}: {} (changed)\n", key, secrets::MASK),
+            _ => format!("{}: {}\n", key, secrets::MASK),
+        })
+        .collect();
+
+    (current_text, rendered_text)
+}
+
+/// Renders the templates associated with an environment and shows how the
+/// results differ from what's loaded in the environment's namespace: the
+/// `service-configs` secret holding the config files, and the secrets defined
+/// by the manifests in `templates/secrets`. Everything is rendered in memory,
+/// so nothing is written out. Secrets are masked unless `show_secrets` is
+/// true. Returns the number of files that are different.
+pub async fn diff_cluster(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    templates_dir: &Path,
+    show_secrets: bool,
+    kube_context: Option<&str>,
+) -> anyhow::Result<usize> {
+    let namespace = db::get_namespace(tx, env).await?;
+    let context = db_context(tx, env).await?;
+    let mut tera = new_tera();
+
+    let template_paths = db::list_templates(tx, env).await?;
+    let outputs = template_paths
+        .iter()
+        .map(|template_path| {
+            Ok((
+                template_path,
+                db_output_file(env, Path::new(env), template_path)?,
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    check_collisions(&outputs)?;
 
-        let output_filename = tmpl_path
+    let mut rendered: BTreeMap<String, String> = BTreeMap::new();
+    for (template_path, out_file) in outputs {
+        let full_template_path = templates_dir.join(template_path);
+        tera.add_raw_template(template_path, &fs::read_to_string(&full_template_path)?)?;
+        let name = out_file
             .file_name()
             .context("failed to get the filename")?
-            .to_str()
-            .context("failed to convert the filename to a string")?;
-        let out_file = out_dir.join(&output_filename);
+            .to_string_lossy()
+            .to_string();
+        rendered.insert(name, tera.render(template_path, &context)?);
+    }
+    let current =
+        configs::get_secret(&namespace, SERVICE_CONFIGS, kube_context)?.unwrap_or_default();
 
-        println!("Creating {}...", out_dir.display());
-        fs::create_dir_all(out_dir)?;
+    let mut rendered_secrets: BTreeMap<String, String> = BTreeMap::new();
+    let mut current_secrets: BTreeMap<String, String> = BTreeMap::new();
+    let mut secret_values: Vec<String> = Vec::new();
+    let secrets_dir = templates_dir.join("templates").join("secrets");
+    if secrets_dir.is_dir() {
+        let mut manifests: Vec<PathBuf> = fs::read_dir(&secrets_dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        manifests.sort();
 
-        println!("Creating {}...", out_file.display());
-        let out_writer = fs::File::create(&out_file)?;
+        for manifest in manifests {
+            let name = manifest.display().to_string();
+            tera.add_raw_template(&name, &fs::read_to_string(&manifest)?)?;
+            for (secret, entries) in configs::manifest_secrets(&tera.render(&name, &context)?)? {
+                let live = configs::get_secret(&namespace, &secret, kube_context)?;
+                let (current_text, rendered_text) =
+                    secret_texts(live.as_ref(), &entries, !show_secrets);
+                if let Some(live) = live {
+                    secret_values.extend(live.into_values());
+                    current_secrets.insert(secret.clone(), current_text);
+                }
+                secret_values.extend(entries.into_values());
+                rendered_secrets.insert(secret, rendered_text);
+            }
+        }
+    }
 
-        let out_file_str = out_file
-            .to_str()
-            .context("failed to get the output file path")?;
-        let full_template_path = templates_dir.join(&template_path);
+    let mut masker = TextMasker::default();
+    if !show_secrets {
+        secret_values.extend(sensitive_values(tx, env).await?);
+        masker = TextMasker::new(secret_values);
+        rendered
+            .values()
+            .chain(current.values())
+            .for_each(|text| masker.learn(text));
+    }
+
+    let changed = print_diffs(SERVICE_CONFIGS, &current, &rendered, &masker)
+        + print_diffs("secrets", &current_secrets, &rendered_secrets, &masker);
+
+    if changed == 0 {
         println!(
-            "Rendering {} from template {}...",
-            out_file.display(),
-            full_template_path.display()
+            "The rendered configs for {} match what's in the {} namespace.",
+            env, namespace
+        );
+    } else {
+        println!(
+            "{} file(s) differ from what's in the {} namespace.",
+            changed, namespace
         );
-        tera.add_raw_template(&out_file_str, &fs::read_to_string(&full_template_path)?)?;
-        tera.render_to(&out_file_str, &defaults_context, out_writer)?;
+    }
+
+    Ok(changed)
+}
+
+/// Scans the templates associated with the services in an environment for
+/// the config settings they refer to. The references are stored for each
+/// template, and the links between the environment's services and its config
+/// values are rebuilt from them. Templates are read from the templates
+/// directory. Missing templates are skipped with a warning.
+pub async fn scan_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    templates_dir: &Path,
+) -> anyhow::Result<()> {
+    let templates = db::list_env_service_templates(tx, env).await?;
+
+    // The settings are matched against the values that apply to the
+    // environment, including the ones inherited from its parents, so that
+    // services are linked to the values they're actually rendered with.
+    let defaults = db::list_default_config_values(tx, None, None).await?;
+    let layered: Vec<db::ConfigurationValue> = ops::layered_env_values(tx, env)
+        .await?
+        .into_iter()
+        .filter(|cfg| !cfg.value.is_empty())
+        .collect();
+    let value_ids: Vec<i32> = layered.iter().map(|cfg| cfg.id).collect();
+    let default_ids: Vec<i32> = defaults
+        .iter()
+        .filter(|d| {
+            !layered
+                .iter()
+                .any(|cfg| cfg.section == d.section && cfg.key == d.key)
+        })
+        .map(|d| d.id)
+        .collect();
+
+    let known: Vec<(String, String)> = defaults
+        .iter()
+        .chain(layered.iter())
+        .map(|cfg| (cfg.section.clone(), cfg.key.clone()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut seen: BTreeSet<i32> = BTreeSet::new();
+    let mut scanned = 0;
+    for template in &templates {
+        if !seen.insert(template.template_id) {
+            continue;
+        }
+
+        let full_template_path = templates_dir.join(&template.template_path);
+        if !full_template_path.exists() {
+            println!(
+                "Skipping {}, it doesn't exist.",
+                full_template_path.display()
+            );
+            continue;
+        }
+
+        let contents = fs::read_to_string(&full_template_path)?;
+        let idents = scan::referenced_variables(&template.template_path, &contents)?;
+        let references: Vec<(String, String)> = scan::match_references(&idents, &known)
+            .into_iter()
+            .collect();
+        db::set_template_references(tx, template.template_id, &references).await?;
+        scanned += 1;
+
+        println!(
+            "{} refers to {} setting(s).",
+            template.template_path,
+            references.len()
+        );
+    }
+
+    let links = db::link_env_service_cfg_values(tx, env, &value_ids, &default_ids).await?;
+    println!(
+        "Scanned {} template(s) and linked {} config value(s) to services in {}.",
+        scanned, links, env
+    );
+
+    Ok(())
+}
+
+/// Lints the templates in a directory against the config settings they can
+/// use, printing out the problems found. If an environment is given, its
+/// feature flags decide which optional sections are available. Returns an
+/// error if any of the problems would break rendering.
+pub async fn lint_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    templates_dir: &Path,
+    env: Option<&str>,
+) -> anyhow::Result<()> {
+    let section_options: Option<SectionOptions> = match env {
+        Some(env) => Some(db::get_feature_flags(tx, env).await?.into()),
+        None => None,
+    };
+    let schema = lint::Schema::new(section_options)?;
+    let issues = lint::lint_dir(templates_dir, &schema)?;
+
+    if issues.is_empty() {
+        println!(
+            "No problems found in the templates in {}.",
+            templates_dir.display()
+        );
+        return Ok(());
+    }
+
+    println!(
+        "Problems found in the templates in {}:",
+        templates_dir.display()
+    );
+    for issue in &issues {
+        println!("  {}", issue);
+    }
+
+    let errors = issues.iter().filter(|i| i.kind.is_error()).count();
+    if errors > 0 {
+        return Err(anyhow::anyhow!(
+            "{} problem(s) found in the templates in {} that would break rendering",
+            errors,
+            templates_dir.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the repo given with `--repo-id`, if there is one, along with the
+/// revision given with `--revision`.
+pub async fn repo_from_args(
+    tx: &mut Transaction<'_, Postgres>,
+    sub_m: &ArgMatches,
+) -> anyhow::Result<Option<(db::Repository, Option<String>)>> {
+    let repo_id = match sub_m.get_one::<i32>("repo-id") {
+        Some(repo_id) => *repo_id,
+        None => return Ok(None),
+    };
+
+    let repo = db::get_repo_by_id(tx, repo_id)
+        .await
+        .with_context(|| format!("no repo with the ID {}", repo_id))?;
+    Ok(Some((repo, sub_m.get_one::<String>("revision").cloned())))
+}
+
+// Checks out a revision of a repo, saying what's being read.
+fn checkout_revision(url: &str, revision: &str) -> anyhow::Result<RevisionCheckout> {
+    println!("Checking out {} at {}...", url, revision);
+    let checkout = RevisionCheckout::new(url, revision)?;
+    println!("Reading templates from commit {}.", checkout.commit);
+    Ok(checkout)
+}
+
+/// Checks out the revision of a repo that templates are read from, when a
+/// repo is given with `--repo-id`. The revision is `--revision` if it's given
+/// and the one recorded for the repo in the database otherwise. The checkout
+/// is removed when it's dropped.
+pub async fn checkout_from_args(
+    tx: &mut Transaction<'_, Postgres>,
+    sub_m: &ArgMatches,
+) -> anyhow::Result<Option<RevisionCheckout>> {
+    match repo_from_args(tx, sub_m).await? {
+        Some((repo, revision)) => Ok(Some(checkout_revision(
+            &repo.url,
+            revision.as_deref().unwrap_or(&repo.revision),
+        )?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns where a templates path given on the command line is. It's inside
+/// the checkout if templates are read from a revision of a repo.
+pub fn checkout_path(checkout: Option<&RevisionCheckout>, templates_path: &Path) -> PathBuf {
+    match checkout {
+        Some(checkout) => checkout.path.join(templates_path),
+        None => templates_path.to_path_buf(),
+    }
+}
+
+/// Checks the files recorded in a directory's render manifest. Files that were
+/// edited after they were rendered, are missing, or are out of date because
+/// their template or the values for their environment changed are reported.
+/// Template paths are looked up in the templates directory. If a repo is
+/// given, the templates directory is a path in it, and each file's template is
+/// read from the commit recorded for the file, unless a revision is given.
+/// Files without a recorded commit use the repo's revision in the database.
+/// Fails if any problems are found.
+pub async fn verify_render(
+    tx: &mut Transaction<'_, Postgres>,
+    dir: &Path,
+    templates_path: &Path,
+    repo: Option<(&db::Repository, Option<&str>)>,
+) -> anyhow::Result<()> {
+    if !dir.join(manifest::MANIFEST_FILE).exists() {
+        return Err(anyhow::anyhow!(
+            "there's no {} in {}",
+            manifest::MANIFEST_FILE,
+            dir.display()
+        ));
+    }
+    let manifest = Manifest::load(dir)?;
+
+    // The revision each file's template is read from, if it comes from a repo.
+    let entry_revision = |entry: &ManifestEntry| {
+        repo.map(|(repo, revision)| {
+            revision
+                .or(entry.revision.as_deref())
+                .unwrap_or(&repo.revision)
+                .to_string()
+        })
+    };
+    let mut checkouts: BTreeMap<String, RevisionCheckout> = BTreeMap::new();
+    if let Some((repo, _)) = repo {
+        for revision in manifest.files.iter().filter_map(entry_revision) {
+            if let btree_map::Entry::Vacant(e) = checkouts.entry(revision) {
+                let checkout = checkout_revision(&repo.url, e.key())?;
+                e.insert(checkout);
+            }
+        }
+    }
+
+    let mut env_hashes: BTreeMap<String, Option<String>> = BTreeMap::new();
+    let mut problems: Vec<String> = Vec::new();
+    for entry in &manifest.files {
+        match fs::read(dir.join(&entry.output)) {
+            Ok(output) if manifest::hash(&output) != entry.output_hash => {
+                problems.push(format!("{}: edited since it was rendered", entry.output))
+            }
+            Ok(_) => {}
+            Err(_) => problems.push(format!("{}: missing", entry.output)),
+        }
+
+        let templates_dir = match entry_revision(entry) {
+            Some(revision) => checkouts[&revision].path.join(templates_path),
+            None => templates_path.to_path_buf(),
+        };
+        let template_path = templates_dir.join(&entry.template);
+        match fs::read(&template_path) {
+            Ok(template) if manifest::hash(&template) != entry.template_hash => {
+                problems.push(format!(
+                    "{}: out of date, {} has changed",
+                    entry.output, entry.template
+                ))
+            }
+            Ok(_) => {}
+            Err(_) => problems.push(format!(
+                "{}: its template {} doesn't exist",
+                entry.output,
+                template_path.display()
+            )),
+        }
+
+        if let (Some(env), Some(values_hash)) = (&entry.environment, &entry.values_hash) {
+            if !env_hashes.contains_key(env) {
+                let hash = match db::has_env(tx, env).await? {
+                    true => {
+                        render_inputs(&db_context(tx, env).await?, Some(env), None)?.values_hash
+                    }
+                    false => None,
+                };
+                env_hashes.insert(env.clone(), hash);
+            }
+
+            match &env_hashes[env] {
+                Some(hash) if hash != values_hash => problems.push(format!(
+                    "{}: out of date, the values for {} have changed",
+                    entry.output, env
+                )),
+                Some(_) => {}
+                None => problems.push(format!(
+                    "{}: the environment {} doesn't exist anymore",
+                    entry.output, env
+                )),
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!(
+            "The {} file(s) rendered into {} are up to date.",
+            manifest.files.len(),
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    println!("Problems found in {}:", dir.display());
+    for problem in &problems {
+        println!("  {}", problem);
+    }
+
+    Err(anyhow::anyhow!(
+        "{} problem(s) found with the files rendered into {}",
+        problems.len(),
+        dir.display()
+    ))
+}
+
+// Checks that a deployment can honour a template's render path for a service.
+// Deployments load every template into the service-configs secret under its
+// file name, and the service's deployment mounts that entry at the render
+// path. A different template with the same file name would take its place in
+// the secret, and a different template at the same render path would be
+// mounted over it. The template's ID is None if it isn't in the database yet.
+fn check_deployable(
+    env: &str,
+    env_templates: &[db::ServiceTemplate],
+    svc_name: &str,
+    template_id: Option<i32>,
+    template_path: &str,
+    render_path: &str,
+) -> anyhow::Result<()> {
+    let file_name = Path::new(template_path).file_name();
+    let render_file = render_path_file(render_path)?;
+
+    for t in env_templates
+        .iter()
+        .filter(|t| Some(t.template_id) != template_id)
+    {
+        if Path::new(&t.template_path).file_name() == file_name {
+            return Err(anyhow::anyhow!(
+                "{} has the same file name as {}, which is already used in {}, so a deployment can't load both into the service-configs secret",
+                template_path,
+                t.template_path,
+                env
+            ));
+        }
+        if t.service_name == svc_name && render_path_file(&t.render_path)? == render_file {
+            return Err(anyhow::anyhow!(
+                "{} is already rendered to {} for {}",
+                t.template_path,
+                t.render_path,
+                svc_name
+            ));
+        }
     }
 
     Ok(())
 }
 
+/// Associates templates with a service in an environment. The rendered
+/// configs go to the render path if one is given, which only works for a
+/// single template, and to each template's file name otherwise. Render paths
+/// that a deployment couldn't honour are rejected, see `check_deployable()`.
 pub async fn assoc_template(
     tx: &mut Transaction<'_, Postgres>,
     env: &str,
     repo_id: i32,
     svc_name: &str,
     templates: &[PathBuf],
+    render_path: Option<&str>,
 ) -> anyhow::Result<()> {
+    if let Some(render_path) = render_path {
+        if templates.len() > 1 {
+            return Err(anyhow::anyhow!(
+                "--render-path can only be used when associating a single template"
+            ));
+        }
+        render_path_file(render_path)?;
+    }
+
     for template in templates {
-        let render_path = template
-            .file_name()
-            .context("failed to get the filename")?
+        let render_path = match render_path {
+            Some(render_path) => render_path,
+            None => template
+                .file_name()
+                .context("failed to get the filename")?
+                .to_str()
+                .context("failed to convert the filename to a string")?,
+        };
+        let template_path = template
             .to_str()
-            .context("failed to convert the filename to a string")?;
-        let tmpl_id = db::add_template(tx, repo_id, template).await?;
+            .context("failed to convert the template path to a string")?;
+        let tmpl_id = db::get_template_id(tx, repo_id, template_path).await?;
+        let env_templates = db::list_env_service_templates(tx, env).await?;
+        check_deployable(
+            env,
+            &env_templates,
+            svc_name,
+            tmpl_id,
+            template_path,
+            render_path,
+        )?;
+
+        let tmpl_id = match tmpl_id {
+            Some(tmpl_id) => tmpl_id,
+            None => db::add_template(tx, repo_id, template).await?,
+        };
         db::add_template_to_service(tx, env, svc_name, tmpl_id, render_path).await?;
     }
     Ok(())
 }
+
+// Points out a template that's no longer associated with any service.
+async fn note_orphan(
+    tx: &mut Transaction<'_, Postgres>,
+    repo_id: i32,
+    template_path: &str,
+) -> anyhow::Result<()> {
+    if db::count_template_uses(tx, repo_id, template_path).await? == 0 {
+        println!(
+            "{} isn't used by any service now. Use `mgmt templates rm` to remove it.",
+            template_path
+        );
+    }
+    Ok(())
+}
+
+/// Removes the associations between templates in a repo and a service in an
+/// environment. Templates that end up without any services are pointed out.
+pub async fn dissoc_template(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    repo_id: i32,
+    svc_name: &str,
+    templates: &[String],
+) -> anyhow::Result<()> {
+    for template in templates {
+        if db::remove_template_from_service(tx, env, svc_name, repo_id, template).await? == 0 {
+            return Err(anyhow::anyhow!(
+                "{} isn't associated with {} in {}",
+                template,
+                svc_name,
+                env
+            ));
+        }
+        println!("Removed {} from {} in {}.", template, svc_name, env);
+        note_orphan(tx, repo_id, template).await?;
+    }
+    Ok(())
+}
+
+/// Removes templates in a repo from the database. Templates that are still
+/// associated with services are refused unless `force` is true, in which case
+/// the associations go too.
+pub async fn remove_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    repo_id: i32,
+    templates: &[String],
+    force: bool,
+) -> anyhow::Result<()> {
+    for template in templates {
+        let uses = db::count_template_uses(tx, repo_id, template).await?;
+        if uses > 0 && !force {
+            return Err(anyhow::anyhow!(
+                "{} is still used by {} service(s). Use `mgmt templates dissoc` first, or pass --force.",
+                template,
+                uses
+            ));
+        }
+
+        if db::delete_template(tx, repo_id, template).await? == 0 {
+            return Err(anyhow::anyhow!(
+                "there's no template at {} in repo {}",
+                template,
+                repo_id
+            ));
+        }
+        println!("Removed {}.", template);
+    }
+    Ok(())
+}
+
+/// Changes the path of a template in a repo, keeping its associations with
+/// services. If a templates directory is given, the new path has to be a file
+/// in it. A new path that a deployment couldn't honour in one of the
+/// environments using the template is rejected, see `check_deployable()`.
+pub async fn move_template(
+    tx: &mut Transaction<'_, Postgres>,
+    repo_id: i32,
+    from_path: &str,
+    to_path: &str,
+    templates_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    if db::get_template_id(tx, repo_id, to_path).await?.is_some() {
+        return Err(anyhow::anyhow!(
+            "there's already a template at {} in repo {}",
+            to_path,
+            repo_id
+        ));
+    }
+
+    if let Some(templates_dir) = templates_dir {
+        let full_path = templates_dir.join(to_path);
+        if !full_path.is_file() {
+            return Err(anyhow::anyhow!("{} doesn't exist", full_path.display()));
+        }
+    }
+
+    let template_id = db::get_template_id(tx, repo_id, from_path)
+        .await?
+        .with_context(|| format!("there's no template at {} in repo {}", from_path, repo_id))?;
+    for env in db::list_envs(tx).await? {
+        let env_templates = db::list_env_service_templates(tx, &env).await?;
+        for t in env_templates
+            .iter()
+            .filter(|t| t.template_id == template_id)
+        {
+            check_deployable(
+                &env,
+                &env_templates,
+                &t.service_name,
+                Some(template_id),
+                to_path,
+                &t.render_path,
+            )?;
+        }
+    }
+
+    db::move_template(tx, repo_id, from_path, to_path).await?;
+    println!("Moved {} to {}.", from_path, to_path);
+
+    Ok(())
+}
+
+/// Copies the template associations of the services in one environment to the
+/// same services in another environment, keeping their render paths. Only the
+/// given service's templates are copied if there is one. Services that aren't
+/// in the target environment are skipped, as are associations it already has.
+/// Nothing is copied if any of the associations couldn't be deployed in the
+/// target environment, see `check_deployable()`.
+pub async fn copy_templates(
+    tx: &mut Transaction<'_, Postgres>,
+    from_env: &str,
+    to_env: &str,
+    svc_name: Option<&str>,
+) -> anyhow::Result<()> {
+    for env in [from_env, to_env] {
+        if !db::has_env(tx, env).await? {
+            return Err(anyhow::anyhow!("no environment named {}", env));
+        }
+    }
+
+    let to_services: BTreeSet<String> = db::list_services(tx, to_env)
+        .await?
+        .into_iter()
+        .map(|svc| svc.name)
+        .collect();
+    if let Some(svc_name) = svc_name {
+        if !to_services.contains(svc_name) {
+            return Err(anyhow::anyhow!("{} isn't in {}", svc_name, to_env));
+        }
+    }
+
+    let mut to_copy = Vec::new();
+    let mut skipped: BTreeSet<String> = BTreeSet::new();
+    let mut to_templates = db::list_env_service_templates(tx, to_env).await?;
+    for t in db::list_env_service_templates(tx, from_env).await? {
+        if svc_name.is_some_and(|name| name != t.service_name) {
+            continue;
+        }
+        if !to_services.contains(&t.service_name) {
+            skipped.insert(t.service_name);
+            continue;
+        }
+        if db::has_service_template(tx, to_env, &t.service_name, t.template_id).await? {
+            continue;
+        }
+
+        check_deployable(
+            to_env,
+            &to_templates,
+            &t.service_name,
+            Some(t.template_id),
+            &t.template_path,
+            &t.render_path,
+        )?;
+        to_templates.push(t.clone());
+        to_copy.push(t);
+    }
+
+    for t in &to_copy {
+        db::add_template_to_service(tx, to_env, &t.service_name, t.template_id, &t.render_path)
+            .await?;
+        println!(
+            "Associated {} with {} in {}.",
+            t.template_path, t.service_name, to_env
+        );
+    }
+    let copied = to_copy.len();
+
+    for svc in &skipped {
+        println!("Skipped {}, which isn't in {}.", svc, to_env);
+    }
+    println!(
+        "Copied {} template association(s) from {} to {}.",
+        copied, from_env, to_env
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Creates an empty directory for a test to work in.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mgmt-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_secret_texts() {
+        let current = BTreeMap::from([
+            ("pin".to_string(), "123".to_string()),
+            ("user".to_string(), "de".to_string()),
+        ]);
+        let rendered = BTreeMap::from([
+            ("pin".to_string(), "456".to_string()),
+            ("token".to_string(), "x".to_string()),
+            ("user".to_string(), "de".to_string()),
+        ]);
+
+        let (old, new) = secret_texts(Some(&current), &rendered, true);
+        assert_eq!(old, "pin: ********\nuser: ********\n");
+        assert_eq!(
+            new,
+            "pin: ******** (changed)\ntoken: ********\nuser: ********\n"
+        );
+
+        let (old, new) = secret_texts(None, &rendered, false);
+        assert_eq!(old, "");
+        assert_eq!(new, "pin: 456\ntoken: x\nuser: de\n");
+    }
+
+    #[test]
+    fn test_check_collisions() {
+        let ok = vec![
+            ("a/app.conf", PathBuf::from("out/a/app.conf")),
+            ("b/app.conf", PathBuf::from("out/b/app.conf")),
+        ];
+        assert!(check_collisions(&ok).is_ok());
+
+        let clash = vec![
+            ("a/app.conf", PathBuf::from("out/app.conf")),
+            ("b/app.conf", PathBuf::from("out/app.conf")),
+        ];
+        let err = check_collisions(&clash).unwrap_err().to_string();
+        assert!(err.contains("a/app.conf and b/app.conf would both be written to out/app.conf"));
+    }
+
+    #[test]
+    fn test_render_path_file() {
+        assert_eq!(
+            render_path_file("/etc/iplant/de/apps.properties").unwrap(),
+            PathBuf::from("etc/iplant/de/apps.properties")
+        );
+        assert_eq!(
+            render_path_file("./conf/app.yml").unwrap(),
+            PathBuf::from("conf/app.yml")
+        );
+        assert!(render_path_file("../app.yml").is_err());
+        assert!(render_path_file("/").is_err());
+
+        assert_eq!(
+            db_output_file("de", Path::new("out/de/x"), "templates/sonora.yaml").unwrap(),
+            PathBuf::from("out/de/sonora.yaml")
+        );
+    }
+
+    #[test]
+    fn test_check_deployable() {
+        let template = |service: &str, template_id: i32, template_path: &str, render_path: &str| {
+            db::ServiceTemplate {
+                service_name: service.to_string(),
+                template_id,
+                template_path: template_path.to_string(),
+                render_path: render_path.to_string(),
+            }
+        };
+        let env_templates = vec![
+            template(
+                "apps",
+                1,
+                "templates/apps.properties",
+                "/etc/iplant/de/apps.properties",
+            ),
+            template(
+                "qms",
+                2,
+                "templates/jobservices.yml",
+                "/etc/cyverse/de/configs/service.yml",
+            ),
+        ];
+
+        // Sharing a template, or a render path across services, is fine.
+        assert!(check_deployable(
+            "de",
+            &env_templates,
+            "analyses",
+            Some(1),
+            "templates/apps.properties",
+            "/etc/iplant/de/apps.properties"
+        )
+        .is_ok());
+        assert!(check_deployable(
+            "de",
+            &env_templates,
+            "apps",
+            Some(2),
+            "templates/jobservices.yml",
+            "/etc/cyverse/de/configs/service.yml"
+        )
+        .is_ok());
+
+        // Another template with the same file name, even at the same path in
+        // another repo, would replace it in the service-configs secret.
+        assert!(check_deployable(
+            "de",
+            &env_templates,
+            "analyses",
+            None,
+            "other/apps.properties",
+            "/etc/iplant/de/analyses.properties"
+        )
+        .is_err());
+        assert!(check_deployable(
+            "de",
+            &env_templates,
+            "analyses",
+            None,
+            "templates/apps.properties",
+            "/etc/iplant/de/analyses.properties"
+        )
+        .is_err());
+
+        // Another template at the same render path for the service.
+        assert!(check_deployable(
+            "de",
+            &env_templates,
+            "qms",
+            None,
+            "templates/qms.yml",
+            "etc/cyverse/de/configs/service.yml"
+        )
+        .is_err());
+        assert!(check_deployable(
+            "de",
+            &env_templates,
+            "qms",
+            None,
+            "templates/qms.yml",
+            "../service.yml"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_render_dir() {
+        let dir = test_dir("render-dir");
+        let src = dir.join("src");
+        let out = dir.join("out");
+        fs::create_dir_all(src.join("nested/deeper")).unwrap();
+        fs::create_dir_all(src.join(".git")).unwrap();
+        fs::write(src.join("top.conf.tera"), "env={{ env }}").unwrap();
+        fs::write(
+            src.join("nested/deeper/app.yaml.tera"),
+            "{% include \"top.conf.tera\" %}!",
+        )
+        .unwrap();
+        fs::write(src.join("nested/plain.txt"), "{{ env }} stays").unwrap();
+        fs::write(src.join("nested/logo.png"), [0x89, 0x50, 0xff, 0x00]).unwrap();
+        fs::write(src.join(".git/HEAD"), "ignored").unwrap();
+
+        let mut context = tera::Context::new();
+        context.insert("env", "qa");
+        render_dir(&src.join("*"), &context, None, None, true, &out).unwrap();
+
+        assert_eq!(fs::read_to_string(out.join("top.conf")).unwrap(), "env=qa");
+        assert_eq!(
+            fs::read_to_string(out.join("nested/deeper/app.yaml")).unwrap(),
+            "env=qa!"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("nested/plain.txt")).unwrap(),
+            "{{ env }} stays"
+        );
+        assert_eq!(
+            fs::read(out.join("nested/logo.png")).unwrap(),
+            vec![0x89, 0x50, 0xff, 0x00]
+        );
+        assert!(!out.join(".git").exists());
+
+        fs::write(src.join("top.conf"), "clash").unwrap();
+        assert!(render_dir(&src, &context, None, None, true, &out).is_err());
+
+        // By default every file is a template, so binary files fail.
+        fs::remove_file(src.join("top.conf")).unwrap();
+        assert!(render_dir(&src, &context, None, None, false, &out).is_err());
+        fs::remove_file(src.join("nested/logo.png")).unwrap();
+        render_dir(&src, &context, None, None, false, &out).unwrap();
+        assert_eq!(
+            fs::read_to_string(out.join("nested/plain.txt")).unwrap(),
+            "qa stays"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_looks_like_template() {
+        assert!(looks_like_template(b"name: {{ env }}"));
+        assert!(looks_like_template(b"{% if agave %}on{% endif %}"));
+        assert!(looks_like_template(b"{# note #}"));
+        assert!(!looks_like_template(b"plain: text"));
+        assert!(!looks_like_template(&[0x7b, 0x7b, 0xff, 0xfe]));
+    }
+}
@@ -0,0 +1,304 @@
+//! # Template filters
+//!
+//! The filters every Tera instance used to render configs has registered, on
+//! top of Tera's built-in ones:
+//!
+//! - `base64_encode` and `base64_decode`
+//! - `to_json` (`pretty=true` to indent it) and `to_yaml`
+//! - `sha256`, the hex digest of a string
+//! - `indent(width=4)` indents every line, for YAML blocks. Without a width it
+//!   behaves like Tera's own `indent`, taking `prefix`, `first`, and `blank`.
+//! - `nindent(width=4)` is `indent` with a newline in front
+//! - `quote` wraps a value in double quotes, escaping what's inside
+//! - `required(msg="...")` fails rendering if the value is null or empty
+//!
+//! Tera treats `default` specially: it's used when a variable is undefined or
+//! null and can't be replaced, so it's left as it is. Tera's `urlencode` is
+//! left alone too, since it keeps `/` for paths. Use Tera's `urlencode_strict`
+//! to put passwords into connection strings; it encodes everything but
+//! letters and digits.
+use crate::manifest;
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::HashMap;
+use tera::{to_value, try_get_value, Error, Result, Tera, Value};
+
+/// Registers the filters with a Tera instance.
+pub fn register(tera: &mut Tera) {
+    tera.register_filter("base64_encode", base64_encode);
+    tera.register_filter("base64_decode", base64_decode);
+    tera.register_filter("to_json", to_json);
+    tera.register_filter("to_yaml", to_yaml);
+    tera.register_filter("sha256", sha256);
+    tera.register_filter("indent", indent);
+    tera.register_filter("nindent", nindent);
+    tera.register_filter("quote", quote);
+    tera.register_filter("required", required);
+}
+
+// Returns a value as a string. Strings are used as they are and anything else
+// is converted to JSON, so numbers and booleans come out the way they're
+// written.
+fn as_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Base64 encodes a string.
+pub fn base64_encode(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("base64_encode", "value", String, value);
+    Ok(to_value(general_purpose::STANDARD.encode(s.as_bytes()))?)
+}
+
+/// Decodes a base64 encoded string. The result has to be UTF-8.
+pub fn base64_decode(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("base64_decode", "value", String, value);
+    let decoded = general_purpose::STANDARD
+        .decode(s.trim())
+        .map_err(|e| Error::msg(format!("base64_decode: {}", e)))?;
+    let decoded = String::from_utf8(decoded)
+        .map_err(|_| Error::msg("base64_decode: the decoded value isn't UTF-8"))?;
+    Ok(to_value(decoded)?)
+}
+
+/// Serializes a value as JSON. Pass `pretty=true` to indent it.
+pub fn to_json(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let pretty = match args.get("pretty") {
+        Some(p) => try_get_value!("to_json", "pretty", bool, p),
+        None => false,
+    };
+    let json = if pretty {
+        serde_json::to_string_pretty(value)?
+    } else {
+        serde_json::to_string(value)?
+    };
+    Ok(to_value(json)?)
+}
+
+/// Serializes a value as YAML, without a trailing newline.
+pub fn to_yaml(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    let yaml = serde_yaml::to_string(value).map_err(|e| Error::msg(format!("to_yaml: {}", e)))?;
+    Ok(to_value(yaml.trim_end())?)
+}
+
+/// Returns the hex encoded SHA-256 digest of a string.
+pub fn sha256(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    Ok(to_value(manifest::hash(as_string(value).as_bytes()))?)
+}
+
+/// Indents each line of a string. With `width`, every line gets that many
+/// spaces. Otherwise the `prefix` (four spaces by default) goes in front of
+/// every line but the first, unless `first=true`. Blank lines are left alone
+/// unless `blank=true`.
+pub fn indent(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let s = try_get_value!("indent", "value", String, value);
+
+    let width = match args.get("width") {
+        Some(w) => Some(try_get_value!("indent", "width", usize, w)),
+        None => None,
+    };
+    let prefix = match (width, args.get("prefix")) {
+        (Some(width), _) => " ".repeat(width),
+        (None, Some(p)) => try_get_value!("indent", "prefix", String, p),
+        (None, None) => "    ".to_string(),
+    };
+    let first = match args.get("first") {
+        Some(f) => try_get_value!("indent", "first", bool, f),
+        None => width.is_some(),
+    };
+    let blank = match args.get("blank") {
+        Some(b) => try_get_value!("indent", "blank", bool, b),
+        None => false,
+    };
+
+    let lines: Vec<String> = s
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let skip = (i == 0 && !first) || (!blank && line.trim().is_empty());
+            if skip {
+                line.to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect();
+
+    Ok(to_value(lines.join("\n"))?)
+}
+
+/// Indents every line of a string by `width` spaces (four by default) and
+/// puts a newline in front of it, so a block can start on the line after a
+/// YAML key.
+pub fn nindent(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let mut args = args.clone();
+    args.entry("width".to_string())
+        .or_insert_with(|| Value::from(4));
+    let indented = indent(value, &args)?;
+    Ok(to_value(format!("\n{}", as_string(&indented)))?)
+}
+
+/// Wraps a value in double quotes, escaping backslashes and double quotes in
+/// it.
+pub fn quote(value: &Value, _: &HashMap<String, Value>) -> Result<Value> {
+    let escaped = as_string(value).replace('\\', "\\\\").replace('"', "\\\"");
+    Ok(to_value(format!("\"{}\"", escaped))?)
+}
+
+/// Fails rendering with the `msg` if the value is null or an empty string.
+/// Otherwise the value is passed through.
+pub fn required(value: &Value, args: &HashMap<String, Value>) -> Result<Value> {
+    let missing = match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        _ => false,
+    };
+    if !missing {
+        return Ok(value.clone());
+    }
+
+    let msg = match args.get("msg") {
+        Some(m) => try_get_value!("required", "msg", String, m),
+        None => "a required value is missing".to_string(),
+    };
+    Err(Error::msg(msg))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn args(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_base64() {
+        let encoded = base64_encode(&json!("hunter2"), &args(&[])).unwrap();
+        assert_eq!(encoded, json!("aHVudGVyMg=="));
+        assert_eq!(
+            base64_decode(&encoded, &args(&[])).unwrap(),
+            json!("hunter2")
+        );
+        assert!(base64_decode(&json!("not base64!"), &args(&[])).is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let value = json!({"a": [1, 2]});
+        assert_eq!(
+            to_json(&value, &args(&[])).unwrap(),
+            json!(r#"{"a":[1,2]}"#)
+        );
+        assert_eq!(
+            to_json(&value, &args(&[("pretty", json!(true))])).unwrap(),
+            json!("{\n  \"a\": [\n    1,\n    2\n  ]\n}")
+        );
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        let value = json!({"a": [1, 2], "b": "c"});
+        assert_eq!(
+            to_yaml(&value, &args(&[])).unwrap(),
+            json!("a:\n- 1\n- 2\nb: c")
+        );
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(&json!("abc"), &args(&[])).unwrap(),
+            json!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+    }
+
+    #[test]
+    fn test_indent() {
+        let value = json!("a:\n  b: c\n\nd: e");
+        assert_eq!(
+            indent(&value, &args(&[("width", json!(2))])).unwrap(),
+            json!("  a:\n    b: c\n\n  d: e")
+        );
+        assert_eq!(
+            indent(&value, &args(&[])).unwrap(),
+            json!("a:\n      b: c\n\n    d: e")
+        );
+        assert_eq!(
+            indent(
+                &json!("a\n\nb"),
+                &args(&[
+                    ("prefix", json!("> ")),
+                    ("first", json!(true)),
+                    ("blank", json!(true))
+                ])
+            )
+            .unwrap(),
+            json!("> a\n> \n> b")
+        );
+    }
+
+    #[test]
+    fn test_nindent() {
+        assert_eq!(
+            nindent(&json!("a: b\nc: d"), &args(&[("width", json!(2))])).unwrap(),
+            json!("\n  a: b\n  c: d")
+        );
+        assert_eq!(nindent(&json!("a"), &args(&[])).unwrap(), json!("\n    a"));
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(
+            quote(&json!(r#"say "hi" \o/"#), &args(&[])).unwrap(),
+            json!(r#""say \"hi\" \\o/""#)
+        );
+        assert_eq!(quote(&json!(5432), &args(&[])).unwrap(), json!("\"5432\""));
+    }
+
+    #[test]
+    fn test_required() {
+        assert_eq!(required(&json!("x"), &args(&[])).unwrap(), json!("x"));
+        assert_eq!(required(&json!(0), &args(&[])).unwrap(), json!(0));
+
+        let err = required(
+            &json!(""),
+            &args(&[("msg", json!("DE.BaseURI must be set"))]),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "DE.BaseURI must be set");
+        assert!(required(&Value::Null, &args(&[])).is_err());
+    }
+
+    #[test]
+    fn test_registered() {
+        let mut tera = Tera::default();
+        register(&mut tera);
+        tera.add_raw_template(
+            "t",
+            "{{ pw | urlencode }} {{ pw | urlencode_strict }} {{ pw | base64_encode | base64_decode | quote }}{{ block | nindent(width=2) }}",
+        )
+        .unwrap();
+
+        let mut context = tera::Context::new();
+        context.insert("pw", "a/b");
+        context.insert("block", "x: 1");
+        assert_eq!(
+            tera.render("t", &context).unwrap(),
+            "a/b a%2Fb \"a/b\"\n  x: 1"
+        );
+
+        tera.add_raw_template(
+            "missing",
+            "{{ empty | required(msg=\"empty is required\") }}",
+        )
+        .unwrap();
+        context.insert("empty", "");
+        assert!(tera.render("missing", &context).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+//! # Linting templates
+//!
+//! Checks Tera templates against the configuration values they're rendered
+//! with, so that a misspelled setting is caught before a deployment instead of
+//! during one. The settings a template can use are the fields `ConfigValues`
+//! serializes. When an environment is given, the optional sections its feature
+//! flags leave out are removed from that set.
+use super::scan;
+use crate::config_values::config::{ConfigValues, SectionOptions};
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use tera::Template;
+
+/// Names Tera provides itself, which are never settings.
+const BUILT_INS: &[&str] = &["loop", "__tera_context"];
+
+/// The kinds of problems that linting can find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    Syntax,
+    UnknownVariable,
+    DisabledSection,
+    UnusedSection,
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LintKind::Syntax => "syntax error",
+            LintKind::UnknownVariable => "unknown variable",
+            LintKind::DisabledSection => "disabled section",
+            LintKind::UnusedSection => "unused section",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl LintKind {
+    /// Returns whether the problem will break rendering. Disabled sections
+    /// aren't counted, since templates often guard them with `is defined`.
+    pub fn is_error(&self) -> bool {
+        matches!(self, LintKind::Syntax | LintKind::UnknownVariable)
+    }
+}
+
+/// A single problem found in a directory of templates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub file: String,
+    pub line: Option<usize>,
+    pub kind: LintKind,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {} ({})",
+                self.file, line, self.message, self.kind
+            ),
+            None => write!(f, "{}: {} ({})", self.file, self.message, self.kind),
+        }
+    }
+}
+
+/// The settings templates can refer to.
+pub struct Schema {
+    enabled: Value,
+    all: Value,
+}
+
+impl Schema {
+    /// Builds the schema from the fields `ConfigValues` serializes. Optional
+    /// sections turned off in the section options are left out of it; with
+    /// no options, every section is in it.
+    pub fn new(section_options: Option<SectionOptions>) -> Result<Self> {
+        let all = serde_json::to_value(ConfigValues::default())?;
+
+        let mut values = ConfigValues::default();
+        if let Some(opts) = section_options {
+            values.set_section_options(opts);
+            values.reset_sections()?;
+        }
+        let enabled = serde_json::to_value(values)?;
+
+        Ok(Schema { enabled, all })
+    }
+
+    /// Returns the top-level sections that are turned on.
+    pub fn sections(&self) -> BTreeSet<String> {
+        match &self.enabled {
+            Value::Object(fields) => fields
+                .iter()
+                .filter(|(_, v)| v.is_object())
+                .map(|(k, _)| k.clone())
+                .collect(),
+            _ => BTreeSet::new(),
+        }
+    }
+}
+
+// Returns whether a dotted path names a field in the values. Anything below a
+// list or an unset optional field is accepted, since its shape isn't known.
+fn has_path(values: &Value, path: &str) -> bool {
+    let mut current = values;
+    for part in path.split('.') {
+        match current {
+            Value::Object(fields) => match fields.get(part) {
+                Some(next) => current = next,
+                None => return false,
+            },
+            Value::Null | Value::Array(_) => return true,
+            _ => return false,
+        }
+    }
+    true
+}
+
+// Returns the line a variable is first used on. Falls back to the first use of
+// its top-level name, for variables written with subscripts.
+fn find_line(contents: &str, ident: &str) -> Option<usize> {
+    let root = ident.split('.').next().unwrap_or(ident);
+    [ident, root].iter().find_map(|needle| {
+        contents
+            .lines()
+            .position(|line| line.contains(needle))
+            .map(|i| i + 1)
+    })
+}
+
+// Turns a Tera parse error into a message and the line it points at. The
+// parser's message has a `--> line:column` marker and ends with what it
+// expected to find.
+fn syntax_error(err: &tera::Error) -> (Option<usize>, String) {
+    let mut detail = err.to_string();
+    let mut source = err.source();
+    while let Some(e) = source {
+        detail = e.to_string();
+        source = e.source();
+    }
+
+    let line = detail.find("--> ").and_then(|start| {
+        detail[start + 4..]
+            .split(':')
+            .next()
+            .and_then(|l| l.trim().parse().ok())
+    });
+    let message = detail
+        .lines()
+        .rev()
+        .map(|l| l.trim().trim_start_matches("= ").trim())
+        .find(|l| !l.is_empty())
+        .unwrap_or("failed to parse the template")
+        .to_string();
+
+    (line, message)
+}
+
+/// Lints one template. Returns the problems found in it and the top-level
+/// sections it refers to.
+///
+/// # Example
+/// ```ignore
+/// let (issues, used) = lint_template("apps.properties", &contents, &schema);
+/// ```
+pub fn lint_template(
+    name: &str,
+    contents: &str,
+    schema: &Schema,
+) -> (Vec<LintIssue>, BTreeSet<String>) {
+    let mut issues = Vec::new();
+    let mut used = BTreeSet::new();
+
+    let template = match Template::new(name, None, contents) {
+        Ok(template) => template,
+        Err(e) => {
+            let (line, message) = syntax_error(&e);
+            issues.push(LintIssue {
+                file: name.to_string(),
+                line,
+                kind: LintKind::Syntax,
+                message,
+            });
+            return (issues, used);
+        }
+    };
+
+    let locals = scan::local_variables(&template);
+    for ident in scan::template_variables(&template) {
+        let root = ident.split('.').next().unwrap_or_default();
+        if root.is_empty() || locals.contains(root) || BUILT_INS.contains(&root) {
+            continue;
+        }
+
+        if has_path(&schema.enabled, &ident) {
+            used.insert(root.to_string());
+            continue;
+        }
+
+        let (kind, message) = if has_path(&schema.all, &ident) {
+            (
+                LintKind::DisabledSection,
+                format!("{} is in the {} section, which is turned off", ident, root),
+            )
+        } else {
+            (
+                LintKind::UnknownVariable,
+                format!("{} isn't a config setting", ident),
+            )
+        };
+        issues.push(LintIssue {
+            file: name.to_string(),
+            line: find_line(contents, &ident),
+            kind,
+            message,
+        });
+    }
+
+    (issues, used)
+}
+
+/// Lints every template in a directory. Besides the problems in each
+/// template, sections that are turned on but never used by any of them are
+/// reported.
+///
+/// # Example
+/// ```ignore
+/// let issues = lint_dir(Path::new("templates"), &Schema::new(None)?)?;
+/// ```
+pub fn lint_dir(templates_dir: &Path, schema: &Schema) -> Result<Vec<LintIssue>> {
+    let mut issues = Vec::new();
+    let mut used = BTreeSet::new();
+
+    for path in super::list_template_files(templates_dir)? {
+        let name = path
+            .strip_prefix(templates_dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+
+        let (found, sections) = lint_template(&name, &contents, schema);
+        issues.extend(found);
+        used.extend(sections);
+    }
+
+    for section in schema.sections().difference(&used) {
+        issues.push(LintIssue {
+            file: templates_dir.display().to_string(),
+            line: None,
+            kind: LintKind::UnusedSection,
+            message: format!("the {} section isn't used by any template", section),
+        });
+    }
+
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schema() -> Schema {
+        let mut opts = SectionOptions::default();
+        opts.set_section("Agave", true);
+        Schema::new(Some(opts)).unwrap()
+    }
+
+    #[test]
+    fn test_has_path() {
+        let schema = schema();
+        assert!(has_path(&schema.enabled, "Environment"));
+        assert!(has_path(&schema.enabled, "DE.AMQP.Host"));
+        assert!(has_path(&schema.enabled, "Agave.Key"));
+        assert!(!has_path(&schema.enabled, "DE.AMQP.Uri"));
+        assert!(!has_path(&schema.enabled, "Environment.Name"));
+        assert!(!has_path(&schema.enabled, "QA"));
+        assert!(has_path(&schema.all, "QA"));
+    }
+
+    #[test]
+    fn test_lint_template() {
+        let contents = r#"env = {{ Environment }}
+amqp = {{ DE.AMQP.Uri }}
+{% for r in DE.Subscriptions %}{{ r }} {{ loop.index }}{% endfor %}
+{% if QA is defined %}{{ QA.Foo }}{% endif %}
+{{ Agave.Key }}
+"#;
+        let (issues, used) = lint_template("test", contents, &schema());
+        let found: Vec<(Option<usize>, LintKind)> =
+            issues.iter().map(|i| (i.line, i.kind)).collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (Some(2), LintKind::UnknownVariable),
+                (Some(4), LintKind::DisabledSection),
+                (Some(4), LintKind::UnknownVariable),
+            ]
+        );
+        assert!(used.contains("Agave"));
+        assert!(used.contains("DE"));
+        assert!(!used.contains("Docker"));
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let (issues, _) = lint_template("test", "ok\n{{ Environment | }}\nmore\n", &schema());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, LintKind::Syntax);
+        assert_eq!(issues[0].line, Some(2));
+    }
+}
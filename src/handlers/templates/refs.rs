@@ -0,0 +1,440 @@
+//! # Secret References
+//!
+//! Config values can point at a secret that's stored somewhere else instead of
+//! holding the secret itself. A reference looks like
+//! `ref+<scheme>://<path>#<key>` and is resolved just before the templates are
+//! rendered, so the secret never has to be stored in the database.
+//!
+//! The built-in schemes are:
+//!
+//! - `ref+file:///path/to/file` reads a whole file. With a `#key`, the file is
+//!   parsed as YAML or JSON and the key is looked up in it. Nested keys are
+//!   separated with dots.
+//! - `ref+env://NAME` reads an environment variable.
+//! - `ref+vault://secret/data/path#key` reads a key from a Vault-compatible KV
+//!   store, using the `VAULT_ADDR` and `VAULT_TOKEN` environment variables.
+//!
+//! Other schemes can be supported by implementing `RefResolver` and
+//! registering it with `Resolvers::register()`.
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::{env, fmt, fs, future::Future, pin::Pin};
+
+/// The prefix that marks a value as a reference.
+pub const REF_PREFIX: &str = "ref+";
+
+/// A reference to a value stored outside of the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretRef {
+    pub scheme: String,
+    pub path: String,
+    pub key: Option<String>,
+}
+
+impl fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}://{}", REF_PREFIX, self.scheme, self.path)?;
+        if let Some(key) = &self.key {
+            write!(f, "#{}", key)?;
+        }
+        Ok(())
+    }
+}
+
+impl SecretRef {
+    /// Parses a value into a reference. Returns `None` if the value isn't a
+    /// reference at all and an error if it looks like one but is malformed.
+    ///
+    /// # Example
+    /// ```ignore
+    ///     let r = SecretRef::parse("ref+vault://secret/data/de#password")?;
+    /// ```
+    pub fn parse(value: &str) -> Result<Option<SecretRef>> {
+        let rest = match value.strip_prefix(REF_PREFIX) {
+            Some(rest) => rest,
+            None => return Ok(None),
+        };
+
+        let (scheme, location) = rest
+            .split_once("://")
+            .ok_or_else(|| anyhow!("malformed reference '{}'", value))?;
+        if scheme.is_empty() {
+            return Err(anyhow!("reference '{}' has no scheme", value));
+        }
+
+        let (path, key) = match location.split_once('#') {
+            Some((path, key)) => (path, Some(key.to_string())),
+            None => (location, None),
+        };
+        if path.is_empty() {
+            return Err(anyhow!("reference '{}' has no path", value));
+        }
+
+        Ok(Some(SecretRef {
+            scheme: scheme.to_string(),
+            path: path.to_string(),
+            key,
+        }))
+    }
+}
+
+/// The value a resolver returns. Resolvers may need to make requests, so
+/// resolving a reference is asynchronous.
+pub type Resolved<'a> = Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+
+/// Resolves references for a single scheme.
+pub trait RefResolver: Send + Sync {
+    /// Returns the scheme the resolver handles, for example `env`.
+    fn scheme(&self) -> &str;
+
+    /// Returns the value that the reference points to.
+    fn resolve<'a>(&'a self, reference: &'a SecretRef) -> Resolved<'a>;
+}
+
+// Looks up a dot-separated key in a parsed document and returns it as a string.
+fn lookup(doc: &Value, key: &str) -> Result<String> {
+    let found = key
+        .split('.')
+        .try_fold(doc, |current, part| current.get(part))
+        .ok_or_else(|| anyhow!("key '{}' not found", key))?;
+
+    match found {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(anyhow!("key '{}' does not hold a single value", key)),
+    }
+}
+
+/// Resolves `ref+file://` references.
+pub struct FileResolver;
+
+impl RefResolver for FileResolver {
+    fn scheme(&self) -> &str {
+        "file"
+    }
+
+    fn resolve<'a>(&'a self, reference: &'a SecretRef) -> Resolved<'a> {
+        Box::pin(async move {
+            let contents = fs::read_to_string(&reference.path)
+                .with_context(|| format!("failed to read {}", reference.path))?;
+
+            match &reference.key {
+                Some(key) => {
+                    let doc: Value = serde_yaml::from_str(&contents)
+                        .with_context(|| format!("failed to parse {}", reference.path))?;
+                    lookup(&doc, key)
+                }
+                None => Ok(contents.trim_end_matches('\n').to_string()),
+            }
+        })
+    }
+}
+
+/// Resolves `ref+env://` references.
+pub struct EnvResolver;
+
+impl RefResolver for EnvResolver {
+    fn scheme(&self) -> &str {
+        "env"
+    }
+
+    fn resolve<'a>(&'a self, reference: &'a SecretRef) -> Resolved<'a> {
+        Box::pin(async move {
+            env::var(&reference.path)
+                .map_err(|_| anyhow!("environment variable {} is not set", reference.path))
+        })
+    }
+}
+
+/// Resolves `ref+vault://` references against the HTTP API of a
+/// Vault-compatible KV store. Both version 1 and version 2 of the KV secrets
+/// engine are supported.
+pub struct VaultResolver {
+    addr: Option<String>,
+    token: Option<String>,
+}
+
+impl VaultResolver {
+    pub fn new(addr: &str, token: &str) -> Self {
+        VaultResolver {
+            addr: Some(addr.to_string()),
+            token: Some(token.to_string()),
+        }
+    }
+
+    /// Creates a resolver from the `VAULT_ADDR` and `VAULT_TOKEN` environment
+    /// variables. Missing variables are only reported when a reference needs
+    /// them.
+    pub fn from_env() -> Self {
+        VaultResolver {
+            addr: env::var("VAULT_ADDR").ok(),
+            token: env::var("VAULT_TOKEN").ok(),
+        }
+    }
+}
+
+impl RefResolver for VaultResolver {
+    fn scheme(&self) -> &str {
+        "vault"
+    }
+
+    fn resolve<'a>(&'a self, reference: &'a SecretRef) -> Resolved<'a> {
+        Box::pin(async move {
+            let addr = self
+                .addr
+                .as_ref()
+                .ok_or_else(|| anyhow!("VAULT_ADDR is not set"))?;
+            let token = self
+                .token
+                .as_ref()
+                .ok_or_else(|| anyhow!("VAULT_TOKEN is not set"))?;
+            let key = reference
+                .key
+                .as_ref()
+                .ok_or_else(|| anyhow!("vault references need a #key"))?;
+
+            let url = format!(
+                "{}/v1/{}",
+                addr.trim_end_matches('/'),
+                reference.path.trim_start_matches('/')
+            );
+
+            let body: Value = async {
+                reqwest::Client::new()
+                    .get(&url)
+                    .header("X-Vault-Token", token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+            }
+            .await
+            .with_context(|| format!("failed to read {} from vault", reference.path))?;
+
+            // KV version 2 nests the secret inside of another data field.
+            let data = match body.pointer("/data/data") {
+                Some(data) if data.is_object() => data,
+                _ => body
+                    .get("data")
+                    .ok_or_else(|| anyhow!("no data in the vault response"))?,
+            };
+
+            lookup(data, key)
+        })
+    }
+}
+
+/// The set of resolvers used to resolve references, keyed by scheme.
+#[derive(Default)]
+pub struct Resolvers {
+    resolvers: Vec<Box<dyn RefResolver>>,
+}
+
+impl Resolvers {
+    /// Creates an empty set of resolvers.
+    pub fn new() -> Self {
+        Resolvers::default()
+    }
+
+    /// Creates a set of resolvers with the file, env, and vault resolvers
+    /// registered.
+    pub fn with_defaults() -> Self {
+        let mut resolvers = Resolvers::new();
+        resolvers.register(Box::new(FileResolver));
+        resolvers.register(Box::new(EnvResolver));
+        resolvers.register(Box::new(VaultResolver::from_env()));
+        resolvers
+    }
+
+    /// Registers a resolver, replacing any resolver for the same scheme.
+    pub fn register(&mut self, resolver: Box<dyn RefResolver>) {
+        self.resolvers.retain(|r| r.scheme() != resolver.scheme());
+        self.resolvers.push(resolver);
+    }
+
+    /// Resolves a single value. Returns `None` if the value isn't a
+    /// reference.
+    pub async fn resolve(&self, value: &str) -> Result<Option<String>> {
+        let reference = match SecretRef::parse(value)? {
+            Some(reference) => reference,
+            None => return Ok(None),
+        };
+
+        let resolver = self
+            .resolvers
+            .iter()
+            .find(|r| r.scheme() == reference.scheme)
+            .ok_or_else(|| anyhow!("no resolver for the '{}' scheme", reference.scheme))?;
+
+        Ok(Some(resolver.resolve(&reference).await?))
+    }
+
+    /// Replaces every reference in a serialized set of config values with the
+    /// value it points to. Fails on the first reference that can't be
+    /// resolved.
+    ///
+    /// # Example
+    /// ```ignore
+    ///     let mut values = serde_json::to_value(&merged_cv)?;
+    ///     Resolvers::with_defaults().resolve_all(&mut values).await?;
+    /// ```
+    pub async fn resolve_all(&self, values: &mut Value) -> Result<()> {
+        let mut strings = Vec::new();
+        collect_strings(values, &mut Vec::new(), &mut strings);
+
+        for (path, s) in strings {
+            let resolved = self
+                .resolve(s)
+                .await
+                .with_context(|| format!("failed to resolve the reference in {}: {}", path, s))?;
+            if let Some(resolved) = resolved {
+                *s = resolved;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Collects the strings in a set of values along with where they are, so that
+// errors can say which setting held the bad reference.
+fn collect_strings<'a>(
+    value: &'a mut Value,
+    path: &mut Vec<String>,
+    strings: &mut Vec<(String, &'a mut String)>,
+) {
+    match value {
+        Value::String(s) => strings.push((path.join("."), s)),
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                path.push(key.clone());
+                collect_strings(child, path, strings);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter_mut().enumerate() {
+                path.push(index.to_string());
+                collect_strings(child, path, strings);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(SecretRef::parse("plain value").unwrap(), None);
+
+        let r = SecretRef::parse("ref+vault://secret/data/de#db.password")
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.scheme, "vault");
+        assert_eq!(r.path, "secret/data/de");
+        assert_eq!(r.key.as_deref(), Some("db.password"));
+        assert_eq!(r.to_string(), "ref+vault://secret/data/de#db.password");
+
+        assert!(SecretRef::parse("ref+env:NAME").is_err());
+        assert!(SecretRef::parse("ref+env://").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_and_env() {
+        let path = env::temp_dir().join(format!("mgmt-refs-test-{}.yaml", std::process::id()));
+        fs::write(&path, "db:\n  password: hunter2\n  port: 5432\n").unwrap();
+        env::set_var("MGMT_REFS_TEST_VALUE", "from-env");
+
+        let resolvers = Resolvers::with_defaults();
+        let file_ref = format!("ref+file://{}#db.password", path.display());
+        let port_ref = format!("ref+file://{}#db.port", path.display());
+        assert_eq!(
+            resolvers.resolve(&file_ref).await.unwrap().unwrap(),
+            "hunter2"
+        );
+        assert_eq!(resolvers.resolve(&port_ref).await.unwrap().unwrap(), "5432");
+        assert_eq!(
+            resolvers
+                .resolve("ref+env://MGMT_REFS_TEST_VALUE")
+                .await
+                .unwrap()
+                .unwrap(),
+            "from-env"
+        );
+        assert!(resolvers
+            .resolve("ref+env://MGMT_REFS_TEST_UNSET")
+            .await
+            .is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resolve_all() {
+        env::set_var("MGMT_REFS_TEST_ALL", "secret");
+        let resolvers = Resolvers::with_defaults();
+
+        let mut values = json!({
+            "DEDB": {"Password": "ref+env://MGMT_REFS_TEST_ALL", "Port": 5432},
+            "Environment": "prod",
+        });
+        resolvers.resolve_all(&mut values).await.unwrap();
+        assert_eq!(values["DEDB"]["Password"], "secret");
+        assert_eq!(values["Environment"], "prod");
+
+        let mut values = json!({"Keycloak": {"ClientSecret": "ref+sops://secrets.yaml#key"}});
+        let err = resolvers.resolve_all(&mut values).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Keycloak.ClientSecret"));
+    }
+
+    #[tokio::test]
+    async fn test_vault() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let n = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+
+            let body = r#"{"data": {"data": {"password": "from-vault"}}}"#;
+            let status = if request.starts_with("get /v1/secret/data/de ")
+                && request.contains("x-vault-token: test-token")
+            {
+                "200 OK"
+            } else {
+                "403 Forbidden"
+            };
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        let mut resolvers = Resolvers::new();
+        resolvers.register(Box::new(VaultResolver::new(&addr, "test-token")));
+        let value = resolvers
+            .resolve("ref+vault://secret/data/de#password")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(value, "from-vault");
+
+        server.join().unwrap();
+    }
+}
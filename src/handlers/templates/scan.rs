@@ -0,0 +1,284 @@
+//! # Scanning templates
+//!
+//! Finds the configuration values that a Tera template refers to. Templates
+//! are parsed with Tera itself and every variable in the syntax tree is
+//! collected, so references inside filters, loops, conditions, and macros are
+//! all found. The variables are then matched against the known Section.Key
+//! names, keeping in mind that top-level settings are referred to by their key
+//! alone.
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
+use tera::Template;
+
+// Turns subscripts into dotted paths, so `DE["AMQP"].URI` becomes
+// `DE.AMQP.URI`. Numeric subscripts are dropped.
+fn normalize_ident(ident: &str) -> String {
+    let mut path = String::new();
+    let mut rest = ident;
+
+    while let Some(start) = rest.find('[') {
+        path.push_str(&rest[..start]);
+        let end = rest[start..].find(']').map_or(rest.len(), |e| start + e);
+        let inner = rest[start + 1..end].trim_matches(|c| c == '"' || c == '\'');
+        if !inner.is_empty() && !inner.chars().all(|c| c.is_ascii_digit()) {
+            path.push('.');
+            path.push_str(inner);
+        }
+        rest = rest.get(end + 1..).unwrap_or("");
+    }
+    path.push_str(rest);
+
+    path
+}
+
+fn walk_call(call: &FunctionCall, out: &mut BTreeSet<String>) {
+    call.args.values().for_each(|arg| walk_expr(arg, out));
+}
+
+fn walk_expr(expr: &Expr, out: &mut BTreeSet<String>) {
+    expr.filters.iter().for_each(|f| walk_call(f, out));
+
+    match &expr.val {
+        ExprVal::Ident(ident) => {
+            out.insert(normalize_ident(ident));
+        }
+        ExprVal::Math(math) => {
+            walk_expr(&math.lhs, out);
+            walk_expr(&math.rhs, out);
+        }
+        ExprVal::Logic(logic) => {
+            walk_expr(&logic.lhs, out);
+            walk_expr(&logic.rhs, out);
+        }
+        ExprVal::Test(test) => {
+            out.insert(normalize_ident(&test.ident));
+            test.args.iter().for_each(|arg| walk_expr(arg, out));
+        }
+        ExprVal::MacroCall(call) => call.args.values().for_each(|arg| walk_expr(arg, out)),
+        ExprVal::FunctionCall(call) => walk_call(call, out),
+        ExprVal::Array(items) => items.iter().for_each(|item| walk_expr(item, out)),
+        ExprVal::StringConcat(concat) => {
+            for value in &concat.values {
+                if let ExprVal::Ident(ident) = value {
+                    out.insert(normalize_ident(ident));
+                }
+            }
+        }
+        ExprVal::In(expr_in) => {
+            walk_expr(&expr_in.lhs, out);
+            walk_expr(&expr_in.rhs, out);
+        }
+        ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => (),
+    }
+}
+
+fn walk_nodes(nodes: &[Node], out: &mut BTreeSet<String>) {
+    for node in nodes {
+        match node {
+            Node::VariableBlock(_, expr) => walk_expr(expr, out),
+            Node::MacroDefinition(_, def, _) => {
+                def.args
+                    .values()
+                    .flatten()
+                    .for_each(|arg| walk_expr(arg, out));
+                walk_nodes(&def.body, out);
+            }
+            Node::Set(_, set) => walk_expr(&set.value, out),
+            Node::FilterSection(_, section, _) => {
+                walk_call(&section.filter, out);
+                walk_nodes(&section.body, out);
+            }
+            Node::Block(_, block, _) => walk_nodes(&block.body, out),
+            Node::Forloop(_, forloop, _) => {
+                walk_expr(&forloop.container, out);
+                walk_nodes(&forloop.body, out);
+                if let Some(body) = &forloop.empty_body {
+                    walk_nodes(body, out);
+                }
+            }
+            Node::If(cond, _) => {
+                for (_, expr, body) in &cond.conditions {
+                    walk_expr(expr, out);
+                    walk_nodes(body, out);
+                }
+                if let Some((_, body)) = &cond.otherwise {
+                    walk_nodes(body, out);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn walk_locals(nodes: &[Node], out: &mut BTreeSet<String>) {
+    for node in nodes {
+        match node {
+            Node::MacroDefinition(_, def, _) => {
+                out.extend(def.args.keys().cloned());
+                walk_locals(&def.body, out);
+            }
+            Node::Set(_, set) => {
+                out.insert(set.key.clone());
+            }
+            Node::FilterSection(_, section, _) => walk_locals(&section.body, out),
+            Node::Block(_, block, _) => walk_locals(&block.body, out),
+            Node::Forloop(_, forloop, _) => {
+                out.extend(forloop.key.iter().cloned());
+                out.insert(forloop.value.clone());
+                walk_locals(&forloop.body, out);
+                if let Some(body) = &forloop.empty_body {
+                    walk_locals(body, out);
+                }
+            }
+            Node::If(cond, _) => {
+                for (_, _, body) in &cond.conditions {
+                    walk_locals(body, out);
+                }
+                if let Some((_, body)) = &cond.otherwise {
+                    walk_locals(body, out);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Returns every variable referred to in a parsed template, as dotted paths.
+pub fn template_variables(template: &Template) -> BTreeSet<String> {
+    let mut idents = BTreeSet::new();
+    walk_nodes(&template.ast, &mut idents);
+    idents
+}
+
+/// Returns the names a parsed template binds itself: loop variables, `set`
+/// variables, and macro arguments. Scoping isn't tracked, so a name bound
+/// anywhere in the template counts everywhere in it.
+pub fn local_variables(template: &Template) -> BTreeSet<String> {
+    let mut locals = BTreeSet::new();
+    walk_locals(&template.ast, &mut locals);
+    locals
+}
+
+/// Returns every variable referred to in a template, as dotted paths.
+///
+/// # Example
+/// ```ignore
+/// let idents = referenced_variables("templates/apps.properties", &contents)?;
+/// ```
+pub fn referenced_variables(name: &str, contents: &str) -> Result<BTreeSet<String>> {
+    let template = Template::new(name, None, contents)
+        .map_err(|e| anyhow!("failed to parse {}: {}", name, e))?;
+
+    Ok(template_variables(&template))
+}
+
+/// Matches the variables used in a template against the known (section, key)
+/// pairs. A variable matches a setting if it names the setting, one of the
+/// maps the setting is nested in, or something nested inside the setting.
+///
+/// # Example
+/// ```ignore
+/// let refs = match_references(&idents, &known);
+/// ```
+pub fn match_references(
+    idents: &BTreeSet<String>,
+    known: &[(String, String)],
+) -> BTreeSet<(String, String)> {
+    known
+        .iter()
+        .filter(|(section, key)| {
+            let full = if section == "TopLevel" {
+                key.clone()
+            } else {
+                format!("{}.{}", section, key)
+            };
+
+            idents.iter().any(|ident| {
+                *ident == full
+                    || full.starts_with(&format!("{}.", ident))
+                    || ident.starts_with(&format!("{}.", full))
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn known() -> Vec<(String, String)> {
+        [
+            ("TopLevel", "Environment"),
+            ("DE", "AMQP.URI"),
+            ("DE", "AMQP.Exchange.Name"),
+            ("DE", "BaseURI"),
+            ("DEDB", "Host"),
+            ("DEDB", "Password"),
+            ("Docker", "TrustedRegistries"),
+            ("Keycloak", "ServerURI"),
+        ]
+        .iter()
+        .map(|(s, k)| (s.to_string(), k.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn test_normalize_ident() {
+        assert_eq!(normalize_ident("DE.AMQP.URI"), "DE.AMQP.URI");
+        assert_eq!(normalize_ident("DE[\"AMQP\"].URI"), "DE.AMQP.URI");
+        assert_eq!(
+            normalize_ident("Docker.TrustedRegistries[0]"),
+            "Docker.TrustedRegistries"
+        );
+    }
+
+    #[test]
+    fn test_referenced_variables() {
+        let contents = r#"
+env = {{ Environment }}
+amqp = {{ DE.AMQP.URI | default(value=DE.BaseURI) }}
+{% if DEDB.Host is defined %}db = {{ DEDB.Host }}{% endif %}
+{% for r in Docker.TrustedRegistries %}{{ r }}{% endfor %}
+{% set pw = DEDB["Password"] %}
+"#;
+        let idents = referenced_variables("test", contents).unwrap();
+        let refs = match_references(&idents, &known());
+        let refs: Vec<String> = refs.iter().map(|(s, k)| format!("{}.{}", s, k)).collect();
+
+        assert_eq!(
+            refs,
+            vec![
+                "DE.AMQP.URI",
+                "DE.BaseURI",
+                "DEDB.Host",
+                "DEDB.Password",
+                "Docker.TrustedRegistries",
+                "TopLevel.Environment",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_section_reference() {
+        let idents = referenced_variables("test", "{{ DE.AMQP | json_encode() }}").unwrap();
+        let refs = match_references(&idents, &known());
+        assert_eq!(refs.len(), 2);
+        assert!(refs.contains(&("DE".to_string(), "AMQP.Exchange.Name".to_string())));
+
+        assert!(referenced_variables("test", "{{ unclosed").is_err());
+    }
+
+    #[test]
+    fn test_local_variables() {
+        let contents = r#"
+{% macro port(name, default=80) %}{{ name }}{% endmacro %}
+{% set host = DEDB.Host %}
+{% for k, v in DE.AMQP %}{{ k }}={{ v }}{% endfor %}
+"#;
+        let template = Template::new("test", None, contents).unwrap();
+        let locals: Vec<String> = local_variables(&template).into_iter().collect();
+        assert_eq!(locals, vec!["default", "host", "k", "name", "v"]);
+    }
+}
@@ -4,7 +4,12 @@ pub mod config_values;
 pub mod configs;
 pub mod db;
 pub mod deploy;
+pub mod diff;
 pub mod dolt;
 pub mod git;
 pub mod handlers;
+pub mod manifest;
 pub mod ops;
+pub mod output;
+pub mod secrets;
+pub mod settings;
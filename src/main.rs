@@ -3,25 +3,49 @@ use std::path::PathBuf;
 use anyhow::{Context, Result};
 use clap::{arg, Command};
 use mgmt::cli::{
-    configs, container_images, deploy, envs, release, repos, services, site, templates,
+    configs, container_images, db as db_cli, deploy, envs, release, repos, services, site,
+    templates,
 };
+use mgmt::db::backend::Database;
+use mgmt::db::migrate;
 use mgmt::handlers;
+use mgmt::output::{self, OutputFormat};
+use mgmt::settings::{self, Settings};
 use mgmt::{app, db};
-use sqlx::postgres::PgPoolOptions;
-use tabled::Table;
 use which::which;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let commands = Command::new("mgmt")
+    let args: Vec<String> = std::env::args().collect();
+    let settings = Settings::load(&args)?;
+
+    let cli = Command::new("mgmt")
         .version("0.1.0")
         .about("Discovery Environment deployment management tool")
+        .after_help(settings::HELP)
         .subcommand_required(true)
+        .arg(
+            arg!(-P --profile <PROFILE> "The profile in the config file to take settings from")
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
         .arg(
             arg!(-d --"database-url" <DATABASE>)
                 .default_value("postgresql://root@127.0.0.1:5432/de_releases?sslmode=disable")
                 .value_parser(clap::value_parser!(String)),
         )
+        .arg(
+            arg!(--format <FORMAT> "The format the results of list, get, and explain commands are printed in, e.g. `mgmt env list --format json`")
+                .global(true)
+                .default_value("table")
+                .value_parser(clap::value_parser!(OutputFormat)),
+        )
+        .arg(
+            arg!(--"kube-context" <CONTEXT> "The Kubernetes context kubectl and skaffold use")
+                .global(true)
+                .required(false)
+                .value_parser(clap::value_parser!(String)),
+        )
         .subcommand(configs::cli())
         .subcommand(container_images::cli())
         .subcommand(release::cli())
@@ -31,23 +55,63 @@ async fn main() -> Result<()> {
         .subcommand(services::cli())
         .subcommand(envs::cli())
         .subcommand(repos::cli())
-        .get_matches();
+        .subcommand(db_cli::cli());
+
+    let commands = settings.apply(cli).get_matches_from(args);
 
     let database_url = commands.get_one::<String>("database-url").context(
         "No database URL specified. Use --database-url <url> to specify a database URL.",
     )?;
 
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(database_url)
+    let output = OutputFormat::from_matches(&commands);
+
+    let database = Database::connect(database_url)
         .await
         .map_err(|e| anyhow::anyhow!("error connecting to database: {}", e))?;
 
+    // The migrate commands manage the schema and the site commands use their
+    // own Dolt database, so only the other commands need an up to date schema.
+    // The schema of a MySQL (Dolt) database is migrated with the migrate CLI
+    // instead, see docs/database.md.
+    let command = commands.subcommand_name().unwrap_or_default();
+    let migrating = commands
+        .subcommand_matches("db")
+        .is_some_and(|db| db.subcommand_name() == Some("migrate"));
+    if let Database::Postgres(pool) = &database {
+        if !migrating && command != "site" {
+            migrate::check(pool).await?;
+        }
+    }
+
+    // The site commands and the feature flag commands work against either
+    // backend. The rest are only implemented for Postgres.
+    if let Some(sub_m) = commands
+        .subcommand_matches("env")
+        .and_then(|env| env.subcommand_matches("feature-flags"))
+    {
+        handlers::envs::env_feature_flags_handler(&database, sub_m, output).await?;
+        database.close().await;
+        return Ok(());
+    }
+
+    if let Some(sub_m) = commands.subcommand_matches("site") {
+        database.close().await;
+        match sub_m.subcommand() {
+            Some(("init", sub_m)) => handlers::sites::init_site(sub_m).await?,
+            Some(("deploy", sub_m)) => handlers::sites::deploy_site(sub_m).await?,
+            _ => unreachable!("Bad site subcommand"),
+        }
+        return Ok(());
+    }
+
+    let pool = database.postgres(&format!("`mgmt {}`", command))?.clone();
+
     match commands.subcommand() {
         Some(("configs", sub_m)) => match sub_m.subcommand() {
-            Some(("sections", sub_m)) => handlers::configs::sections(&pool, &sub_m).await?,
-            Some(("defaults", sub_m)) => handlers::configs::defaults(&pool, &sub_m).await?,
-            Some(("values", sub_m)) => handlers::configs::values(&pool, &sub_m).await?,
+            Some(("sections", sub_m)) => handlers::configs::sections(&pool, &sub_m, output).await?,
+            Some(("defaults", sub_m)) => handlers::configs::defaults(&pool, &sub_m, output).await?,
+            Some(("values", sub_m)) => handlers::configs::values(&pool, &sub_m, output).await?,
+            Some(("secrets", sub_m)) => handlers::configs::secrets(&pool, sub_m).await?,
             _ => unreachable!("Bad configs subcommand"),
         },
 
@@ -61,7 +125,7 @@ async fn main() -> Result<()> {
                 handlers::container_images::upsert_single_build(&pool, &sub_m).await?
             }
             Some(("delete", sub_m)) => handlers::container_images::delete(&pool, &sub_m).await?,
-            Some(("list", _)) => handlers::container_images::list_all_images(&pool).await?,
+            Some(("list", _)) => handlers::container_images::list_all_images(&pool, output).await?,
             _ => unreachable!("Bad container-images subcommand"),
         },
 
@@ -71,12 +135,6 @@ async fn main() -> Result<()> {
             _ => unreachable!("Bad release subcommand"),
         },
 
-        Some(("site", sub_m)) => match sub_m.subcommand() {
-            Some(("init", sub_m)) => handlers::sites::init_site(&sub_m).await?,
-            Some(("deploy", sub_m)) => handlers::sites::deploy_site(&sub_m).await?,
-            _ => unreachable!("Bad site subcommand"),
-        },
-
         Some(("deploy", sub_m)) => match sub_m.subcommand() {
             Some(("backwards-compat", sub_m)) => {
                 let git_path = which("git").context("git not found")?;
@@ -118,7 +176,8 @@ async fn main() -> Result<()> {
                     defaults_path,
                     values_path,
                     output_path,
-                )?;
+                )
+                .await?;
             }
 
             Some(("render-dir", sub_m)) => {
@@ -142,8 +201,10 @@ async fn main() -> Result<()> {
                     templates_path,
                     defaults_path,
                     values_path,
+                    sub_m.get_flag("tera-only"),
                     output_path,
-                )?;
+                )
+                .await?;
             }
 
             Some(("render-file-db", sub_m)) => {
@@ -185,10 +246,13 @@ async fn main() -> Result<()> {
                 )?;
 
                 let mut tx = pool.begin().await?;
+                let checkout = handlers::templates::checkout_from_args(&mut tx, sub_m).await?;
                 handlers::templates::render_template_dir_from_db(
                     &mut tx,
-                    templates_path,
+                    &handlers::templates::checkout_path(checkout.as_ref(), templates_path),
                     &env,
+                    checkout.as_ref().map(|c| c.commit.as_str()),
+                    sub_m.get_flag("tera-only"),
                     output_path,
                 )
                 .await?;
@@ -205,13 +269,56 @@ async fn main() -> Result<()> {
                     "No environment specified. Use --environment <name> to specify an environment.",
                 )?;
 
-                let output_path = sub_m.get_one::<PathBuf>("output").context(
-                    "No output directory specified. Use --output <path> to specify an output directory.",
+                let mut tx = pool.begin().await?;
+                let checkout = handlers::templates::checkout_from_args(&mut tx, sub_m).await?;
+                let templates_path =
+                    &handlers::templates::checkout_path(checkout.as_ref(), templates_path);
+                if sub_m.get_flag("diff-cluster") {
+                    handlers::templates::diff_cluster(
+                        &mut tx,
+                        env,
+                        templates_path,
+                        sub_m.get_flag("show-secrets"),
+                        settings::kube_context(sub_m).as_deref(),
+                    )
+                    .await?;
+                } else {
+                    let output_path = sub_m.get_one::<PathBuf>("output").context(
+                        "No output directory specified. Use --output <path> to specify an output directory.",
+                    )?;
+
+                    handlers::templates::render_db(
+                        &mut tx,
+                        &env,
+                        templates_path,
+                        checkout.as_ref().map(|c| c.commit.as_str()),
+                        output_path,
+                    )
+                    .await?;
+                }
+
+                tx.commit().await?;
+            }
+
+            Some(("verify", sub_m)) => {
+                let dir = sub_m.get_one::<PathBuf>("dir").context(
+                    "No directory specified. Use --dir <path> to specify a rendered directory.",
                 )?;
 
-                let mut tx = pool.begin().await?;
-                handlers::templates::render_db(&mut tx, &env, templates_path, output_path).await?;
+                let templates_path = sub_m.get_one::<PathBuf>("templates").context(
+                    "No templates directory specified. Use --templates <path> to specify a templates directory.",
+                )?;
 
+                let mut tx = pool.begin().await?;
+                let repo = handlers::templates::repo_from_args(&mut tx, sub_m).await?;
+                handlers::templates::verify_render(
+                    &mut tx,
+                    dir,
+                    templates_path,
+                    repo.as_ref()
+                        .map(|(repo, revision)| (repo, revision.as_deref())),
+                )
+                .await?;
                 tx.commit().await?;
             }
 
@@ -234,9 +341,49 @@ async fn main() -> Result<()> {
                     .map(|s| PathBuf::from(s))
                     .collect::<Vec<PathBuf>>();
 
+                let render_path = sub_m.get_one::<String>("render-path");
+
                 let mut tx = pool.begin().await?;
-                handlers::templates::assoc_template(&mut tx, &env, *repo_id, &svc_name, &templates)
-                    .await?;
+                handlers::templates::assoc_template(
+                    &mut tx,
+                    &env,
+                    *repo_id,
+                    &svc_name,
+                    &templates,
+                    render_path.map(|p| p.as_str()),
+                )
+                .await?;
+                tx.commit().await?;
+            }
+
+            Some(("scan", sub_m)) => {
+                let templates_path = sub_m.get_one::<PathBuf>("templates").context(
+                    "No templates directory specified. Use --templates <path> to specify a templates directory.",
+                )?;
+
+                let env = sub_m.get_one::<String>("environment").context(
+                    "No environment specified. Use --environment <name> to specify an environment.",
+                )?;
+
+                let mut tx = pool.begin().await?;
+                handlers::templates::scan_templates(&mut tx, env, templates_path).await?;
+                tx.commit().await?;
+            }
+
+            Some(("lint", sub_m)) => {
+                let templates_path = sub_m.get_one::<PathBuf>("templates").context(
+                    "No templates directory specified. Use --templates <path> to specify a templates directory.",
+                )?;
+
+                let env = sub_m.get_one::<String>("environment");
+
+                let mut tx = pool.begin().await?;
+                handlers::templates::lint_templates(
+                    &mut tx,
+                    templates_path,
+                    env.map(|e| e.as_str()),
+                )
+                .await?;
                 tx.commit().await?;
             }
 
@@ -251,7 +398,111 @@ async fn main() -> Result<()> {
                 let template_entries = db::list_template_info(&mut tx, &templates).await?;
                 tx.commit().await?;
 
-                println!("{}", Table::new(&template_entries).to_string());
+                output::print_table(output, &template_entries)?;
+            }
+
+            Some(("dissoc", sub_m)) => {
+                let env = sub_m.get_one::<String>("environment").context(
+                    "No environment specified. Use --environment <name> to specify an environment.",
+                )?;
+
+                let svc_name = sub_m.get_one::<String>("service").context(
+                    "No service name specified. Use --service <name> to specify a service name.",
+                )?;
+
+                let templates = sub_m
+                    .get_many::<String>("template")
+                    .unwrap_or_default()
+                    .map(|s| s.to_owned())
+                    .collect::<Vec<String>>();
+
+                let repo_id = sub_m.get_one::<i32>("repo-id").context(
+                    "No repository specified. Use --repo-id <id> to specify a repository.",
+                )?;
+
+                let mut tx = pool.begin().await?;
+                handlers::templates::dissoc_template(&mut tx, env, *repo_id, svc_name, &templates)
+                    .await?;
+                tx.commit().await?;
+            }
+
+            Some(("rm", sub_m)) => {
+                let templates = sub_m
+                    .get_many::<String>("template")
+                    .unwrap_or_default()
+                    .map(|s| s.to_owned())
+                    .collect::<Vec<String>>();
+
+                let repo_id = sub_m.get_one::<i32>("repo-id").context(
+                    "No repository specified. Use --repo-id <id> to specify a repository.",
+                )?;
+
+                let mut tx = pool.begin().await?;
+                handlers::templates::remove_templates(
+                    &mut tx,
+                    *repo_id,
+                    &templates,
+                    sub_m.get_flag("force"),
+                )
+                .await?;
+                tx.commit().await?;
+            }
+
+            Some(("mv", sub_m)) => {
+                let from_path = sub_m
+                    .get_one::<String>("FROM")
+                    .context("No template path specified to move from.")?;
+
+                let to_path = sub_m
+                    .get_one::<String>("TO")
+                    .context("No template path specified to move to.")?;
+
+                let templates_path = sub_m.get_one::<PathBuf>("templates");
+
+                let repo_id = sub_m.get_one::<i32>("repo-id").context(
+                    "No repository specified. Use --repo-id <id> to specify a repository.",
+                )?;
+
+                let mut tx = pool.begin().await?;
+                handlers::templates::move_template(
+                    &mut tx,
+                    *repo_id,
+                    from_path,
+                    to_path,
+                    templates_path.map(|p| p.as_path()),
+                )
+                .await?;
+                tx.commit().await?;
+            }
+
+            Some(("copy", sub_m)) => {
+                let from_env = sub_m.get_one::<String>("from-env").context(
+                    "No environment specified to copy from. Use --from-env <name> to specify one.",
+                )?;
+
+                let to_env = sub_m.get_one::<String>("to-env").context(
+                    "No environment specified to copy to. Use --to-env <name> to specify one.",
+                )?;
+
+                let svc_name = sub_m.get_one::<String>("service");
+
+                let mut tx = pool.begin().await?;
+                handlers::templates::copy_templates(
+                    &mut tx,
+                    from_env,
+                    to_env,
+                    svc_name.map(|s| s.as_str()),
+                )
+                .await?;
+                tx.commit().await?;
+            }
+
+            Some(("orphans", _)) => {
+                let mut tx = pool.begin().await?;
+                let orphans = db::list_orphaned_templates(&mut tx).await?;
+                tx.commit().await?;
+
+                output::print_table(output, &orphans)?;
             }
 
             _ => unreachable!("Bad templates subcommand"),
@@ -260,15 +511,14 @@ async fn main() -> Result<()> {
         Some(("services", sub_m)) => match sub_m.subcommand() {
             Some(("list", _)) => {
                 let services = handlers::services::list_all_services(&pool).await?;
-
-                for service in services {
-                    println!("{}", service);
-                }
+                output::print_names(output, &services)?;
             }
             _ => unreachable!("Bad services subcommand"),
         },
 
-        Some(("env", sub_m)) => handlers::envs::env(&pool, &sub_m).await?,
+        Some(("env", sub_m)) => handlers::envs::env(&pool, &sub_m, output).await?,
+
+        Some(("db", sub_m)) => handlers::db::db(&pool, &sub_m, output).await?,
 
         Some(("repos", sub_m)) => match sub_m.subcommand() {
             Some(("list", _)) => {
@@ -276,7 +526,7 @@ async fn main() -> Result<()> {
                 let repo_list = db::list_repos(&mut tx).await?;
                 tx.commit().await?;
 
-                println!("{}", Table::new(&repo_list).to_string());
+                output::print_table(output, &repo_list)?;
             }
 
             Some(("add", sub_m)) => {
@@ -0,0 +1,209 @@
+//! # Render manifests
+//!
+//! Every directory render writes a `manifest.json` into its output directory,
+//! recording which template each file was rendered from, by its path relative
+//! to the templates directory, and hashes of the template, the values it was
+//! populated with, and the output. Rendering a single file doesn't write one.
+//! `mgmt templates verify` recomputes the hashes to find rendered files that
+//! were edited by hand or that are out of date.
+//!
+//! A render rebuilds the entries for the files it owns, so files that it no
+//! longer writes drop out of the manifest.
+//!
+//! When the templates were checked out from a revision of a repo, the commit
+//! is recorded as well.
+//!
+//! The manifest has no timestamps and its entries are sorted by output path,
+//! so rendering the same templates with the same values always writes the same
+//! manifest.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Component, Path};
+
+/// The name of the manifest file written into render output directories.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Returns the hex encoded SHA-256 digest of some bytes.
+pub fn hash(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A file that was rendered, or copied, into the output directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The path of the file, relative to the manifest's directory.
+    pub output: String,
+
+    /// The path of the template, relative to the templates directory that
+    /// was rendered from.
+    pub template: String,
+
+    pub template_hash: String,
+    pub output_hash: String,
+
+    /// The environment whose values were used, if they came from the
+    /// database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+
+    /// The hash of the values the template was populated with. Files that
+    /// were copied instead of rendered don't have one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub values_hash: Option<String>,
+
+    /// The commit the template was read from, if it was checked out from a
+    /// repo instead of read from a local directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+/// The files rendered into a directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Reads the manifest in a directory. A directory without one has an
+    /// empty manifest.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    /// Writes the manifest into a directory, with its entries sorted.
+    pub fn save(&mut self, dir: &Path) -> Result<()> {
+        self.files.sort_by(|a, b| a.output.cmp(&b.output));
+        let path = dir.join(MANIFEST_FILE);
+        fs::write(&path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Adds an entry, replacing the one for the same output file if there is
+    /// one.
+    pub fn record(&mut self, entry: ManifestEntry) {
+        self.files.retain(|e| e.output != entry.output);
+        self.files.push(entry);
+    }
+
+    /// Drops the entries for output files that aren't in the given list.
+    pub fn retain_outputs(&mut self, outputs: &[String]) {
+        self.files.retain(|e| outputs.contains(&e.output));
+    }
+}
+
+/// Returns the path of an output file relative to the manifest's directory,
+/// with forward slashes.
+pub fn relative_output(dir: &Path, out_file: &Path) -> Result<String> {
+    let relative = out_file.strip_prefix(dir).with_context(|| {
+        format!(
+            "{} isn't in the output directory {}",
+            out_file.display(),
+            dir.display()
+        )
+    })?;
+
+    Ok(relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(output: &str) -> ManifestEntry {
+        ManifestEntry {
+            output: output.to_string(),
+            template: format!("templates/{}", output),
+            template_hash: hash(b"template"),
+            output_hash: hash(output.as_bytes()),
+            environment: Some("de".to_string()),
+            values_hash: None,
+            revision: None,
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(
+            hash(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let mut manifest = Manifest::default();
+        manifest.record(entry("b.yml"));
+        manifest.record(entry("a.yml"));
+        let mut changed = entry("b.yml");
+        changed.output_hash = hash(b"changed");
+        manifest.record(changed.clone());
+
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(manifest.files[1], changed);
+    }
+
+    #[test]
+    fn test_retain_outputs() {
+        let mut manifest = Manifest::default();
+        manifest.record(entry("a.yml"));
+        manifest.record(entry("apps/etc/apps.properties"));
+        manifest.record(entry("b.yml"));
+        manifest.retain_outputs(&["a.yml".to_string(), "b.yml".to_string()]);
+
+        assert_eq!(manifest.files, vec![entry("a.yml"), entry("b.yml")]);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("mgmt-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(Manifest::load(&dir).unwrap(), Manifest::default());
+
+        let mut manifest = Manifest::default();
+        manifest.record(entry("b.yml"));
+        manifest.record(entry("a.yml"));
+        manifest.save(&dir).unwrap();
+        let first = fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+
+        let mut loaded = Manifest::load(&dir).unwrap();
+        assert_eq!(loaded.files[0].output, "a.yml");
+        assert!(!first.contains("values_hash"));
+        loaded.save(&dir).unwrap();
+        assert_eq!(fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap(), first);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_relative_output() {
+        assert_eq!(
+            relative_output(
+                Path::new("out/de"),
+                Path::new("out/de/apps/etc/apps.properties")
+            )
+            .unwrap(),
+            "apps/etc/apps.properties"
+        );
+        assert!(relative_output(Path::new("out/de"), Path::new("other/apps.properties")).is_err());
+    }
+}
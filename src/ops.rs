@@ -5,13 +5,22 @@
 //! cloning repos into it, and the various handlers for the subcommands
 //! implemented by the tools inside this crate.
 //!
-use crate::config_values::config;
-use crate::db::{self, ConfigurationValue, LoadFromDatabase};
+use crate::config_values::{
+    config,
+    fragment::{self, FragmentValue},
+    provenance::{self, Provenance},
+    validation::{self, ValidationIssue},
+};
+use crate::db::{self, backend::Connection, ConfigurationValue, LoadFromDatabase};
+use crate::output::{self, OutputFormat};
+use crate::secrets::{self, Masker, SecretKey};
 use crate::{dolt, git, handlers::envs::populate_env_templates};
 use anyhow::{anyhow, Context};
-use sqlx::{Pool, Postgres};
+use sqlx::{Pool, Postgres, Transaction};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use tabled::Table;
 
 /// Adds a set of configuration values for an environment to the database.
 /// Interactively prompts the user for all of the values, including the
@@ -32,7 +41,9 @@ use std::path::{Path, PathBuf};
 pub async fn populate_env(pool: &Pool<Postgres>, from_env: &str) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
     let mut env_config = config::ConfigValues::default();
-    env_config.ask_for_info(&mut tx).await?;
+    env_config
+        .ask_for_info(&mut Connection::Postgres(&mut tx))
+        .await?;
     populate_env_templates(&mut tx, &from_env, &env_config.environment).await?;
     tx.commit().await?;
     Ok(())
@@ -74,25 +85,40 @@ pub async fn delete_section(pool: &Pool<Postgres>, section: &str) -> anyhow::Res
 ///
 /// # Example
 /// ```ignore
-///    list_sections(&pool).await?;
+///    list_sections(&pool, OutputFormat::Table).await?;
 /// ```
-pub async fn list_sections(pool: &Pool<Postgres>) -> anyhow::Result<()> {
+pub async fn list_sections(pool: &Pool<Postgres>, output: OutputFormat) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
     let sections = db::list_sections(&mut tx).await?;
     tx.commit().await?;
-    for section in sections {
-        println!("{}", section);
-    }
-    Ok(())
+    output::print_names(output, &sections)
+}
+
+/// Returns a Masker that masks the values of the settings marked as sensitive
+/// in the database, unless secrets are being shown.
+///
+/// # Example
+/// ```ignore
+///     let masker = new_masker(&mut tx, false).await?;
+/// ```
+pub async fn new_masker(
+    tx: &mut Transaction<'_, Postgres>,
+    show_secrets: bool,
+) -> anyhow::Result<Masker> {
+    Ok(Masker::new(
+        show_secrets,
+        db::list_sensitive_keys(tx).await?,
+    ))
 }
 
-/// Sets a default configuration value in the database.
+/// Sets a default configuration value in the database. Sensitive defaults
+/// have their values masked when they're displayed.
 ///
 /// Handler for the `mgmt-configs defaults set` command.
 ///
 /// # Example
 /// ```ignore
-///    set_default_value(&pool, "Agave", "Key", "12345", "string").await?;
+///    set_default_value(&pool, "Agave", "Key", "12345", "string", true).await?;
 /// ```
 pub async fn set_default_value(
     pool: &Pool<Postgres>,
@@ -100,12 +126,15 @@ pub async fn set_default_value(
     key: &str,
     value: &str,
     value_type: &str,
+    sensitive: bool,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
     let has_section = db::has_section(&mut tx, section).await?;
     if has_section {
+        let value = secrets::encrypt_value(value, value_type)?;
         let cfg_id =
             db::set_default_config_value(&mut tx, section, &key, &value, &value_type).await?;
+        db::set_default_sensitive(&mut tx, section, key, sensitive).await?;
         println!("Added default config value with and ID of {}", cfg_id);
     } else {
         return Err(anyhow!("No section found with name: {section}"));
@@ -121,15 +150,17 @@ pub async fn set_default_value(
 ///
 /// # Example
 /// ```ignore
-///   get_default_value(&pool, "Agave", "Key").await?;
+///   get_default_value(&pool, "Agave", "Key", false, OutputFormat::Table).await?;
 /// ```
 pub async fn get_default_value(
     pool: &Pool<Postgres>,
     section: &str,
     key: &str,
+    show_secrets: bool,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
-    let cfg: db::ConfigurationValue;
+    let mut cfg: db::ConfigurationValue;
     let has_section = db::has_section(&mut tx, section).await?;
     if has_section {
         let has_default_value = db::has_default_config_value(&mut tx, section, key).await?;
@@ -143,9 +174,10 @@ pub async fn get_default_value(
     } else {
         return Err(anyhow!("No section found with name: {section}"));
     }
+    let masker = new_masker(&mut tx, show_secrets).await?;
     tx.commit().await?;
-    println!("{:?}", cfg);
-    Ok(())
+    cfg.value = masker.display(&cfg)?;
+    output::print_fields(output, &cfg)
 }
 
 /// Deletes a default configuration value from the database and prints out a
@@ -189,35 +221,47 @@ pub async fn delete_default_value(
 /// # Example
 /// To list all of the default configuation values:
 /// ```ignore
-///    list_default_values(&pool, None, None).await?;
+///    list_default_values(&pool, None, None, false, OutputFormat::Table).await?;
 /// ```
 ///
 /// To list all of the default configuration values for a section:
 /// ```ignore
-///   list_default_values(&pool, Some("Agave"), None).await?;
+///   list_default_values(&pool, Some("Agave"), None, false, OutputFormat::Table).await?;
 /// ```
 ///
 /// To list all of the default configuration values for a section and key:
 /// ```ignore
-///     list_default_values(&pool, Some("Agave"), Some("Key"))
+///     list_default_values(&pool, Some("Agave"), Some("Key"), false, OutputFormat::Table)
 /// ```
 ///
-/// To list all of the default configuration values for a key:
+/// To list all of the default configuration values for a key, with sensitive
+/// values shown:
 /// ```ignore
-///    list_default_values(&pool, None, Some("Key"))
+///    list_default_values(&pool, None, Some("Key"), true, OutputFormat::Table)
 /// ```
 pub async fn list_default_values(
     pool: &Pool<Postgres>,
     section: Option<&str>,
     key: Option<&str>,
+    show_secrets: bool,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
     let cfgs = db::list_default_config_values(&mut tx, section, key).await?;
+    let masker = new_masker(&mut tx, show_secrets).await?;
     tx.commit().await?;
-    for cfg in cfgs {
-        println!("{}.{} = {}", cfg.section, cfg.key, cfg.value);
-    }
-    Ok(())
+    print_values(output, &masker.apply(cfgs)?)
+}
+
+// Prints configuration values as `Section.Key = value` lines, or as JSON or
+// YAML.
+fn print_values(output: OutputFormat, cfgs: &[ConfigurationValue]) -> anyhow::Result<()> {
+    output::print_with(output, cfgs, || {
+        for cfg in cfgs {
+            println!("{}.{} = {}", cfg.section, cfg.key, cfg.value);
+        }
+        Ok(())
+    })
 }
 
 /// Gets all of the default configuration values from the database and
@@ -229,30 +273,29 @@ pub async fn list_default_values(
 /// # Example
 /// To render all of the default configuration values to stdout:
 /// ```ignore
-///     render_default_values(&pool, None).await?;
+///     render_default_values(&pool, None, false).await?;
 /// ```
 ///
 /// To render all of the default configuration values to a file:
 /// ```ignore
-///     render_default_values(&pool, Some(PathBuf::from("defaults.yaml"))).await?;
+///     render_default_values(&pool, Some(PathBuf::from("defaults.yaml")), true).await?;
 /// ```
 pub async fn render_default_values(
     pool: &Pool<Postgres>,
     output_file: Option<PathBuf>,
+    show_secrets: bool,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
 
-    let all_default_cfgs = db::list_default_config_values(&mut tx, None, None).await?;
-    let mut cv = config::ConfigValues::default();
-    let mut section_options = config::SectionOptions::default();
-    section_options.set_all(true)?;
-    cv.set_section_options(section_options);
-    cv.reset_sections()?;
-    cv.cfg_set_keys(all_default_cfgs)?;
+    let masker = new_masker(&mut tx, show_secrets).await?;
+    let all_default_cfgs =
+        masker.apply(db::list_default_config_values(&mut tx, None, None).await?)?;
+    let cv = default_values(all_default_cfgs)?;
 
     if let Some(output_file) = output_file {
         let yaml = serde_yaml::to_string(&cv)?;
-        std::fs::write(output_file, yaml)?;
+        std::fs::write(&output_file, yaml)?;
+        print_masking_note(&masker, &output_file);
     } else {
         let yaml = serde_yaml::to_string(&cv)?;
         println!("{}", yaml);
@@ -263,13 +306,31 @@ pub async fn render_default_values(
     Ok(())
 }
 
+/// Builds the config values for the defaults, with every optional section
+/// included.
+///
+/// # Example
+/// ```ignore
+///    let cv = default_values(db::list_default_config_values(&mut tx, None, None).await?)?;
+/// ```
+pub fn default_values(cfgs: Vec<ConfigurationValue>) -> anyhow::Result<config::ConfigValues> {
+    let mut cv = config::ConfigValues::default();
+    let mut section_options = config::SectionOptions::default();
+    section_options.set_all(true)?;
+    cv.set_section_options(section_options);
+    cv.reset_sections()?;
+    cv.cfg_set_keys(cfgs)?;
+
+    Ok(cv)
+}
+
 /// Sets a configuration value for an environment in the database.
 ///
 /// Handler for the `mgmt-configs values set` command.
 ///
 /// # Example
 /// ```ignore
-///    set_value(&pool, "prod", "Agave", "Key", "12345", "string").await?;
+///    set_value(&pool, "prod", "Agave", "Key", "12345", "string", false).await?;
 /// ```
 pub async fn set_value(
     pool: &Pool<Postgres>,
@@ -278,6 +339,7 @@ pub async fn set_value(
     key: &str,
     value: &str,
     value_type: &str,
+    show_secrets: bool,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
 
@@ -287,19 +349,36 @@ pub async fn set_value(
     let has_config_value = db::has_config_value(&mut tx, environment, section, &key).await?;
 
     if has_default {
+        // Values for keys with secret defaults are always stored as secrets.
+        let default = db::get_default_config_value(&mut tx, section, key).await?;
+        let value_type = if secrets::is_secret(&default) {
+            secrets::SECRET_TYPE
+        } else {
+            value_type
+        };
+        let masker = new_masker(&mut tx, show_secrets).await?;
+        let shown_value = if masker.is_masking()
+            && (masker.is_sensitive(&default) || value_type == secrets::SECRET_TYPE)
+        {
+            secrets::MASK
+        } else {
+            value
+        };
+        let value = secrets::encrypt_value(value, value_type)?;
+
         if !has_config_value {
             let cfg_id = db::set_config_value(&mut tx, section, &key, &value, &value_type).await?;
             db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
             println!(
                 "Added config value to environment '{}': {}.{} = {}",
-                environment, section, key, value
+                environment, section, key, shown_value
             );
         } else {
             db::update_env_cfg_value(&mut tx, &environment, &section, &key, &value, &value_type)
                 .await?;
             println!(
                 "Updated config value in environment '{}': {}.{} = {}",
-                environment, section, key, value
+                environment, section, key, shown_value
             );
         }
     } else {
@@ -323,16 +402,18 @@ pub async fn set_value(
 ///
 /// # Example
 /// ```ignore
-///    get_value(&pool, "prod", "Agave", "Key").await?;
+///    get_value(&pool, "prod", "Agave", "Key", false).await?;
 /// ```
 pub async fn get_value(
     pool: &Pool<Postgres>,
     environment: &str,
     section: &str,
     key: &str,
+    show_secrets: bool,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
-    let cfg: db::ConfigurationValue;
+    let mut cfg: db::ConfigurationValue;
 
     let has_config_value = db::has_config_value(&mut tx, environment, section, key).await?;
     if has_config_value {
@@ -349,11 +430,14 @@ pub async fn get_value(
         }
     }
 
+    let masker = new_masker(&mut tx, show_secrets).await?;
     tx.commit().await?;
 
-    println!("{}.{} = {}", cfg.section, cfg.key, cfg.value);
-
-    Ok(())
+    cfg.value = masker.display(&cfg)?;
+    output::print_with(output, &cfg, || {
+        println!("{}.{} = {}", cfg.section, cfg.key, cfg.value);
+        Ok(())
+    })
 }
 
 /// Deletes a configuration value from an environment in the database and
@@ -389,146 +473,868 @@ pub async fn delete_value(
 /// # Example
 /// To list all of the configuration values for an environment:
 /// ```ignore
-///     list_values(&pool, Some("prod"), None, None).await?;
+///     list_values(&pool, Some("prod"), None, None, false, OutputFormat::Table).await?;
 /// ```
 ///
 /// To list all of the configuration values for a section in an environment:
 /// ```ignore
-///     list_values(&pool, Some("prod"), Some("Agave"), None).await?;
+///     list_values(&pool, Some("prod"), Some("Agave"), None, false, OutputFormat::Table).await?;
 /// ```
 ///
-/// To list all of the configuration values for a key in an environment:
+/// To list all of the configuration values for a key in an environment, with
+/// sensitive values shown:
 /// ```ignore
-///     list_values(&pool, Some("prod"), None, Some("Key")).await?;
+///     list_values(&pool, Some("prod"), None, Some("Key"), true, OutputFormat::Table).await?;
 /// ```
 pub async fn list_values(
     pool: &Pool<Postgres>,
     environment: Option<&str>,
     section: Option<&str>,
     key: Option<&str>,
+    show_secrets: bool,
+    output: OutputFormat,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
     let cfgs = db::list_config_values(&mut tx, environment, section, key).await?;
+    let masker = new_masker(&mut tx, show_secrets).await?;
     tx.commit().await?;
-    for cfg in cfgs {
-        println!("{}.{} = {}", cfg.section, cfg.key, cfg.value);
+    print_values(output, &masker.apply(cfgs)?)
+}
+
+/// Returns the names of the environments whose values apply to an
+/// environment, starting with its furthest ancestor and ending with the
+/// environment itself. Returns an error if the parents form a cycle.
+///
+/// # Example
+/// ```ignore
+///     let layers = env_layers(&mut tx, "prod").await?;
+/// ```
+pub async fn env_layers(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<Vec<String>> {
+    let mut layers = vec![environment.to_string()];
+
+    while let Some(parent) = db::get_env_parent(tx, layers.last().unwrap()).await? {
+        if layers.contains(&parent) {
+            return Err(anyhow!(
+                "the parent environments of {} form a cycle: {} -> {}",
+                environment,
+                layers.join(" -> "),
+                parent
+            ));
+        }
+        layers.push(parent);
     }
-    Ok(())
+
+    layers.reverse();
+    Ok(layers)
+}
+
+/// Returns the values set for an environment and the environments it inherits
+/// from, with the defaults left out. A value in an environment replaces the
+/// value from its parent unless it's empty, which matches how environment
+/// values are merged with the defaults.
+///
+/// # Example
+/// ```ignore
+///     let cfgs = layered_env_values(&mut tx, "prod").await?;
+/// ```
+pub async fn layered_env_values(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<Vec<ConfigurationValue>> {
+    let mut merged: Vec<ConfigurationValue> = Vec::new();
+
+    for layer in env_layers(tx, environment).await? {
+        for cfg in db::list_config_values(tx, Some(&layer), None, None).await? {
+            match merged
+                .iter_mut()
+                .find(|m| m.section == cfg.section && m.key == cfg.key)
+            {
+                Some(existing) => {
+                    if !cfg.value.is_empty() {
+                        *existing = cfg;
+                    }
+                }
+                None => merged.push(cfg),
+            }
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Returns the configuration values that apply to an environment: the value
+/// from the environment or the nearest parent environment that sets each key,
+/// otherwise the default. Empty values don't override anything, the same as
+/// when the values are rendered. Sections excluded by the section options are
+/// left out.
+///
+/// # Example
+/// ```ignore
+///     let cfgs = env_config_values(&mut tx, "prod", &opts).await?;
+/// ```
+pub async fn env_config_values(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+    opts: &config::SectionOptions,
+) -> anyhow::Result<Vec<ConfigurationValue>> {
+    Ok(resolve_env_values(tx, environment, opts)
+        .await?
+        .into_iter()
+        .map(|p| p.value)
+        .collect())
+}
+
+// Resolves the values that apply to an environment along with where each one
+// comes from. Only settings with a default are included, since those are the
+// only ones the templates can use.
+async fn resolve_env_values(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+    opts: &config::SectionOptions,
+) -> anyhow::Result<Vec<Provenance>> {
+    Ok(trace_values(tx, environment, None, None)
+        .await?
+        .into_iter()
+        .filter(|p| p.default.is_some() && opts.include_section(&p.value.section))
+        .collect())
+}
+
+/// Validates the configuration values for an environment, using the
+/// environment's feature flags to decide which optional sections must be
+/// filled in. Secret values are decrypted first so that they're checked like
+/// the rest. Returns the list of problems found.
+///
+/// # Example
+/// ```ignore
+///     let issues = validate_env(&mut tx, "prod").await?;
+/// ```
+pub async fn validate_env(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+) -> anyhow::Result<Vec<ValidationIssue>> {
+    let opts: config::SectionOptions = db::get_feature_flags(tx, environment).await?.into();
+    let cfgs = secrets::decrypt_values(env_config_values(tx, environment, &opts).await?)?;
+    Ok(validation::validate_values(&cfgs, &opts))
+}
+
+/// Validates the configuration values for an environment and prints out any
+/// problems that were found. Returns an error if there were problems.
+///
+/// Handler for the `mgmt-configs values check` command.
+///
+/// # Example
+/// ```ignore
+///     check_values(&pool, "prod").await?;
+/// ```
+pub async fn check_values(pool: &Pool<Postgres>, environment: &str) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    let issues = validate_env(&mut tx, environment).await?;
+    tx.commit().await?;
+
+    report_issues(environment, &issues)
+}
+
+/// Prints out the validation problems for an environment. Returns an error if
+/// the list isn't empty.
+pub fn report_issues(environment: &str, issues: &[ValidationIssue]) -> anyhow::Result<()> {
+    if issues.is_empty() {
+        println!("No problems found in the config values for {}.", environment);
+        return Ok(());
+    }
+
+    println!("Problems found in the config values for {}:", environment);
+    for issue in issues {
+        println!("  {}", issue);
+    }
+
+    Err(anyhow!(
+        "{} problem(s) found in the config values for {}",
+        issues.len(),
+        environment
+    ))
 }
 
 /// Gets all of the configuration values for an environment from the database
 /// and serializes them to YAML. If an output file is specified, the YAML is
-/// written to that file. Otherwise, the YAML is printed to stdout.
+/// written to that file. Otherwise, the YAML is printed to stdout. If
+/// `provenance` is true, a comment block listing where each value came from is
+/// added to the end.
 ///
 /// Handler  for the `mgmt-configs values render` command.
 ///
 /// # Example
 /// To render all of the configuration values for an environment to stdout:
 /// ```ignore
-///    render_values(&pool, "prod", &opts, None).await?;
+///    render_values(&pool, "prod", &opts, None, false, false).await?;
 /// ```
 ///
 /// To render all of the configuration values for an environment to a file:
 /// ```ignore
-///   render_values(&pool, "prod", &opts, Some(PathBuf::from("prod.yaml"))).await?;
+///   render_values(&pool, "prod", &opts, Some(PathBuf::from("prod.yaml")), false, true).await?;
 /// ```
 pub async fn render_values(
     pool: &Pool<Postgres>,
     environment: &str,
     opts: &config::SectionOptions,
     output_file: Option<PathBuf>,
+    provenance: bool,
+    show_secrets: bool,
 ) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
-    let mut all_cfgs: Vec<ConfigurationValue> = Vec::new();
-    let all_default_cfgs = db::list_default_config_values(&mut tx, None, None).await?;
-
-    for default in all_default_cfgs
-        .into_iter()
-        .filter(|cfg| opts.include_section(&cfg.section.clone()))
-    {
-        let section = default.section.clone();
-        let key = default.key.clone();
-        let has_config_value = db::has_config_value(&mut tx, environment, &section, &key)
-            .await
-            .unwrap_or(false);
-
-        if has_config_value {
-            all_cfgs.push(
-                db::get_config_value(&mut tx, environment, &section, &key)
-                    .await
-                    .unwrap(),
-            );
-        } else {
-            all_cfgs.push(default);
-        }
-    }
+    let masker = new_masker(&mut tx, show_secrets).await?;
+    let resolved = resolve_env_values(&mut tx, environment, opts).await?;
+    let all_cfgs = masker.apply(resolved.iter().map(|p| p.value.clone()).collect())?;
 
     let mut cv = config::ConfigValues::default();
     cv.set_section_options(opts.clone());
     cv.reset_sections()?;
     cv.cfg_set_keys(all_cfgs)?;
 
+    let mut yaml = serde_yaml::to_string(&cv)?;
+    if provenance {
+        yaml.push_str(&format!("\n# Provenance for {}:\n", environment));
+        for p in &resolved {
+            yaml.push_str(&format!("#   {}: {} ({})\n", p.name(), p.source, p.rows()));
+        }
+    }
+
     tx.commit().await?;
 
     if let Some(output_file) = output_file {
-        let yaml = serde_yaml::to_string(&cv)?;
-        std::fs::write(output_file, yaml)?;
+        std::fs::write(&output_file, yaml)?;
+        print_masking_note(&masker, &output_file);
     } else {
-        let yaml = serde_yaml::to_string(&cv)?;
         println!("{}", yaml);
     }
 
     Ok(())
 }
 
+// Traces where the merged values for an environment come from, including the
+// environments it inherits from, optionally limited to a single section or
+// key.
+async fn trace_values(
+    tx: &mut Transaction<'_, Postgres>,
+    environment: &str,
+    section: Option<&str>,
+    key: Option<&str>,
+) -> anyhow::Result<Vec<Provenance>> {
+    let defaults = db::list_default_config_values(tx, section, key).await?;
+    let mut layers = Vec::new();
+    for layer in env_layers(tx, environment).await? {
+        let values = db::list_config_values(tx, Some(&layer), section, key).await?;
+        layers.push((layer, values));
+    }
+
+    Ok(provenance::trace(&defaults, &layers))
+}
+
+/// Lists the services in an environment that use a config setting, along with
+/// the templates that refer to it. Relies on the references found by
+/// `mgmt templates scan`.
+///
+/// Handler for the `mgmt-configs values impact` command.
+///
+/// # Example
+/// ```ignore
+///     value_impact(&pool, "prod", "DE", "AMQP.URI", OutputFormat::Table).await?;
+/// ```
+pub async fn value_impact(
+    pool: &Pool<Postgres>,
+    environment: &str,
+    section: &str,
+    key: &str,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    db::get_env_id(&mut tx, environment)
+        .await
+        .with_context(|| format!("no environment named {}", environment))?;
+    if !db::has_default_config_value(&mut tx, section, key).await?
+        && !layered_env_values(&mut tx, environment)
+            .await?
+            .iter()
+            .any(|cfg| cfg.section == section && cfg.key == key)
+    {
+        tx.rollback().await?;
+        return Err(anyhow!("No value found for section: {section}, key: {key}"));
+    }
+    let templates = db::list_impacted_templates(&mut tx, environment, section, key).await?;
+    tx.commit().await?;
+
+    if output != OutputFormat::Table {
+        return output::print_table(output, &templates);
+    }
+
+    if templates.is_empty() {
+        println!(
+            "No services in {} use {}.{}. Run `mgmt templates scan` if the templates have changed.",
+            environment, section, key
+        );
+        return Ok(());
+    }
+
+    println!("Services in {} that use {}.{}:", environment, section, key);
+    println!("{}", Table::new(&templates));
+
+    Ok(())
+}
+
+/// Explains where the value used for a setting in an environment comes from:
+/// the environment's own value, a value inherited from a parent environment,
+/// the default, or the default because the values set are empty. Every layer
+/// that sets the value is listed along with the IDs of the rows involved.
+///
+/// Handler for the `mgmt-configs values explain` command.
+///
+/// # Example
+/// ```ignore
+///     explain_value(&pool, "prod", "Keycloak", "ServerURI", false, OutputFormat::Table).await?;
+/// ```
+pub async fn explain_value(
+    pool: &Pool<Postgres>,
+    environment: &str,
+    section: &str,
+    key: &str,
+    show_secrets: bool,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+    db::get_env_id(&mut tx, environment)
+        .await
+        .with_context(|| format!("no environment named {}", environment))?;
+    let masker = new_masker(&mut tx, show_secrets).await?;
+    let traced = trace_values(&mut tx, environment, Some(section), Some(key)).await?;
+    tx.commit().await?;
+
+    let p = traced
+        .first()
+        .ok_or_else(|| anyhow!("No value found for section: {section}, key: {key}"))?;
+
+    if output != OutputFormat::Table {
+        let mut masked = p.clone();
+        masked.value.value = masker.display(&p.value)?;
+        if let Some(default) = masked.default.as_mut() {
+            default.value = masker.display(default)?;
+        }
+        for layer in masked.layers.iter_mut() {
+            layer.value.value = masker.display(&layer.value)?;
+        }
+        return output::print_fields(output, &masked);
+    }
+
+    println!("{} = {}", p.name(), masker.display(&p.value)?);
+    match p.winner() {
+        Some(layer) => println!("  source: {} from {}", p.source, layer.environment),
+        None => println!("  source: {}", p.source),
+    }
+    if p.layers.is_empty() {
+        println!("  {} value: not set", environment);
+    }
+    for layer in p.layers.iter().rev() {
+        let cfg = &layer.value;
+        println!(
+            "  {} value: '{}' (config_values id {}, type {}){}",
+            layer.environment,
+            masker.display(cfg)?,
+            cfg.id,
+            cfg.value_type,
+            if p.winner() == Some(layer) {
+                " <- used"
+            } else {
+                ""
+            }
+        );
+    }
+    match &p.default {
+        Some(cfg) => println!(
+            "  default value: '{}' (config_defaults id {}, type {})",
+            masker.display(cfg)?,
+            cfg.id,
+            cfg.value_type
+        ),
+        None => println!("  default value: not set"),
+    }
+    if p.source == provenance::Source::EmptyOverrideIgnored {
+        println!(
+            "  The values set for {} are empty, so the default is used when templates are rendered.",
+            environment
+        );
+    }
+
+    Ok(())
+}
+
+// A change to a single config value in an environment.
+struct ValueChange {
+    value: FragmentValue,
+    value_type: String,
+    old_value: Option<String>,
+    sensitive: bool,
+}
+
+/// Applies a set of changes from a YAML, JSON, or flat `Section.Key=value`
+/// file to an environment. Every setting is checked against the defaults
+/// before anything is changed, and all of the changes are made in a single
+/// transaction. A diff of the changes is printed first. Nothing is changed if
+/// `dry_run` is true.
+///
+/// Handler for the `mgmt-configs values apply` command.
+///
+/// # Example
+/// ```ignore
+///     apply_values(&pool, "prod", Path::new("changes.yaml"), false, false).await?;
+/// ```
+pub async fn apply_values(
+    pool: &Pool<Postgres>,
+    environment: &str,
+    path: &Path,
+    dry_run: bool,
+    show_secrets: bool,
+) -> anyhow::Result<()> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let values = fragment::parse_fragment(&contents)?;
+
+    let mut tx = pool.begin().await?;
+    let env_id = db::get_env_id(&mut tx, environment)
+        .await
+        .with_context(|| format!("no environment named {}", environment))?;
+    let masker = new_masker(&mut tx, show_secrets).await?;
+
+    let defaults: HashMap<(String, String), ConfigurationValue> =
+        db::list_default_config_values(&mut tx, None, None)
+            .await?
+            .into_iter()
+            .map(|cfg| ((cfg.section.clone(), cfg.key.clone()), cfg))
+            .collect();
+
+    // Secret values have to be decrypted so they can be compared.
+    let current: HashMap<(String, String), ConfigurationValue> = secrets::decrypt_values(
+        db::list_config_values(&mut tx, Some(environment), None, None)
+            .await?
+            .into_iter()
+            .filter(|cfg| {
+                values
+                    .iter()
+                    .any(|v| v.section == cfg.section && v.key == cfg.key)
+            })
+            .collect(),
+    )?
+    .into_iter()
+    .map(|cfg| ((cfg.section.clone(), cfg.key.clone()), cfg))
+    .collect();
+
+    let mut problems: Vec<String> = Vec::new();
+    let mut changes: Vec<ValueChange> = Vec::new();
+
+    for value in values {
+        let id = (value.section.clone(), value.key.clone());
+        let default = match defaults.get(&id) {
+            Some(default) => default,
+            None => {
+                problems.push(format!(
+                    "{}.{}: no default value exists for this setting",
+                    value.section, value.key
+                ));
+                continue;
+            }
+        };
+
+        let existing = current.get(&id);
+        let is_secret = secrets::is_secret(default) || existing.is_some_and(secrets::is_secret);
+        let value_type = if is_secret {
+            secrets::SECRET_TYPE.to_string()
+        } else {
+            default.value_type.clone()
+        };
+
+        if secrets::is_masked(&value.value) {
+            problems.push(format!(
+                "{}.{}: the value is masked, render the values with --show-secrets to get the real one",
+                value.section, value.key
+            ));
+            continue;
+        }
+
+        if let Err(e) = fragment::check_type(&value.value, &default.value_type) {
+            problems.push(format!("{}.{}: {}", value.section, value.key, e));
+            continue;
+        }
+
+        let old_value = existing.map(|cfg| cfg.value.clone());
+        if old_value.as_ref() == Some(&value.value) {
+            continue;
+        }
+
+        changes.push(ValueChange {
+            sensitive: is_secret || masker.is_sensitive(default),
+            value,
+            value_type,
+            old_value,
+        });
+    }
+
+    if !problems.is_empty() {
+        tx.rollback().await?;
+        println!("Problems found in {}:", path.display());
+        for problem in &problems {
+            println!("  {}", problem);
+        }
+        return Err(anyhow!(
+            "{} problem(s) found in {}, nothing was changed",
+            problems.len(),
+            path.display()
+        ));
+    }
+
+    if changes.is_empty() {
+        tx.rollback().await?;
+        println!("No changes to apply to {}.", environment);
+        return Ok(());
+    }
+
+    println!("Changes to {}:", environment);
+    for change in &changes {
+        let shown = |v: &str| {
+            if change.sensitive && masker.is_masking() && !v.is_empty() {
+                secrets::MASK.to_string()
+            } else {
+                format!("'{}'", v)
+            }
+        };
+        match &change.old_value {
+            Some(old) => println!(
+                "  ~ {}.{}: {} -> {}",
+                change.value.section,
+                change.value.key,
+                shown(old),
+                shown(&change.value.value)
+            ),
+            None => println!(
+                "  + {}.{}: {}",
+                change.value.section,
+                change.value.key,
+                shown(&change.value.value)
+            ),
+        }
+    }
+
+    if dry_run {
+        tx.rollback().await?;
+        println!("Dry run, nothing was changed.");
+        return Ok(());
+    }
+
+    for change in &changes {
+        let section = &change.value.section;
+        let key = &change.value.key;
+        let value = secrets::encrypt_value(&change.value.value, &change.value_type)?;
+
+        if change.old_value.is_some() {
+            db::update_env_cfg_value(
+                &mut tx,
+                environment,
+                section,
+                key,
+                &value,
+                &change.value_type,
+            )
+            .await?;
+        } else {
+            let cfg_id =
+                db::set_config_value(&mut tx, section, key, &value, &change.value_type).await?;
+            db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    println!("Applied {} change(s) to {}.", changes.len(), environment);
+
+    Ok(())
+}
+
+// Lets the user know when a rendered file has masked values in it, since it
+// can't be used for a deployment as-is.
+fn print_masking_note(masker: &Masker, output_file: &Path) {
+    if masker.is_masking() {
+        println!(
+            "Sensitive values in {} were masked. Use --show-secrets to include them.",
+            output_file.display()
+        );
+    }
+}
+
 /// Imports a YAML file into the database. The YAML file must be in the same
 /// format as the output of the `mgmt-configs values render` command.
 ///
+/// Values are upserted by section and key, so importing the same file twice
+/// doesn't change anything the second time around. Every setting is checked
+/// for a default value before anything is written, and all of the settings
+/// without one are reported together. If `prune` is true, values in the
+/// environment that aren't in the file are deleted. A summary of the changes
+/// is printed at the end.
+///
 /// Handler for importing files.
 ///
 /// # Example
 /// ```ignore
-///    import_yaml_file(&pool, PathBuf::from("prod.yaml"), "prod").await?;
+///    import_yaml_file(&pool, PathBuf::from("prod.yaml"), "prod", false, false).await?;
 /// ```
 pub async fn import_yaml_file(
     pool: &Pool<Postgres>,
     path: PathBuf,
     environment: &str,
+    prune: bool,
+    show_secrets: bool,
 ) -> anyhow::Result<()> {
-    let mut tx = pool.begin().await?;
-    let file = std::fs::File::open(path)?;
+    let file = std::fs::File::open(&path)?;
     let mut cv: config::ConfigValues = serde_yaml::from_reader(file)?;
     cv.set_section_options(cv.generate_section_options());
-
     let items: Vec<db::ConfigurationValue> = cv.into();
-    for item in items.into_iter() {
-        let section = item.section.clone();
-        let key = item.key.clone();
-        let value = item.value.clone();
-        let value_type = item.value_type.clone();
-        println!("{}.{} = {}", section, key, value);
-        let real_section: String;
-        if section.is_empty() {
-            real_section = "TopLevel".to_string();
-        } else {
-            real_section = section;
+
+    let mut tx = pool.begin().await?;
+    let env_id = db::get_env_id(&mut tx, environment)
+        .await
+        .with_context(|| format!("no environment named {}", environment))?;
+    let masker = new_masker(&mut tx, show_secrets).await?;
+
+    let defaults: HashMap<(String, String), ConfigurationValue> =
+        db::list_default_config_values(&mut tx, None, None)
+            .await?
+            .into_iter()
+            .map(|cfg| ((cfg.section.clone(), cfg.key.clone()), cfg))
+            .collect();
+
+    let unknown: Vec<String> = items
+        .iter()
+        .map(|item| (import_section(&item.section), item.key.clone()))
+        .filter(|id| !defaults.contains_key(id))
+        .map(|(section, key)| format!("{}.{}", section, key))
+        .collect();
+    if !unknown.is_empty() {
+        tx.rollback().await?;
+        println!(
+            "No default value found for these settings in {}:",
+            path.display()
+        );
+        for name in &unknown {
+            println!("  {}", name);
         }
+        return Err(anyhow!(
+            "{} setting(s) in {} have no default value, nothing was imported",
+            unknown.len(),
+            path.display()
+        ));
+    }
 
-        if db::has_default_config_value(&mut tx, &real_section, &key).await? {
-            let cfg_id =
-                db::set_config_value(&mut tx, &real_section, &key, &value, &value_type).await?;
-            let env_id = db::get_env_id(&mut tx, &environment).await?;
-            db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
+    let masked: Vec<String> = items
+        .iter()
+        .filter(|item| secrets::is_masked(&item.value))
+        .map(|item| format!("{}.{}", import_section(&item.section), item.key))
+        .collect();
+    if !masked.is_empty() {
+        tx.rollback().await?;
+        println!("These settings in {} have masked values:", path.display());
+        for name in &masked {
+            println!("  {}", name);
+        }
+        return Err(anyhow!(
+            "{} setting(s) in {} have masked values, nothing was imported. Render the values with --show-secrets to get the real ones",
+            masked.len(),
+            path.display()
+        ));
+    }
+
+    // Earlier versions of the import could leave more than one value for the
+    // same setting, so every stored value is tracked here. Secret values have
+    // to be decrypted so they can be compared.
+    let mut current: HashMap<(String, String), Vec<ConfigurationValue>> = HashMap::new();
+    for cfg in secrets::decrypt_values(
+        db::list_config_values(&mut tx, Some(environment), None, None).await?,
+    )? {
+        current
+            .entry((cfg.section.clone(), cfg.key.clone()))
+            .or_default()
+            .push(cfg);
+    }
+
+    let shown = |cfg: &ConfigurationValue, sensitive: bool| {
+        if sensitive && masker.is_masking() && !cfg.value.is_empty() {
+            secrets::MASK.to_string()
         } else {
-            tx.rollback().await?;
-            return Err(anyhow!(
-                "No default value found for section: {real_section}, key: {key}"
-            ));
+            cfg.value.clone()
+        }
+    };
+
+    let (mut created, mut updated, mut unchanged, mut removed) = (0, 0, 0, 0);
+
+    for mut item in items {
+        item.section = import_section(&item.section);
+        let id = (item.section.clone(), item.key.clone());
+        let default = &defaults[&id];
+        if secrets::is_secret(default) {
+            item.value_type = secrets::SECRET_TYPE.to_string();
         }
+        let sensitive = masker.is_sensitive(default) || secrets::is_secret(&item);
+        let value = secrets::encrypt_value(&item.value, &item.value_type)?;
+
+        let mut existing = current.remove(&id).unwrap_or_default().into_iter();
+        match existing.next() {
+            None => {
+                let cfg_id = db::set_config_value(
+                    &mut tx,
+                    &item.section,
+                    &item.key,
+                    &value,
+                    &item.value_type,
+                )
+                .await?;
+                db::add_env_cfg_value(&mut tx, env_id, cfg_id).await?;
+                println!(
+                    "  + {}.{} = {}",
+                    item.section,
+                    item.key,
+                    shown(&item, sensitive)
+                );
+                created += 1;
+            }
+            Some(cfg) if cfg.value == item.value && cfg.value_type == item.value_type => {
+                unchanged += 1;
+            }
+            Some(cfg) => {
+                db::update_typed_config_value_by_id(&mut tx, cfg.id, &value, &item.value_type)
+                    .await?;
+                println!(
+                    "  ~ {}.{} = {}",
+                    item.section,
+                    item.key,
+                    shown(&item, sensitive)
+                );
+                updated += 1;
+            }
+        }
+
+        for duplicate in existing {
+            db::delete_config_value_by_id(&mut tx, duplicate.id).await?;
+            removed += 1;
+        }
+    }
+
+    if prune {
+        let mut leftover: Vec<ConfigurationValue> = current.into_values().flatten().collect();
+        leftover.sort_by(|a, b| (&a.section, &a.key).cmp(&(&b.section, &b.key)));
+        for cfg in leftover {
+            db::delete_config_value_by_id(&mut tx, cfg.id).await?;
+            println!("  - {}.{}", cfg.section, cfg.key);
+            removed += 1;
+        }
+    }
+
+    tx.commit().await?;
+
+    println!(
+        "{} created, {} updated, {} unchanged, {} removed.",
+        created, updated, unchanged, removed
+    );
+
+    Ok(())
+}
+
+// Settings at the top level of a values file don't have a section when
+// they're read in, but are stored in the TopLevel section.
+fn import_section(section: &str) -> String {
+    if section.is_empty() {
+        "TopLevel".to_string()
+    } else {
+        section.to_string()
+    }
+}
+
+/// Generates a new key for encrypting secret configuration values and writes
+/// it out to a file. Uses the configured key file if no file is given. Refuses
+/// to overwrite an existing key.
+///
+/// Handler for the `mgmt-configs secrets generate-key` command.
+///
+/// # Example
+/// ```ignore
+///     generate_secrets_key(Some(PathBuf::from("secrets.key")))?;
+/// ```
+pub fn generate_secrets_key(file: Option<PathBuf>) -> anyhow::Result<()> {
+    let path = match file {
+        Some(file) => file,
+        None => secrets::key_file_path()?,
+    };
+
+    if path.exists() {
+        return Err(anyhow!("{} already exists", path.display()));
+    }
+
+    SecretKey::generate().write_to_file(&path)?;
+    println!("Wrote a new secrets key to {}", path.display());
+
+    Ok(())
+}
+
+/// Re-encrypts every secret configuration value, including defaults, with a
+/// new key. The current key is loaded the usual way. If the new key file
+/// doesn't exist, a new key is generated and written to it. Everything is
+/// updated in a single transaction.
+///
+/// Handler for the `mgmt-configs secrets rotate-key` command.
+///
+/// # Example
+/// ```ignore
+///     rotate_secrets_key(&pool, Path::new("new-secrets.key")).await?;
+/// ```
+pub async fn rotate_secrets_key(pool: &Pool<Postgres>, new_key_file: &Path) -> anyhow::Result<()> {
+    let old_key = SecretKey::load()?;
+    let new_key = if new_key_file.exists() {
+        SecretKey::from_file(new_key_file)?
+    } else {
+        let key = SecretKey::generate();
+        key.write_to_file(new_key_file)?;
+        println!("Wrote a new secrets key to {}", new_key_file.display());
+        key
+    };
+
+    let mut tx = pool.begin().await?;
+
+    let cfgs = db::list_secret_config_values(&mut tx).await?;
+    for cfg in &cfgs {
+        let plaintext = old_key
+            .decrypt(&cfg.value)
+            .with_context(|| format!("failed to decrypt {}.{}", cfg.section, cfg.key))?;
+        db::update_config_value_by_id(&mut tx, cfg.id, &new_key.encrypt(&plaintext)?).await?;
+    }
+
+    let defaults = db::list_secret_default_values(&mut tx).await?;
+    for cfg in &defaults {
+        let plaintext = old_key
+            .decrypt(&cfg.value)
+            .with_context(|| format!("failed to decrypt default {}.{}", cfg.section, cfg.key))?;
+        db::update_default_value_by_id(&mut tx, cfg.id, &new_key.encrypt(&plaintext)?).await?;
     }
 
     tx.commit().await?;
 
+    println!(
+        "Re-encrypted {} secret value(s) and {} secret default(s).",
+        cfgs.len(),
+        defaults.len()
+    );
+    println!(
+        "Point {} at {} (or set {}) to use the new key.",
+        secrets::KEY_FILE_ENV_VAR,
+        new_key_file.display(),
+        secrets::KEY_ENV_VAR
+    );
+
     Ok(())
 }
 
@@ -0,0 +1,150 @@
+//! # Output
+//!
+//! Formats the results of the read commands. Everything can be printed as a
+//! table for people or as JSON or YAML for scripts, based on the global
+//! `--format` setting.
+use anyhow::Result;
+use clap::ArgMatches;
+use serde::Serialize;
+use tabled::{builder::Builder, Table, Tabled};
+
+/// The format results are printed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Tables and plain text.
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Returns the format selected with the global `--format` setting.
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        matches
+            .get_one::<OutputFormat>("format")
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Serializes a value as JSON or YAML. Returns None for table output.
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Option<String>> {
+        Ok(match self {
+            OutputFormat::Table => None,
+            OutputFormat::Json => Some(serde_json::to_string_pretty(value)?),
+            OutputFormat::Yaml => Some(serde_yaml::to_string(value)?.trim_end().to_string()),
+        })
+    }
+}
+
+/// Prints a list of records as a table, or as a JSON or YAML array.
+///
+/// # Example
+/// ```ignore
+/// print_table(format, &repos)?;
+/// ```
+pub fn print_table<T: Serialize + Tabled>(format: OutputFormat, items: &[T]) -> Result<()> {
+    match format.serialize(items)? {
+        Some(serialized) => println!("{}", serialized),
+        None => println!("{}", Table::new(items)),
+    }
+
+    Ok(())
+}
+
+/// Prints a list of names one per line, or as a JSON or YAML array.
+///
+/// # Example
+/// ```ignore
+/// print_names(format, &envs)?;
+/// ```
+pub fn print_names(format: OutputFormat, names: &[String]) -> Result<()> {
+    match format.serialize(names)? {
+        Some(serialized) => println!("{}", serialized),
+        None => names.iter().for_each(|name| println!("{}", name)),
+    }
+
+    Ok(())
+}
+
+/// Prints the fields of a single record as a two column table, or as a JSON or
+/// YAML object.
+///
+/// # Example
+/// ```ignore
+/// print_fields(format, &flags)?;
+/// ```
+pub fn print_fields<T: Serialize>(format: OutputFormat, value: &T) -> Result<()> {
+    if let Some(serialized) = format.serialize(value)? {
+        println!("{}", serialized);
+        return Ok(());
+    }
+
+    let mut builder = Builder::default();
+    builder.set_header(["field", "value"]);
+    if let serde_json::Value::Object(fields) = serde_json::to_value(value)? {
+        for (field, value) in fields {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            builder.push_record([field, value]);
+        }
+    }
+    println!("{}", builder.build());
+
+    Ok(())
+}
+
+/// Prints a value as JSON or YAML. For table output the `text` function is
+/// called instead, so the command can print its usual human readable output.
+///
+/// # Example
+/// ```ignore
+/// print_with(format, &cfgs, || {
+///     cfgs.iter().for_each(|cfg| println!("{}.{} = {}", cfg.section, cfg.key, cfg.value));
+///     Ok(())
+/// })?;
+/// ```
+pub fn print_with<T, F>(format: OutputFormat, value: &T, text: F) -> Result<()>
+where
+    T: Serialize + ?Sized,
+    F: FnOnce() -> Result<()>,
+{
+    match format.serialize(value)? {
+        Some(serialized) => {
+            println!("{}", serialized);
+            Ok(())
+        }
+        None => text(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Serialize, Tabled)]
+    struct Row {
+        id: i32,
+        name: String,
+    }
+
+    #[test]
+    fn test_serialize() {
+        let rows = vec![Row {
+            id: 1,
+            name: "de".to_string(),
+        }];
+
+        assert_eq!(OutputFormat::Table.serialize(&rows).unwrap(), None);
+        assert_eq!(
+            OutputFormat::Json.serialize(&rows).unwrap().unwrap(),
+            "[\n  {\n    \"id\": 1,\n    \"name\": \"de\"\n  }\n]"
+        );
+        assert_eq!(
+            OutputFormat::Yaml.serialize(&rows).unwrap().unwrap(),
+            "- id: 1\n  name: de"
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! # Secrets
+//!
+//! Encrypts and decrypts configuration values that have the `secret` value
+//! type. Secret values are encrypted with an age X25519 key before they're
+//! written to the database and are only decrypted when templates are rendered.
+//!
+//! The key is read from the `MGMT_SECRETS_KEY` environment variable if it's
+//! set. Otherwise it's read from the file named by `MGMT_SECRETS_KEY_FILE`,
+//! falling back to `~/.config/mgmt/secrets.key`. Key files use the same format
+//! as the ones created by `age-keygen`.
+use crate::db::ConfigurationValue;
+use age::secrecy::ExposeSecret;
+use age::x25519;
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::{env, fs, iter};
+
+/// The name of the value type used for encrypted configuration values.
+pub const SECRET_TYPE: &str = "secret";
+
+/// What gets printed in place of a secret value.
+pub const MASK: &str = "********";
+
+/// The environment variable containing the secrets key itself.
+pub const KEY_ENV_VAR: &str = "MGMT_SECRETS_KEY";
+
+/// The environment variable containing the path to the secrets key file.
+pub const KEY_FILE_ENV_VAR: &str = "MGMT_SECRETS_KEY_FILE";
+
+// Prepended to encrypted values so they can be told apart from plaintext.
+const PREFIX: &str = "age:";
+
+/// Returns whether the configuration value has the secret value type.
+pub fn is_secret(cfg: &ConfigurationValue) -> bool {
+    cfg.value_type == SECRET_TYPE
+}
+
+/// Returns whether the value is the mask printed in place of a secret. A
+/// masked value read back from a rendered file would overwrite the real
+/// secret, so it's never accepted as a new value.
+pub fn is_masked(value: &str) -> bool {
+    value == MASK
+}
+
+/// Returns whether the value was encrypted by a `SecretKey`.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Returns the path to the secrets key file, either from the
+/// `MGMT_SECRETS_KEY_FILE` environment variable or the default location in the
+/// user's home directory.
+pub fn key_file_path() -> Result<PathBuf> {
+    if let Ok(path) = env::var(KEY_FILE_ENV_VAR) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let home = env::var("HOME").context("HOME is not set, can't find the secrets key file")?;
+    Ok(PathBuf::from(home)
+        .join(".config")
+        .join("mgmt")
+        .join("secrets.key"))
+}
+
+/// The key used to encrypt and decrypt secret configuration values.
+pub struct SecretKey {
+    identity: x25519::Identity,
+}
+
+impl SecretKey {
+    /// Generates a brand new key.
+    pub fn generate() -> Self {
+        SecretKey {
+            identity: x25519::Identity::generate(),
+        }
+    }
+
+    /// Loads the key from the `MGMT_SECRETS_KEY` environment variable or the
+    /// key file.
+    pub fn load() -> Result<Self> {
+        if let Ok(key) = env::var(KEY_ENV_VAR) {
+            return SecretKey::parse(&key);
+        }
+
+        let path = key_file_path()?;
+        SecretKey::from_file(&path).with_context(|| {
+            format!(
+                "failed to load the secrets key. Set {} or {}, or create {}",
+                KEY_ENV_VAR,
+                KEY_FILE_ENV_VAR,
+                path.display()
+            )
+        })
+    }
+
+    /// Reads the key from a file. Blank lines and comments are skipped.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        SecretKey::parse(&contents)
+    }
+
+    // Parses the first line that isn't blank or a comment as an age identity.
+    fn parse(contents: &str) -> Result<Self> {
+        let line = contents
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or_else(|| anyhow!("no secrets key found"))?;
+
+        let identity = line
+            .parse::<x25519::Identity>()
+            .map_err(|e| anyhow!("invalid secrets key: {}", e))?;
+
+        Ok(SecretKey { identity })
+    }
+
+    /// Writes the key out to a file in the format used by `age-keygen`. The
+    /// file is only readable by the current user.
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let contents = format!(
+            "# public key: {}\n{}\n",
+            self.identity.to_public(),
+            self.identity.to_string().expose_secret()
+        );
+        fs::write(path, contents)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts a value. Empty values are left alone so that unset secrets
+    /// still show up as missing.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        if plaintext.is_empty() {
+            return Ok(String::new());
+        }
+
+        let encryptor = age::Encryptor::with_recipients(vec![Box::new(self.identity.to_public())])
+            .context("no recipients for the secrets key")?;
+
+        let mut encrypted = vec![];
+        let mut writer = encryptor.wrap_output(&mut encrypted)?;
+        writer.write_all(plaintext.as_bytes())?;
+        writer.finish()?;
+
+        Ok(format!(
+            "{}{}",
+            PREFIX,
+            general_purpose::STANDARD.encode(encrypted)
+        ))
+    }
+
+    /// Decrypts a value that was encrypted with `encrypt()`.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        if value.is_empty() {
+            return Ok(String::new());
+        }
+
+        let encoded = value
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| anyhow!("the value is not encrypted"))?;
+        let encrypted = general_purpose::STANDARD.decode(encoded)?;
+
+        let decryptor = match age::Decryptor::new(&encrypted[..])? {
+            age::Decryptor::Recipients(d) => d,
+            _ => return Err(anyhow!("the value was not encrypted with a secrets key")),
+        };
+
+        let mut decrypted = vec![];
+        let mut reader = decryptor.decrypt(iter::once(&self.identity as &dyn age::Identity))?;
+        reader.read_to_end(&mut decrypted)?;
+
+        Ok(String::from_utf8(decrypted)?)
+    }
+}
+
+/// Encrypts the value if the value type is `secret`, otherwise returns it
+/// unchanged. The key is only loaded when it's needed.
+///
+/// # Example
+/// ```ignore
+///     let value = secrets::encrypt_value("hunter2", "secret")?;
+/// ```
+pub fn encrypt_value(value: &str, value_type: &str) -> Result<String> {
+    if value_type != SECRET_TYPE {
+        return Ok(value.to_string());
+    }
+
+    SecretKey::load()?.encrypt(value)
+}
+
+/// Decrypts any secret values in the list. The key is only loaded if there
+/// are secret values to decrypt.
+///
+/// # Example
+/// ```ignore
+///     let cfgs = secrets::decrypt_values(db::list_config_values(tx, Some("prod"), None, None).await?)?;
+/// ```
+pub fn decrypt_values(cfgs: Vec<ConfigurationValue>) -> Result<Vec<ConfigurationValue>> {
+    if !cfgs.iter().any(is_secret) {
+        return Ok(cfgs);
+    }
+
+    let key = SecretKey::load()?;
+    cfgs.into_iter()
+        .map(|mut cfg| {
+            if is_secret(&cfg) {
+                cfg.value = key
+                    .decrypt(&cfg.value)
+                    .with_context(|| format!("failed to decrypt {}.{}", cfg.section, cfg.key))?;
+            }
+            Ok(cfg)
+        })
+        .collect()
+}
+
+/// Decides which configuration values get masked before they're shown to the
+/// user. A value is sensitive if it has the secret value type or if its
+/// default is marked as sensitive.
+pub struct Masker {
+    show_secrets: bool,
+    sensitive: HashSet<(String, String)>,
+}
+
+impl Masker {
+    /// Creates a new Masker. The sensitive keys are (section, key) pairs.
+    pub fn new(show_secrets: bool, sensitive: Vec<(String, String)>) -> Self {
+        Masker {
+            show_secrets,
+            sensitive: sensitive.into_iter().collect(),
+        }
+    }
+
+    /// Returns whether sensitive values will be masked.
+    pub fn is_masking(&self) -> bool {
+        !self.show_secrets
+    }
+
+    /// Returns whether the configuration value is sensitive.
+    pub fn is_sensitive(&self, cfg: &ConfigurationValue) -> bool {
+        is_secret(cfg)
+            || self
+                .sensitive
+                .contains(&(cfg.section.clone(), cfg.key.clone()))
+    }
+
+    /// Returns the value as it should be shown to the user. Secret values are
+    /// decrypted first when secrets are being shown.
+    pub fn display(&self, cfg: &ConfigurationValue) -> Result<String> {
+        Ok(self.apply(vec![cfg.clone()])?.remove(0).value)
+    }
+
+    /// Prepares a list of values to be shown to the user. Non-empty sensitive
+    /// values are masked, unless secrets are being shown, in which case secret
+    /// values are decrypted instead.
+    pub fn apply(&self, cfgs: Vec<ConfigurationValue>) -> Result<Vec<ConfigurationValue>> {
+        if self.show_secrets {
+            return decrypt_values(cfgs);
+        }
+
+        Ok(cfgs
+            .into_iter()
+            .map(|mut cfg| {
+                if self.is_sensitive(&cfg) && !cfg.value.is_empty() {
+                    cfg.value = MASK.to_string();
+                }
+                cfg
+            })
+            .collect())
+    }
+}
+
+/// Masks secret values wherever they show up in a piece of text, such as a
+/// rendered config file. The text in front of a secret on its line (e.g.
+/// `password = `) is remembered as well, so that another version of the same
+/// line holding a value that isn't known, like the one a cluster still has,
+/// gets masked too. Values shorter than four characters would mask unrelated
+/// text if they were replaced wherever they show up, so they're only masked
+/// where they make up the end of a line, through the text in front of them.
+#[derive(Debug, Clone, Default)]
+pub struct TextMasker {
+    values: Vec<String>,
+    short_values: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl TextMasker {
+    /// Creates a new TextMasker for the secret values. Values with more than
+    /// one line are masked a line at a time.
+    pub fn new<I: IntoIterator<Item = String>>(values: I) -> Self {
+        let (mut lines, mut short_values): (Vec<String>, Vec<String>) = values
+            .into_iter()
+            .flat_map(|v| {
+                v.lines()
+                    .map(|l| l.trim().to_string())
+                    .collect::<Vec<String>>()
+            })
+            .filter(|l| !l.is_empty())
+            .partition(|l| l.chars().count() >= 4);
+        lines.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        lines.dedup();
+        short_values.sort();
+        short_values.dedup();
+
+        TextMasker {
+            values: lines,
+            short_values,
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Remembers what comes before each secret value in the text.
+    pub fn learn(&mut self, text: &str) {
+        let mut found: Vec<&str> = Vec::new();
+        for line in text.lines() {
+            for value in &self.values {
+                if let Some(pos) = line.find(value.as_str()) {
+                    found.push(&line[..pos]);
+                }
+            }
+
+            let line = line.trim_end();
+            for value in &self.short_values {
+                // Only a whole value counts, not the end of a longer one.
+                if let Some(prefix) = line.strip_suffix(value.as_str()) {
+                    if prefix.ends_with(|c: char| !c.is_alphanumeric()) {
+                        found.push(prefix);
+                    }
+                }
+            }
+        }
+
+        for prefix in found {
+            if !prefix.trim().is_empty() && !self.prefixes.iter().any(|p| p == prefix) {
+                self.prefixes.push(prefix.to_string());
+            }
+        }
+    }
+
+    /// Returns the line with its secrets masked.
+    pub fn mask_line(&self, line: &str) -> String {
+        let mut masked = line.to_string();
+        for value in &self.values {
+            masked = masked.replace(value.as_str(), MASK);
+        }
+
+        for prefix in &self.prefixes {
+            if let Some(rest) = masked.strip_prefix(prefix.as_str()) {
+                if !rest.is_empty() && !rest.starts_with(MASK) {
+                    masked = format!("{}{}", prefix, MASK);
+                }
+                break;
+            }
+        }
+
+        masked
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let key = SecretKey::generate();
+        let encrypted = key.encrypt("hunter2").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("hunter2"));
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "hunter2");
+        assert_eq!(key.encrypt("").unwrap(), "");
+
+        let other = SecretKey::generate();
+        assert!(other.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_parse_key_file() {
+        let key = SecretKey::generate();
+        let contents = format!(
+            "# created: today\n\n{}\n",
+            key.identity.to_string().expose_secret()
+        );
+        let parsed = SecretKey::parse(&contents).unwrap();
+        let encrypted = key.encrypt("value").unwrap();
+        assert_eq!(parsed.decrypt(&encrypted).unwrap(), "value");
+    }
+
+    #[test]
+    fn test_masker() {
+        let cfg = |key: &str, value: &str, value_type: &str| ConfigurationValue {
+            id: 0,
+            section: "DEDB".to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            value_type: value_type.to_string(),
+        };
+        let cfgs = vec![
+            cfg("Password", "age:abc", SECRET_TYPE),
+            cfg("User", "de", "string"),
+            cfg("Token", "abc123", "string"),
+            cfg("Other", "", SECRET_TYPE),
+        ];
+
+        let masker = Masker::new(false, vec![("DEDB".to_string(), "Token".to_string())]);
+        let values: Vec<String> = masker
+            .apply(cfgs.clone())
+            .unwrap()
+            .into_iter()
+            .map(|c| c.value)
+            .collect();
+        assert_eq!(values, vec![MASK, "de", MASK, ""]);
+        assert!(is_masked(&values[0]));
+        assert!(!is_masked(&values[1]));
+
+        let masker = Masker::new(true, vec![("DEDB".to_string(), "Token".to_string())]);
+        assert!(!masker.is_masking());
+        assert_eq!(masker.display(&cfgs[2]).unwrap(), "abc123");
+    }
+
+    #[test]
+    fn test_text_masker() {
+        let mut masker = TextMasker::new(vec![
+            "hunter2".to_string(),
+            "abc".to_string(),
+            "line one\nline two".to_string(),
+        ]);
+        masker.learn("user = de\npassword = hunter2\n");
+
+        assert_eq!(
+            masker.mask_line("password = hunter2"),
+            "password = ********"
+        );
+        assert_eq!(
+            masker.mask_line("password = oldpass"),
+            "password = ********"
+        );
+        assert_eq!(masker.mask_line("user = abc"), "user = abc");
+
+        masker.learn("pin = abc\nname = xabc\n");
+        assert_eq!(masker.mask_line("pin = abc"), "pin = ********");
+        assert_eq!(masker.mask_line("pin = xyz"), "pin = ********");
+        assert_eq!(masker.mask_line("name = xabc"), "name = xabc");
+        assert_eq!(masker.mask_line("  line two"), "  ********");
+        assert_eq!(masker.mask_line("x=hunter2hunter2"), "x=****************");
+    }
+}
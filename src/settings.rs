@@ -0,0 +1,467 @@
+//! # Settings
+//!
+//! Loads the settings `mgmt` uses when they aren't given on the command line.
+//! Settings are grouped into named profiles in `~/.config/mgmt/config.toml`:
+//!
+//! ```toml
+//! default_profile = "local-dolt"
+//!
+//! [profiles.prod]
+//! database_url = "postgresql://mgmt@db.example.org:5432/de_releases"
+//! releases_repo = "https://github.com/cyverse-de/de-releases"
+//! releases_branch = "main"
+//! default_env = "prod"
+//! kube_context = "prod"
+//! site_dir = "/opt/sites/prod"
+//!
+//! [profiles.local-dolt]
+//! database_url = "mysql://root@127.0.0.1:3306/de_releases"
+//! ```
+//!
+//! A setting is taken from the first of these that has it: the command line,
+//! the matching `MGMT_*` environment variable, the selected profile, and
+//! finally the built-in default. The profile is selected with `--profile`,
+//! then `MGMT_PROFILE`, then `default_profile` in the file. An empty
+//! `MGMT_PROFILE` counts as unset, like the other `MGMT_*` variables.
+//!
+//! A `mysql://` URL like the one in `local-dolt` only works for the commands
+//! that support Dolt, which are listed in `docs/database.md`.
+use anyhow::{anyhow, Context, Result};
+use clap::{Arg, ArgMatches, Command};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// The environment variable that overrides the location of the config file.
+pub const CONFIG_FILE_ENV_VAR: &str = "MGMT_CONFIG";
+
+/// The environment variable that selects a profile.
+pub const PROFILE_ENV_VAR: &str = "MGMT_PROFILE";
+
+/// The environment variable that sets the Kubernetes context used by kubectl
+/// and skaffold.
+pub const KUBE_CONTEXT_ENV_VAR: &str = "MGMT_KUBE_CONTEXT";
+
+/// The ID of the global option that selects the Kubernetes context.
+pub const KUBE_CONTEXT_ARG: &str = "kube-context";
+
+/// The commands that fall back to `default_env` when no environment is given.
+/// They only read from the database and the cluster, so running one against
+/// the wrong environment can't change anything. Every other command needs an
+/// explicit environment.
+pub const DEFAULT_ENV_COMMANDS: &[&str] = &[
+    "mgmt configs values get",
+    "mgmt configs values list",
+    "mgmt configs values explain",
+    "mgmt configs values impact",
+    "mgmt configs values render",
+    "mgmt configs values check",
+    "mgmt env service list",
+    "mgmt env feature-flags list",
+    "mgmt templates render-file-db",
+    "mgmt templates render-dir-db",
+    "mgmt templates render-db",
+];
+
+/// Describes the settings and precedence rules in `mgmt --help`.
+pub const HELP: &str = "\
+Settings that aren't given on the command line are read from MGMT_* environment
+variables, then from the selected profile in ~/.config/mgmt/config.toml (or
+$MGMT_CONFIG), then from the built-in defaults. The profile is selected with
+--profile, then MGMT_PROFILE, then default_profile in the file.
+
+Profile setting     Environment variable    Used for
+database_url        MGMT_DATABASE_URL       --database-url
+releases_repo       MGMT_RELEASES_REPO      release create/deploy --repo-url
+releases_branch     MGMT_RELEASES_BRANCH    release create/deploy --branch
+default_env         MGMT_ENV                --env/--environment of read-only commands
+kube_context        MGMT_KUBE_CONTEXT       --kube-context
+site_dir            MGMT_SITE_DIR           site init/deploy --dir";
+
+/// A named set of settings from the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub database_url: Option<String>,
+    pub releases_repo: Option<String>,
+    pub releases_branch: Option<String>,
+    pub default_env: Option<String>,
+    pub kube_context: Option<String>,
+    pub site_dir: Option<String>,
+}
+
+impl Profile {
+    // Replaces the settings that have an environment variable set. The lookup
+    // function is passed in so this can be tested without touching the real
+    // environment.
+    fn with_env_vars<F>(mut self, var: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let settings = [
+            ("MGMT_DATABASE_URL", &mut self.database_url),
+            ("MGMT_RELEASES_REPO", &mut self.releases_repo),
+            ("MGMT_RELEASES_BRANCH", &mut self.releases_branch),
+            ("MGMT_ENV", &mut self.default_env),
+            (KUBE_CONTEXT_ENV_VAR, &mut self.kube_context),
+            ("MGMT_SITE_DIR", &mut self.site_dir),
+        ];
+
+        for (name, setting) in settings {
+            if let Some(value) = var(name).filter(|v| !v.is_empty()) {
+                *setting = Some(value);
+            }
+        }
+
+        self
+    }
+}
+
+// The contents of config.toml.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// The settings in effect for this run of `mgmt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// The name of the selected profile, if there is one.
+    pub profile_name: Option<String>,
+    /// The selected profile with the environment variables applied.
+    pub profile: Profile,
+}
+
+/// Returns the path to the config file: `$MGMT_CONFIG` if it's set, otherwise
+/// `mgmt/config.toml` in `$XDG_CONFIG_HOME` or `~/.config`.
+pub fn config_path() -> Option<PathBuf> {
+    if let Ok(path) = env::var(CONFIG_FILE_ENV_VAR) {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok()?;
+
+    Some(config_home.join("mgmt").join("config.toml"))
+}
+
+/// Finds the value of the `--profile` (or `-P`) option in the command line
+/// arguments. The profile has to be known before the command line is parsed,
+/// since it provides the defaults for the other options.
+pub fn profile_from_args(args: &[String]) -> Option<String> {
+    let mut args = args.iter().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--profile" || arg == "-P" {
+            return args.next().cloned();
+        }
+        if let Some(value) = arg.strip_prefix("--profile=") {
+            return Some(value.to_string());
+        }
+        if let Some(value) = arg.strip_prefix("-P").filter(|v| !v.is_empty()) {
+            return Some(value.trim_start_matches('=').to_string());
+        }
+    }
+
+    None
+}
+
+// Returns the profile asked for with `--profile` or `MGMT_PROFILE`, if any. An
+// empty `MGMT_PROFILE` counts as unset. The lookup function is passed in so
+// this can be tested without touching the real environment.
+fn requested_profile<F>(args: &[String], var: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    profile_from_args(args).or_else(|| var(PROFILE_ENV_VAR).filter(|p| !p.is_empty()))
+}
+
+/// Returns the Kubernetes context selected with the global `--kube-context`
+/// option, if there is one.
+pub fn kube_context(matches: &ArgMatches) -> Option<String> {
+    matches
+        .get_one::<String>(KUBE_CONTEXT_ARG)
+        .filter(|context| !context.is_empty())
+        .cloned()
+}
+
+/// Returns the arguments that point kubectl at a Kubernetes context, if one
+/// is set.
+pub fn kubectl_context_args(context: Option<&str>) -> Vec<String> {
+    match context {
+        Some(context) => vec!["--context".to_string(), context.to_string()],
+        None => Vec::new(),
+    }
+}
+
+/// Returns the arguments that point skaffold at a Kubernetes context, if one
+/// is set.
+pub fn skaffold_context_args(context: Option<&str>) -> Vec<String> {
+    match context {
+        Some(context) => vec!["--kube-context".to_string(), context.to_string()],
+        None => Vec::new(),
+    }
+}
+
+impl Settings {
+    /// Loads the settings for the profile selected in the command line
+    /// arguments or the environment. A missing config file is fine unless a
+    /// profile was asked for.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let args: Vec<String> = std::env::args().collect();
+    /// let settings = Settings::load(&args)?;
+    /// ```
+    pub fn load(args: &[String]) -> Result<Self> {
+        let requested = requested_profile(args, |name| env::var(name).ok());
+
+        let contents = match config_path() {
+            Some(path) if path.exists() => Some(
+                fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?,
+            ),
+            _ => None,
+        };
+
+        let settings = Self::from_config(contents.as_deref(), requested.as_deref())?;
+        Ok(Self {
+            profile: settings.profile.with_env_vars(|name| env::var(name).ok()),
+            ..settings
+        })
+    }
+
+    // Picks the profile out of the contents of a config file.
+    fn from_config(contents: Option<&str>, requested: Option<&str>) -> Result<Self> {
+        let file: ConfigFile = match contents {
+            Some(contents) => {
+                toml::from_str(contents).context("failed to parse the config file")?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let profile_name = requested
+            .map(|name| name.to_string())
+            .or(file.default_profile);
+
+        let profile = match &profile_name {
+            Some(name) => file.profiles.get(name).cloned().ok_or_else(|| {
+                anyhow!(
+                    "no profile named {} in {}",
+                    name,
+                    config_path()
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_else(|| "the config file".to_string())
+                )
+            })?,
+            None => Profile::default(),
+        };
+
+        Ok(Self {
+            profile_name,
+            profile,
+        })
+    }
+
+    /// Uses the settings as the defaults for the matching options throughout
+    /// the command tree. Options given on the command line still win.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let commands = settings.apply(Command::new("mgmt").subcommand(release::cli()));
+    /// ```
+    pub fn apply(&self, cmd: Command) -> Command {
+        let path = cmd.get_name().to_string();
+        self.apply_to(cmd, &path)
+    }
+
+    fn apply_to(&self, cmd: Command, path: &str) -> Command {
+        let cmd = cmd.mut_args(|arg| self.default_for(path, arg));
+
+        let names: Vec<String> = cmd
+            .get_subcommands()
+            .map(|sub| sub.get_name().to_string())
+            .collect();
+
+        names.into_iter().fold(cmd, |cmd, name| {
+            let sub_path = format!("{} {}", path, name);
+            cmd.mut_subcommand(&name, |sub| self.apply_to(sub, &sub_path))
+        })
+    }
+
+    fn default_for(&self, path: &str, arg: Arg) -> Arg {
+        let p = &self.profile;
+        let id = arg.get_id().as_str().to_string();
+
+        let value = match (path, id.as_str()) {
+            ("mgmt", "database-url") => p.database_url.clone(),
+            ("mgmt", KUBE_CONTEXT_ARG) => p.kube_context.clone(),
+            ("mgmt release create" | "mgmt release deploy", "repo-url") => p.releases_repo.clone(),
+            ("mgmt release create" | "mgmt release deploy", "branch") => p.releases_branch.clone(),
+            ("mgmt site init" | "mgmt site deploy", "dir") => p.site_dir.clone(),
+            (_, "env" | "environment")
+                if arg.is_required_set() && DEFAULT_ENV_COMMANDS.contains(&path) =>
+            {
+                return match &p.default_env {
+                    Some(env) => arg.required(false).default_value(env.clone()),
+                    None => arg,
+                };
+            }
+            _ => None,
+        };
+
+        match value {
+            Some(value) => arg.default_value(value),
+            None => arg,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::arg;
+
+    const CONFIG: &str = r#"
+default_profile = "local"
+
+[profiles.local]
+database_url = "postgresql://root@127.0.0.1:5432/de_releases"
+
+[profiles.prod]
+database_url = "postgresql://mgmt@db:5432/de_releases"
+releases_branch = "prod"
+default_env = "prod"
+kube_context = "prod-cluster"
+"#;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_profile_from_args() {
+        assert_eq!(
+            profile_from_args(&args(&["mgmt", "--profile", "prod", "env", "list"])),
+            Some("prod".to_string())
+        );
+        assert_eq!(
+            profile_from_args(&args(&["mgmt", "--profile=prod"])),
+            Some("prod".to_string())
+        );
+        assert_eq!(
+            profile_from_args(&args(&["mgmt", "-Pprod"])),
+            Some("prod".to_string())
+        );
+        assert_eq!(profile_from_args(&args(&["mgmt", "env", "list"])), None);
+    }
+
+    #[test]
+    fn test_requested_profile() {
+        let var = |value: &'static str| move |_: &str| Some(value.to_string());
+        assert_eq!(
+            requested_profile(&args(&["mgmt", "-P", "prod"]), var("local")),
+            Some("prod".to_string())
+        );
+        assert_eq!(
+            requested_profile(&args(&["mgmt"]), var("local")),
+            Some("local".to_string())
+        );
+        assert_eq!(requested_profile(&args(&["mgmt"]), var("")), None);
+        assert_eq!(requested_profile(&args(&["mgmt"]), |_| None), None);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let settings = Settings::from_config(Some(CONFIG), None).unwrap();
+        assert_eq!(settings.profile_name.as_deref(), Some("local"));
+        assert_eq!(settings.profile.default_env, None);
+
+        let settings = Settings::from_config(Some(CONFIG), Some("prod")).unwrap();
+        assert_eq!(settings.profile.releases_branch.as_deref(), Some("prod"));
+
+        assert!(Settings::from_config(Some(CONFIG), Some("missing")).is_err());
+        assert!(Settings::from_config(Some("bogus = 1"), None).is_err());
+        assert_eq!(
+            Settings::from_config(None, None).unwrap(),
+            Settings::default()
+        );
+    }
+
+    #[test]
+    fn test_env_vars_override_profile() {
+        let settings = Settings::from_config(Some(CONFIG), Some("prod")).unwrap();
+        let profile = settings.profile.with_env_vars(|name| match name {
+            "MGMT_ENV" => Some("qa".to_string()),
+            "MGMT_SITE_DIR" => Some(String::new()),
+            _ => None,
+        });
+
+        assert_eq!(profile.default_env.as_deref(), Some("qa"));
+        assert_eq!(profile.releases_branch.as_deref(), Some("prod"));
+        assert_eq!(profile.site_dir, None);
+    }
+
+    #[test]
+    fn test_apply() {
+        let settings = Settings::from_config(Some(CONFIG), Some("prod")).unwrap();
+        let cmd = || {
+            settings.apply(
+                Command::new("mgmt")
+                    .arg(arg!(-d --"database-url" <DATABASE>).default_value("built-in"))
+                    .arg(
+                        arg!(--"kube-context" <CONTEXT>)
+                            .global(true)
+                            .required(false),
+                    )
+                    .subcommand(
+                        Command::new("env")
+                            .subcommand(
+                                Command::new("sync").arg(arg!(-e --env <ENV>).required(true)),
+                            )
+                            .subcommand(
+                                Command::new("delete").arg(arg!(-e --env <ENV>).required(true)),
+                            )
+                            .subcommand(Command::new("service").subcommand(
+                                Command::new("list").arg(arg!(-e --env <ENV>).required(true)),
+                            )),
+                    ),
+            )
+        };
+
+        let matches = cmd().get_matches_from(["mgmt", "env", "service", "list"]);
+        assert_eq!(
+            matches.get_one::<String>("database-url").unwrap(),
+            "postgresql://mgmt@db:5432/de_releases"
+        );
+        let list = matches.subcommand_matches("env").unwrap();
+        let list = list.subcommand_matches("service").unwrap();
+        let list = list.subcommand_matches("list").unwrap();
+        assert_eq!(list.get_one::<String>("env").unwrap(), "prod");
+        assert_eq!(kube_context(list).as_deref(), Some("prod-cluster"));
+
+        let matches = cmd().get_matches_from(["mgmt", "-d", "cli", "env", "sync", "-e", "qa"]);
+        assert_eq!(matches.get_one::<String>("database-url").unwrap(), "cli");
+        let matches =
+            cmd().get_matches_from(["mgmt", "env", "sync", "-e", "qa", "--kube-context", "qa"]);
+        let sync = matches.subcommand_matches("env").unwrap();
+        let sync = sync.subcommand_matches("sync").unwrap();
+        assert_eq!(kube_context(sync).as_deref(), Some("qa"));
+
+        // Commands that change things need the environment to be given.
+        assert!(cmd().try_get_matches_from(["mgmt", "env", "sync"]).is_err());
+        assert!(cmd()
+            .try_get_matches_from(["mgmt", "env", "delete"])
+            .is_err());
+    }
+}
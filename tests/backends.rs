@@ -0,0 +1,212 @@
+//! Runs the same database operations against each backend `mgmt` supports.
+//!
+//! The tests need databases with the `mgmt` schema and an environment to work
+//! with, so they're ignored by default. Run them with
+//! `cargo test --test backends -- --ignored` after setting:
+//!
+//! * `MGMT_TEST_POSTGRES_URL`, e.g. `postgres://postgres@localhost/de_releases`
+//! * `MGMT_TEST_MYSQL_URL`, e.g. `mysql://root@127.0.0.1:3306/de_releases` for
+//!   a `dolt sql-server`
+//!
+//! Both have to be set, otherwise the tests fail. `MGMT_TEST_ENV` names the
+//! environment to use and defaults to `de`. Feature flags are changed during
+//! the tests, but are put back afterwards.
+use anyhow::{Context, Result};
+use mgmt::db::backend::{self, Connection, Database};
+use mgmt::db::dialect::Dialect;
+use std::collections::BTreeMap;
+use std::env;
+
+// Connects to each backend.
+async fn backends() -> Result<Vec<Database>> {
+    let mut dbs = Vec::new();
+
+    for var in ["MGMT_TEST_POSTGRES_URL", "MGMT_TEST_MYSQL_URL"] {
+        let url = env::var(var)
+            .ok()
+            .filter(|url| !url.is_empty())
+            .with_context(|| format!("{} isn't set", var))?;
+        dbs.push(Database::connect(&url).await?);
+    }
+
+    Ok(dbs)
+}
+
+fn test_env() -> String {
+    env::var("MGMT_TEST_ENV").unwrap_or_else(|_| "de".to_string())
+}
+
+// Returns whether a flag is turned on for the test environment.
+async fn flag_enabled(db: &Database, flag: &str) -> Result<bool> {
+    Ok(db
+        .get_feature_flags(&test_env())
+        .await?
+        .into_iter()
+        .find(|f| f.name == flag)
+        .with_context(|| format!("{:?}: no {} flag", db.dialect(), flag))?
+        .enabled)
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_get_repos() -> Result<()> {
+    for db in backends().await? {
+        let repos = db.get_repos().await?;
+        assert!(
+            repos
+                .iter()
+                .all(|(url, name)| !url.is_empty() && !name.is_empty()),
+            "{:?}: empty repository url or name",
+            db.dialect()
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_list_service_names() -> Result<()> {
+    for db in backends().await? {
+        let names = db.list_service_names(&test_env()).await?;
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted, "{:?}: services aren't sorted", db.dialect());
+
+        assert!(db
+            .list_service_names("no-such-environment")
+            .await?
+            .is_empty());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_list_default_config_values() -> Result<()> {
+    for db in backends().await? {
+        let defaults = db.list_default_config_values().await?;
+        assert!(!defaults.is_empty(), "{:?}: no defaults", db.dialect());
+        assert!(defaults
+            .windows(2)
+            .all(|w| (&w[0].section, &w[0].key) <= (&w[1].section, &w[1].key)));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_set_feature_flag() -> Result<()> {
+    let environment = test_env();
+
+    for db in backends().await? {
+        let original = flag_enabled(&db, "qms").await?;
+
+        assert!(db.set_feature_flag(&environment, "qms", !original).await?);
+        assert_eq!(flag_enabled(&db, "qms").await?, !original);
+
+        db.set_feature_flag(&environment, "qms", original).await?;
+        assert_eq!(flag_enabled(&db, "qms").await?, original);
+
+        assert!(
+            !db.set_feature_flag(&environment, "qms = true; --", true)
+                .await?
+        );
+        assert!(
+            !db.set_feature_flag("no-such-environment", "qms", true)
+                .await?
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_set_feature_flags() -> Result<()> {
+    let environment = test_env();
+
+    for db in backends().await? {
+        let original = flag_enabled(&db, "qms").await?;
+
+        let flags = BTreeMap::from([("qms".to_string(), !original)]);
+        db.set_feature_flags(&environment, &flags).await?;
+        assert_eq!(flag_enabled(&db, "qms").await?, !original);
+
+        let flags = BTreeMap::from([
+            ("qms".to_string(), original),
+            ("no-such-flag".to_string(), true),
+        ]);
+        assert!(db.set_feature_flags(&environment, &flags).await.is_err());
+        assert_eq!(flag_enabled(&db, "qms").await?, !original);
+
+        let flags = BTreeMap::from([("qms".to_string(), original)]);
+        db.set_feature_flags(&environment, &flags).await?;
+        assert!(db
+            .set_feature_flags("no-such-environment", &flags)
+            .await
+            .is_err());
+        assert!(db.get_feature_flags("no-such-environment").await.is_err());
+    }
+
+    Ok(())
+}
+
+// Sets up an environment the way the environment setup prompts do, then
+// rolls it back.
+async fn set_up_environment(conn: &mut Connection<'_>) -> Result<()> {
+    let env_id = backend::upsert_environment(conn, "mgmt-backend-test", "test").await?;
+    assert_eq!(
+        backend::upsert_environment(conn, "mgmt-backend-test", "test").await?,
+        env_id
+    );
+
+    let cfg_id = backend::set_config_value(conn, "TopLevel", "Timezone", "UTC", "string").await?;
+    backend::add_env_cfg_value(conn, env_id, cfg_id).await?;
+    assert!(
+        backend::set_config_value(conn, "NoSuchSection", "Key", "value", "string")
+            .await
+            .is_err()
+    );
+
+    let sections = BTreeMap::from([("QMS".to_string(), true)]);
+    assert!(backend::set_section_flags(conn, "mgmt-backend-test", &sections).await? > 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_environment_setup() -> Result<()> {
+    for db in backends().await? {
+        match &db {
+            Database::Postgres(pool) => {
+                let mut tx = pool.begin().await?;
+                set_up_environment(&mut Connection::Postgres(&mut tx)).await?;
+                tx.rollback().await?;
+            }
+            Database::MySql(pool) => {
+                let mut tx = pool.begin().await?;
+                set_up_environment(&mut Connection::MySql(&mut tx)).await?;
+                tx.rollback().await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_postgres_only_operations() -> Result<()> {
+    for db in backends().await? {
+        match db.dialect() {
+            Dialect::Postgres => assert!(db.postgres("test").is_ok()),
+            Dialect::MySql => assert!(db.postgres("test").is_err()),
+        }
+    }
+
+    Ok(())
+}
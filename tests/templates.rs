@@ -0,0 +1,349 @@
+//! Runs the template management commands against a database.
+//!
+//! The tests need a Postgres database with the `mgmt` schema and at least two
+//! services, so they're ignored by default. Run them with
+//! `cargo test --test templates -- --ignored` after setting
+//! `MGMT_TEST_POSTGRES_URL`, e.g. `postgres://postgres@localhost/de_releases`.
+//! Everything a test changes is rolled back afterwards.
+use anyhow::{Context, Result};
+use mgmt::db;
+use mgmt::handlers::templates;
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Transaction};
+use std::env;
+use std::path::PathBuf;
+
+const ENV: &str = "mgmt-templates-test";
+const OTHER_ENV: &str = "mgmt-templates-test-other";
+const TEMPLATE: &str = "templates/mgmt-test.yml";
+
+async fn connect() -> Result<Pool<Postgres>> {
+    let url = env::var("MGMT_TEST_POSTGRES_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+        .context("MGMT_TEST_POSTGRES_URL isn't set")?;
+    Ok(PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&url)
+        .await?)
+}
+
+// The repos, environments and services a test works with.
+struct Fixture {
+    repo_id: i32,
+    other_repo_id: i32,
+    service: String,
+    other_service: String,
+}
+
+// Adds two repos and two environments. Both services are in the first
+// environment, and only the first service is in the other one.
+async fn set_up(tx: &mut Transaction<'_, Postgres>) -> Result<Fixture> {
+    let url = url::Url::parse("https://example.com/mgmt-test.git")?;
+    let repo_id = db::add_repo(tx, "mgmt-test", &url, "main").await?;
+    let other_repo_id = db::add_repo(tx, "mgmt-test-other", &url, "main").await?;
+
+    let mut services = db::get_all_services(tx).await?.into_iter().map(|s| s.name);
+    let service = services.next().context("no services")?;
+    let other_service = services.next().context("only one service")?;
+
+    db::upsert_environment(tx, ENV, ENV).await?;
+    db::upsert_environment(tx, OTHER_ENV, OTHER_ENV).await?;
+    db::add_service_to_env(tx, ENV, &service).await?;
+    db::add_service_to_env(tx, ENV, &other_service).await?;
+    db::add_service_to_env(tx, OTHER_ENV, &service).await?;
+
+    Ok(Fixture {
+        repo_id,
+        other_repo_id,
+        service,
+        other_service,
+    })
+}
+
+async fn assoc(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    repo_id: i32,
+    service: &str,
+    render_path: Option<&str>,
+) -> Result<()> {
+    templates::assoc_template(
+        tx,
+        env,
+        repo_id,
+        service,
+        &[PathBuf::from(TEMPLATE)],
+        render_path,
+    )
+    .await
+}
+
+// Returns the services a template is associated with in an environment.
+async fn users(
+    tx: &mut Transaction<'_, Postgres>,
+    env: &str,
+    template_id: i32,
+) -> Result<Vec<String>> {
+    Ok(db::list_env_service_templates(tx, env)
+        .await?
+        .into_iter()
+        .filter(|t| t.template_id == template_id)
+        .map(|t| t.service_name)
+        .collect())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_dissoc() -> Result<()> {
+    let pool = connect().await?;
+    let mut tx = pool.begin().await?;
+    let f = set_up(&mut tx).await?;
+
+    assoc(&mut tx, ENV, f.repo_id, &f.service, None).await?;
+    assoc(&mut tx, OTHER_ENV, f.other_repo_id, &f.service, None).await?;
+    let id = db::get_template_id(&mut tx, f.repo_id, TEMPLATE)
+        .await?
+        .unwrap();
+    let other_id = db::get_template_id(&mut tx, f.other_repo_id, TEMPLATE)
+        .await?
+        .unwrap();
+
+    // The other repo's template isn't associated with the service in ENV.
+    let templates = vec![TEMPLATE.to_string()];
+    assert!(
+        templates::dissoc_template(&mut tx, ENV, f.other_repo_id, &f.service, &templates)
+            .await
+            .is_err()
+    );
+
+    templates::dissoc_template(&mut tx, ENV, f.repo_id, &f.service, &templates).await?;
+    assert!(users(&mut tx, ENV, id).await?.is_empty());
+    assert_eq!(
+        users(&mut tx, OTHER_ENV, other_id).await?,
+        vec![f.service.clone()]
+    );
+
+    assert!(
+        templates::dissoc_template(&mut tx, ENV, f.repo_id, &f.service, &templates)
+            .await
+            .is_err()
+    );
+
+    tx.rollback().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_rm() -> Result<()> {
+    let pool = connect().await?;
+    let mut tx = pool.begin().await?;
+    let f = set_up(&mut tx).await?;
+
+    assoc(&mut tx, ENV, f.repo_id, &f.service, None).await?;
+    assoc(&mut tx, OTHER_ENV, f.other_repo_id, &f.service, None).await?;
+
+    // Templates that are still used are only removed with force.
+    let templates = vec![TEMPLATE.to_string()];
+    assert!(
+        templates::remove_templates(&mut tx, f.repo_id, &templates, false)
+            .await
+            .is_err()
+    );
+    templates::remove_templates(&mut tx, f.repo_id, &templates, true).await?;
+
+    assert!(db::get_template_id(&mut tx, f.repo_id, TEMPLATE)
+        .await?
+        .is_none());
+    assert!(db::get_template_id(&mut tx, f.other_repo_id, TEMPLATE)
+        .await?
+        .is_some());
+    assert!(
+        templates::remove_templates(&mut tx, f.repo_id, &templates, true)
+            .await
+            .is_err()
+    );
+
+    tx.rollback().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_mv() -> Result<()> {
+    let pool = connect().await?;
+    let mut tx = pool.begin().await?;
+    let f = set_up(&mut tx).await?;
+    let moved = "templates/moved/mgmt-test.yml";
+
+    assoc(
+        &mut tx,
+        ENV,
+        f.repo_id,
+        &f.service,
+        Some("/etc/mgmt/test.yml"),
+    )
+    .await?;
+    assoc(&mut tx, OTHER_ENV, f.other_repo_id, &f.service, None).await?;
+    let id = db::get_template_id(&mut tx, f.repo_id, TEMPLATE)
+        .await?
+        .unwrap();
+
+    templates::move_template(&mut tx, f.repo_id, TEMPLATE, moved, None).await?;
+    assert_eq!(
+        db::get_template_id(&mut tx, f.repo_id, moved).await?,
+        Some(id)
+    );
+    assert!(db::get_template_id(&mut tx, f.other_repo_id, TEMPLATE)
+        .await?
+        .is_some());
+
+    // The association and its render path come along.
+    let t = db::list_env_service_templates(&mut tx, ENV)
+        .await?
+        .into_iter()
+        .find(|t| t.template_id == id)
+        .unwrap();
+    assert_eq!(t.template_path, moved);
+    assert_eq!(t.render_path, "/etc/mgmt/test.yml");
+
+    assert!(
+        templates::move_template(&mut tx, f.repo_id, TEMPLATE, moved, None)
+            .await
+            .is_err()
+    );
+    db::add_template(&mut tx, f.repo_id, &PathBuf::from(TEMPLATE)).await?;
+    assert!(
+        templates::move_template(&mut tx, f.repo_id, TEMPLATE, moved, None)
+            .await
+            .is_err()
+    );
+
+    tx.rollback().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_copy() -> Result<()> {
+    let pool = connect().await?;
+    let mut tx = pool.begin().await?;
+    let f = set_up(&mut tx).await?;
+
+    assoc(
+        &mut tx,
+        ENV,
+        f.repo_id,
+        &f.service,
+        Some("/etc/mgmt/test.yml"),
+    )
+    .await?;
+    assoc(&mut tx, ENV, f.repo_id, &f.other_service, None).await?;
+    let id = db::get_template_id(&mut tx, f.repo_id, TEMPLATE)
+        .await?
+        .unwrap();
+
+    // The other service isn't in OTHER_ENV, so it's skipped.
+    templates::copy_templates(&mut tx, ENV, OTHER_ENV, None).await?;
+    let copied: Vec<db::ServiceTemplate> = db::list_env_service_templates(&mut tx, OTHER_ENV)
+        .await?
+        .into_iter()
+        .filter(|t| t.template_id == id)
+        .collect();
+    assert_eq!(copied.len(), 1);
+    assert_eq!(copied[0].service_name, f.service);
+    assert_eq!(copied[0].render_path, "/etc/mgmt/test.yml");
+
+    // Copying again doesn't add the association twice.
+    templates::copy_templates(&mut tx, ENV, OTHER_ENV, Some(&f.service)).await?;
+    assert_eq!(
+        users(&mut tx, OTHER_ENV, id).await?,
+        vec![f.service.clone()]
+    );
+
+    assert!(
+        templates::copy_templates(&mut tx, ENV, OTHER_ENV, Some(&f.other_service))
+            .await
+            .is_err()
+    );
+    assert!(
+        templates::copy_templates(&mut tx, ENV, "no-such-environment", None)
+            .await
+            .is_err()
+    );
+
+    tx.rollback().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_undeployable() -> Result<()> {
+    let pool = connect().await?;
+    let mut tx = pool.begin().await?;
+    let f = set_up(&mut tx).await?;
+
+    // The other repo's template has the same file name, so it can't be
+    // loaded into the service-configs secret along with the first one.
+    assoc(&mut tx, ENV, f.repo_id, &f.service, None).await?;
+    assoc(&mut tx, OTHER_ENV, f.other_repo_id, &f.service, None).await?;
+    assert!(templates::copy_templates(&mut tx, ENV, OTHER_ENV, None)
+        .await
+        .is_err());
+    let id = db::get_template_id(&mut tx, f.repo_id, TEMPLATE)
+        .await?
+        .unwrap();
+    assert!(users(&mut tx, OTHER_ENV, id).await?.is_empty());
+
+    let other = "templates/other/mgmt-other.yml";
+    templates::assoc_template(
+        &mut tx,
+        ENV,
+        f.repo_id,
+        &f.other_service,
+        &[PathBuf::from(other)],
+        None,
+    )
+    .await?;
+    assert!(templates::move_template(
+        &mut tx,
+        f.repo_id,
+        TEMPLATE,
+        "templates/moved/mgmt-other.yml",
+        None
+    )
+    .await
+    .is_err());
+    assert!(db::get_template_id(&mut tx, f.repo_id, TEMPLATE)
+        .await?
+        .is_some());
+
+    tx.rollback().await?;
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_orphans() -> Result<()> {
+    let pool = connect().await?;
+    let mut tx = pool.begin().await?;
+    let f = set_up(&mut tx).await?;
+
+    assoc(&mut tx, ENV, f.repo_id, &f.service, None).await?;
+    let id = db::get_template_id(&mut tx, f.repo_id, TEMPLATE)
+        .await?
+        .unwrap();
+    let is_orphan = |orphans: &[db::OrphanedTemplate]| orphans.iter().any(|o| o.template_id == id);
+    assert!(!is_orphan(&db::list_orphaned_templates(&mut tx).await?));
+
+    let templates = vec![TEMPLATE.to_string()];
+    templates::dissoc_template(&mut tx, ENV, f.repo_id, &f.service, &templates).await?;
+    let orphans = db::list_orphaned_templates(&mut tx).await?;
+    assert!(is_orphan(&orphans));
+    assert!(orphans
+        .iter()
+        .any(|o| o.template_id == id && o.repo_name == "mgmt-test" && o.template_path == TEMPLATE));
+
+    tx.rollback().await?;
+    Ok(())
+}